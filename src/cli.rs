@@ -0,0 +1,246 @@
+//! Command line arguments, which override the config file.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::client::url::Url;
+use crate::client::{self, state};
+use crate::config::{Config, Sasl, Settings, DEFAULT_PORT, DEFAULT_TLS_PORT};
+
+/// Yet another IRC client.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    after_help = "Examples:\n  yairc libera\n  yairc irc.libera.chat -n jon -j '#rust'\n  yairc ircs://irc.libera.chat:6697/#rust,#yairc"
+)]
+pub struct Args {
+    /// A network from the config file, the host of a server, or an irc:// or
+    /// ircs:// URL
+    #[arg(value_parser = parse_target)]
+    pub network: Option<Target>,
+
+    /// Config file to use instead of $XDG_CONFIG_HOME/yairc/config.toml
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Check the config file for mistakes and exit
+    #[arg(long)]
+    pub check_config: bool,
+
+    /// Port to connect to, 6697 with TLS and 6667 without by default
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub port: Option<u16>,
+
+    /// Connect with TLS, the default
+    #[arg(long, overrides_with = "no_tls")]
+    pub tls: bool,
+
+    /// Connect without TLS
+    #[arg(long, overrides_with = "tls")]
+    pub no_tls: bool,
+
+    /// Nick to use
+    #[arg(short, long, value_parser = parse_nick)]
+    pub nick: Option<String>,
+
+    /// Username to register with
+    #[arg(short, long, value_parser = parse_username)]
+    pub user: Option<String>,
+
+    /// Real name to register with
+    #[arg(short, long)]
+    pub realname: Option<String>,
+
+    /// Server password. Other users can see it in the process list, the
+    /// config file is safer
+    #[arg(long)]
+    pub password: Option<String>,
+
+    /// Account to log in to with SASL PLAIN
+    #[arg(long, value_name = "ACCOUNT", requires = "sasl_password")]
+    pub sasl_user: Option<String>,
+
+    /// Password for --sasl-user. Other users can see it in the process
+    /// list, the config file is safer
+    #[arg(long, value_name = "PASSWORD", requires = "sasl_user")]
+    pub sasl_password: Option<String>,
+
+    /// Channel to join instead of the configured ones, with its key after a
+    /// space if it needs one. Can be repeated
+    #[arg(short, long = "join", value_name = "CHANNEL [KEY]", value_parser = parse_join)]
+    pub join: Vec<(String, Option<String>)>,
+}
+
+/// What to connect to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A network from the config, or a host.
+    Name(String),
+    Url(Url),
+}
+
+fn parse_target(target: &str) -> Result<Target, String> {
+    if target.contains("://") {
+        return target.parse().map(Target::Url);
+    }
+    if target.is_empty() || target.contains(|c: char| c.is_whitespace() || c == '/') {
+        return Err("expected a network name, a host or an irc:// URL".to_string());
+    }
+    Ok(Target::Name(target.to_string()))
+}
+
+fn parse_nick(nick: &str) -> Result<String, String> {
+    if state::is_nick(nick) {
+        Ok(nick.to_string())
+    } else {
+        Err(
+            "nicks start with a letter or one of [\\]^_`{|} followed by those, digits or -"
+                .to_string(),
+        )
+    }
+}
+
+fn parse_username(username: &str) -> Result<String, String> {
+    if !state::is_username(username) {
+        Err("expected a single word without @".to_string())
+    } else {
+        Ok(username.to_string())
+    }
+}
+
+fn parse_join(join: &str) -> Result<(String, Option<String>), String> {
+    let mut parts = join.trim().splitn(2, ' ');
+    let channel = client::channel_name(parts.next().unwrap_or_default());
+    if !state::is_channel(&channel) {
+        return Err(format!("{} is not a channel name", channel));
+    }
+    let key = parts.next().map(|k| k.trim().to_string());
+    if key.as_deref().is_some_and(|k| k.contains(' ')) {
+        return Err("keys can't contain spaces".to_string());
+    }
+    Ok((channel, key))
+}
+
+impl Args {
+    /// What to connect to, from the config and anything given on the
+    /// command line.
+    pub fn settings(&self, config: &Config) -> Result<Settings, String> {
+        let mut settings = match &self.network {
+            Some(Target::Name(name)) => config.settings(Some(name))?,
+            Some(Target::Url(url)) => url.settings(config)?,
+            None => config.settings(None)?,
+        };
+        self.apply(&mut settings);
+        Ok(settings)
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        let tls = match (self.tls, self.no_tls) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        // Changing TLS changes the usual port, unless one was given with it
+        if let Some(tls) = tls {
+            settings.tls = tls;
+            settings.port = if tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT };
+        }
+        if let Some(port) = self.port {
+            settings.port = port;
+        }
+
+        self.apply_identity(settings);
+        if let Some(password) = &self.password {
+            settings.password = Some(password.clone());
+        }
+        if let (Some(username), Some(password)) = (&self.sasl_user, &self.sasl_password) {
+            settings.sasl = Some(Sasl {
+                mechanism: "PLAIN".to_string(),
+                username: username.clone(),
+                password: password.clone(),
+            });
+        }
+        if !self.join.is_empty() {
+            settings.autojoin = self.join.clone();
+        }
+    }
+
+    /// Overrides who we are, which also holds for servers from /connect.
+    pub fn apply_identity(&self, settings: &mut Settings) {
+        if let Some(nick) = &self.nick {
+            settings.nick = nick.clone();
+        }
+        if let Some(user) = &self.user {
+            settings.username = user.clone();
+        }
+        if let Some(realname) = &self.realname {
+            settings.realname = realname.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let args = Args::parse_from([
+            "yairc",
+            "libera",
+            "-n",
+            "jon",
+            "-j",
+            "#rust",
+            "--join",
+            "ops secret",
+            "--no-tls",
+            "--sasl-user",
+            "jon",
+            "--sasl-password",
+            "hunter2",
+        ]);
+        let mut settings = Settings::new("irc.libera.chat");
+        settings.autojoin = vec![("#configured".to_string(), None)];
+        args.apply(&mut settings);
+
+        assert_eq!(args.network, Some(Target::Name("libera".to_string())));
+        assert_eq!(settings.nick, "jon");
+        assert_eq!((settings.tls, settings.port), (false, 6667));
+        assert_eq!(settings.sasl.unwrap().password, "hunter2");
+        assert_eq!(
+            settings.autojoin,
+            vec![
+                ("#rust".to_string(), None),
+                ("#ops".to_string(), Some("secret".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        let args = Args::parse_from(["yairc", "irc://irc.example.com/%23rust?key", "-p", "6665"]);
+        let settings = args.settings(&Config::default()).unwrap();
+        assert_eq!(settings.host, "irc.example.com");
+        assert_eq!((settings.tls, settings.port), (false, 6665));
+        assert_eq!(
+            settings.autojoin,
+            vec![("#rust".to_string(), Some("key".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for args in [
+            &["yairc", "-n", "2jon"][..],
+            &["yairc", "-j", "#a,b"],
+            &["yairc", "-p", "0"],
+            &["yairc", "--sasl-user", "jon"],
+            &["yairc", "http://example.com"],
+        ] {
+            assert!(Args::try_parse_from(args).is_err(), "{:?}", args);
+        }
+    }
+}
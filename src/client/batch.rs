@@ -0,0 +1,329 @@
+//! IRCv3 batches, which group messages that belong together such as a
+//! netsplit's QUITs or a message sent in several lines.
+//!
+//! <https://ircv3.net/specs/extensions/batch>
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::client::state::State;
+use crate::message::{Command, Message};
+
+pub const NETSPLIT: &str = "netsplit";
+pub const NETJOIN: &str = "netjoin";
+pub const MULTILINE: &str = "draft/multiline";
+pub const LABELED: &str = "labeled-response";
+
+/// A batch that has ended, with everything in it.
+#[derive(Debug, PartialEq)]
+pub struct Batch {
+    /// The `BATCH +<reference> <type> [params]` line that started it. It
+    /// carries the batch's own tags, like the msgid of a multiline message.
+    pub start: Message,
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Item {
+    Message(Message),
+    Batch(Batch),
+}
+
+impl Batch {
+    pub fn kind(&self) -> String {
+        self.start
+            .get_param(1)
+            .map(|p| p.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn params(&self) -> Vec<String> {
+        self.start.params().into_iter().skip(2).collect()
+    }
+
+    /// The messages in the batch, in order. Nested batches are flattened and
+    /// multiline ones joined into a single message. Replies in a labeled
+    /// response get its label.
+    pub fn into_messages(self) -> Vec<Message> {
+        if self.kind() == MULTILINE {
+            return self.join_lines().into_iter().collect();
+        }
+        let label = match self.kind().as_str() {
+            LABELED => self.start.tag("label").map(|l| l.to_string()),
+            _ => None,
+        };
+        self.items
+            .into_iter()
+            .flat_map(|item| match item {
+                Item::Message(message) => vec![message],
+                Item::Batch(batch) => batch.into_messages(),
+            })
+            .map(|message| match &label {
+                Some(label) if message.tag("label").is_none() => {
+                    message.with_tag("label", Some(label))
+                }
+                _ => message,
+            })
+            .collect()
+    }
+
+    /// Joins a multiline batch into one PRIVMSG or NOTICE. Lines are
+    /// separated by newlines unless they're tagged to be concatenated.
+    fn join_lines(self) -> Option<Message> {
+        let mut text = String::new();
+        let mut first = None;
+        for item in self.items {
+            let line = match item {
+                Item::Message(line) => line,
+                Item::Batch(_) => continue,
+            };
+            if first.is_some() && line.tag("draft/multiline-concat").is_none() {
+                text.push('\n');
+            }
+            text.push_str(&line.get_param(1).map(|p| p.to_string()).unwrap_or_default());
+            first.get_or_insert(line);
+        }
+
+        let target = self.start.get_param(2)?.to_string();
+        let mut message = first?.with_params(vec![target, text]);
+        // Client tags like +draft/reply go on the batch too
+        let tags =
+            self.start.tags().into_iter().flatten().filter(|t| {
+                matches!(t.key(), "msgid" | "time" | "label") || t.key().starts_with('+')
+            });
+        for tag in tags {
+            message = message.with_tag(tag.key(), Some(tag.value()));
+        }
+        Some(message)
+    }
+
+    /// Who left or came back in a netsplit or netjoin. `state` mustn't have
+    /// seen the batch's messages yet, so it knows the channels people
+    /// quit from.
+    pub fn netsplit(&self, state: &State) -> Option<Netsplit> {
+        let joined = match self.kind().as_str() {
+            NETSPLIT => false,
+            NETJOIN => true,
+            _ => return None,
+        };
+        let mut nicks = Vec::new();
+        let mut channels = BTreeSet::new();
+        for item in &self.items {
+            let (message, source) = match item {
+                Item::Message(m) => match m.source() {
+                    Some(source) => (m, source),
+                    None => continue,
+                },
+                Item::Batch(_) => continue,
+            };
+            if !nicks.contains(&source.nick) {
+                nicks.push(source.nick.clone());
+            }
+            if joined {
+                channels.extend(message.get_param(0).map(|p| p.to_string()));
+            } else {
+                let quit_from = state
+                    .channels
+                    .iter()
+                    .filter(|(_, c)| c.members.contains(&source.nick))
+                    .map(|(name, _)| name.clone());
+                channels.extend(quit_from);
+            }
+        }
+        Some(Netsplit {
+            joined,
+            servers: self.params(),
+            nicks,
+            channels: channels.into_iter().collect(),
+        })
+    }
+}
+
+/// A netsplit or netjoin, shown as one line rather than a QUIT or JOIN
+/// for everyone involved.
+#[derive(Debug, PartialEq)]
+pub struct Netsplit {
+    pub joined: bool,
+    /// The two servers that split or joined.
+    pub servers: Vec<String>,
+    pub nicks: Vec<String>,
+    pub channels: Vec<String>,
+}
+
+/// Holds on to batched messages until their batch ends.
+#[derive(Debug, Default)]
+pub struct Assembler {
+    /// Batches that haven't ended, by reference, with the batch each is
+    /// nested in.
+    open: HashMap<String, (Option<String>, Batch)>,
+}
+
+impl Assembler {
+    /// Returns messages that aren't in a batch straight away, and batches
+    /// once they end. Nested batches end up in the one they're in.
+    pub fn receive(&mut self, message: Message) -> Option<Item> {
+        let parent = message
+            .tag("batch")
+            .filter(|reference| self.open.contains_key(*reference))
+            .map(|reference| reference.to_string());
+
+        if message.command() == &Command::Batch {
+            let reference = message
+                .get_param(0)
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            if let Some(reference) = reference.strip_prefix('+') {
+                let batch = Batch {
+                    start: message,
+                    items: Vec::new(),
+                };
+                self.open.insert(reference.to_string(), (parent, batch));
+                return None;
+            }
+            if let Some((parent, batch)) = reference
+                .strip_prefix('-')
+                .and_then(|reference| self.open.remove(reference))
+            {
+                return self.add(parent, Item::Batch(batch));
+            }
+        }
+        self.add(parent, Item::Message(message))
+    }
+
+    fn add(&mut self, parent: Option<String>, item: Item) -> Option<Item> {
+        match parent.and_then(|parent| self.open.get_mut(&parent)) {
+            Some((_, batch)) => {
+                batch.items.push(item);
+                None
+            }
+            None => Some(item),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn receive(assembler: &mut Assembler, raw: &str) -> Option<Item> {
+        assembler.receive(parse_line(raw.trim()))
+    }
+
+    /// Plays lines from a server through a new assembler, returning the
+    /// batch they make up.
+    fn assemble(script: &str) -> Batch {
+        let mut assembler = Assembler::default();
+        let mut items: Vec<Item> = script
+            .lines()
+            .filter_map(|line| receive(&mut assembler, line))
+            .collect();
+        assert_eq!(items.len(), 1);
+        match items.remove(0) {
+            Item::Batch(batch) => batch,
+            Item::Message(m) => panic!("{:?}", m),
+        }
+    }
+
+    fn texts(messages: Vec<Message>) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| m.get_param(1).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_nesting() {
+        let mut assembler = Assembler::default();
+        assert!(receive(
+            &mut assembler,
+            ":irc.example.com BATCH +outer chathistory #ops"
+        )
+        .is_none());
+        assert!(receive(&mut assembler, "@batch=outer :a!a@h PRIVMSG #ops :one").is_none());
+        assert!(receive(
+            &mut assembler,
+            "@batch=outer;msgid=m2 :irc.example.com BATCH +inner draft/multiline #ops"
+        )
+        .is_none());
+        assert!(receive(&mut assembler, "@batch=inner :a!a@h PRIVMSG #ops :two").is_none());
+        // Unbatched messages aren't held up
+        assert!(matches!(
+            receive(&mut assembler, ":b!b@h PRIVMSG #ops :live"),
+            Some(Item::Message(_))
+        ));
+        assert!(receive(
+            &mut assembler,
+            "@batch=inner;draft/multiline-concat :a!a@h PRIVMSG #ops :-three"
+        )
+        .is_none());
+        assert!(receive(&mut assembler, ":irc.example.com BATCH -inner").is_none());
+        let batch = match receive(&mut assembler, ":irc.example.com BATCH -outer") {
+            Some(Item::Batch(batch)) => batch,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(batch.kind(), "chathistory");
+        assert_eq!(batch.params(), vec!["#ops"]);
+        assert!(matches!(&batch.items[1], Item::Batch(inner) if inner.kind() == "draft/multiline"));
+        let messages = batch.into_messages();
+        assert_eq!(messages[1].tag("msgid"), Some("m2"));
+        assert_eq!(texts(messages), vec!["one", "two-three"]);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let batch = assemble(
+            "@msgid=xyz;+draft/reply=abc :a!a@h BATCH +m draft/multiline #ops
+             @batch=m :a!a@h PRIVMSG #ops :first
+             @batch=m :a!a@h PRIVMSG #ops :second
+             @batch=m;draft/multiline-concat :a!a@h PRIVMSG #ops : half
+             :a!a@h BATCH -m",
+        );
+        let messages = batch.into_messages();
+        assert_eq!(messages[0].source().unwrap().nick, "a");
+        assert_eq!(messages[0].tag("msgid"), Some("xyz"));
+        assert_eq!(messages[0].tag("+draft/reply"), Some("abc"));
+        assert_eq!(texts(messages), vec!["first\nsecond half"]);
+    }
+
+    #[test]
+    fn test_netsplit() {
+        let mut state = State::new("me");
+        for raw in [
+            ":me!u@h JOIN #ops",
+            ":me!u@h JOIN #rust",
+            ":irc.example.com 353 me = #ops :me alice bob",
+            ":irc.example.com 353 me = #rust :me bob",
+        ] {
+            state.update(&parse_line(raw));
+        }
+        let split = assemble(
+            ":irc.example.com BATCH +s netsplit irc.a.net irc.b.net
+             @batch=s :alice!a@h QUIT :irc.a.net irc.b.net
+             @batch=s :bob!b@h QUIT :irc.a.net irc.b.net
+             :irc.example.com BATCH -s",
+        );
+        assert_eq!(
+            split.netsplit(&state),
+            Some(Netsplit {
+                joined: false,
+                servers: vec!["irc.a.net".to_string(), "irc.b.net".to_string()],
+                nicks: vec!["alice".to_string(), "bob".to_string()],
+                channels: vec!["#ops".to_string(), "#rust".to_string()],
+            })
+        );
+
+        let join = assemble(
+            ":irc.example.com BATCH +j netjoin irc.a.net irc.b.net
+             @batch=j :alice!a@h JOIN #ops
+             @batch=j :bob!b@h JOIN #ops
+             @batch=j :bob!b@h JOIN #rust
+             :irc.example.com BATCH -j",
+        );
+        let netjoin = join.netsplit(&state).unwrap();
+        assert!(netjoin.joined);
+        assert_eq!(netjoin.nicks, vec!["alice", "bob"]);
+        assert_eq!(netjoin.channels, vec!["#ops", "#rust"]);
+        assert_eq!(join.into_messages().len(), 3);
+    }
+}
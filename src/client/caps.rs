@@ -0,0 +1,246 @@
+//! IRCv3 capability negotiation.
+//!
+//! <https://ircv3.net/specs/extensions/capability-negotiation>
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::config::Sasl;
+use crate::message::{Command, Message};
+
+/// Capabilities we ask for when the server offers them. `sasl` is only
+/// asked for when there are credentials to use.
+pub const WANTED: &[&str] = &[
+    "account-notify",
+    "away-notify",
+    "batch",
+    "chghost",
+    "draft/chathistory",
+    "draft/multiline",
+    "echo-message",
+    "extended-join",
+    "invite-notify",
+    "labeled-response",
+    "message-tags",
+    "sasl",
+    "server-time",
+    "setname",
+];
+
+/// AUTHENTICATE payloads are sent in pieces of this size.
+const AUTHENTICATE_CHUNK: usize = 400;
+
+#[derive(Debug, Default)]
+pub struct Caps {
+    /// What the server offers, with any value such as `draft/multiline`'s
+    /// limits.
+    available: BTreeMap<String, Option<String>>,
+    enabled: BTreeSet<String>,
+    /// Registration waits on CAP END, which we send once we're done asking
+    /// and authenticating.
+    negotiated: bool,
+    sasl: Option<Sasl>,
+}
+
+impl Caps {
+    pub fn new(sasl: Option<Sasl>) -> Self {
+        Self {
+            sasl,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self, cap: &str) -> bool {
+        self.enabled.contains(cap)
+    }
+
+    pub fn value(&self, cap: &str) -> Option<&str> {
+        self.available.get(cap)?.as_deref()
+    }
+
+    /// Handles CAP and SASL messages from the server, returning the replies.
+    pub fn handle(&mut self, message: &Message) -> Vec<Message> {
+        match message.command() {
+            Command::Cap => {}
+            Command::Authenticate => return self.authenticate(message),
+            Command::RplSaslSuccess
+            | Command::ErrSaslFail
+            | Command::ErrSaslTooLong
+            | Command::ErrSaslAborted
+            | Command::ErrSaslAlready => return self.end(),
+            _ => return vec![],
+        }
+        let param = |i| {
+            message
+                .get_param(i)
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        };
+        // <client> <subcommand> [*] :<caps>, the * meaning there's more to come
+        let subcommand = param(1).to_uppercase();
+        let more = param(2) == "*" && message.get_param(3).is_some();
+        let caps = if more { param(3) } else { param(2) };
+        let caps = caps.split_whitespace();
+
+        match subcommand.as_str() {
+            "LS" | "NEW" => {
+                for cap in caps {
+                    let (name, value) = match cap.find('=') {
+                        Some(i) => (&cap[..i], Some(cap[i + 1..].to_string())),
+                        None => (cap, None),
+                    };
+                    self.available.insert(name.to_string(), value);
+                }
+                if more {
+                    return vec![];
+                }
+                self.request()
+            }
+            "ACK" => {
+                let mut sasl = false;
+                for cap in caps {
+                    match cap.strip_prefix('-') {
+                        Some(cap) => self.enabled.remove(cap),
+                        None => {
+                            sasl |= cap == "sasl";
+                            self.enabled.insert(cap.to_string())
+                        }
+                    };
+                }
+                match &self.sasl {
+                    Some(credentials) if sasl && !self.negotiated => {
+                        vec![Message::new(
+                            Command::Authenticate,
+                            vec![&credentials.mechanism],
+                        )]
+                    }
+                    _ => self.end(),
+                }
+            }
+            "NAK" => self.end(),
+            "DEL" => {
+                for cap in caps {
+                    self.available.remove(cap);
+                    self.enabled.remove(cap);
+                }
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn request(&mut self) -> Vec<Message> {
+        let wanted: Vec<&str> = WANTED
+            .iter()
+            .copied()
+            .filter(|c| self.available.contains_key(*c) && !self.enabled.contains(*c))
+            .filter(|c| *c != "sasl" || self.sasl.is_some())
+            .collect();
+        if wanted.is_empty() {
+            return self.end();
+        }
+        vec![Message::new(Command::Cap, vec!["REQ", &wanted.join(" ")])]
+    }
+
+    /// Answers the server's `AUTHENTICATE +` with our credentials.
+    fn authenticate(&mut self, message: &Message) -> Vec<Message> {
+        let credentials = match (&self.sasl, message.get_param(0)) {
+            (Some(credentials), Some(p)) if p.to_string() == "+" => credentials,
+            _ => return vec![],
+        };
+        let payload = format!(
+            "{}\0{}\0{}",
+            credentials.username, credentials.username, credentials.password
+        );
+        let encoded = STANDARD.encode(payload);
+
+        let mut messages: Vec<Message> = encoded
+            .as_bytes()
+            .chunks(AUTHENTICATE_CHUNK)
+            .map(|chunk| {
+                let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                Message::new(Command::Authenticate, vec![chunk])
+            })
+            .collect();
+        // A last piece of exactly the chunk size needs a + to say it's done
+        if encoded.len() % AUTHENTICATE_CHUNK == 0 {
+            messages.push(Message::new(Command::Authenticate, vec!["+"]));
+        }
+        messages
+    }
+
+    fn end(&mut self) -> Vec<Message> {
+        if self.negotiated {
+            return vec![];
+        }
+        self.negotiated = true;
+        vec![Message::new(Command::Cap, vec!["END"])]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn handle(caps: &mut Caps, raw: &str) -> Vec<String> {
+        caps.handle(&parse_line(raw))
+            .iter()
+            .map(|m| String::from_utf8(m.as_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let mut caps = Caps::default();
+        let more = ":irc.example.com CAP * LS * :userhost-in-names sasl=PLAIN";
+        assert!(handle(&mut caps, more).is_empty());
+        let last = ":irc.example.com CAP * LS :draft/multiline=max-bytes=4096";
+        assert_eq!(handle(&mut caps, last), vec!["CAP REQ draft/multiline\r\n"]);
+        assert_eq!(caps.value("sasl"), Some("PLAIN"));
+
+        let ack = ":irc.example.com CAP me ACK :draft/multiline";
+        assert_eq!(handle(&mut caps, ack), vec!["CAP END\r\n"]);
+        assert!(caps.is_enabled("draft/multiline"));
+        assert_eq!(caps.value("draft/multiline"), Some("max-bytes=4096"));
+
+        // Later changes don't end registration again
+        assert!(handle(&mut caps, ":irc.example.com CAP me DEL :draft/multiline").is_empty());
+        assert!(!caps.is_enabled("draft/multiline"));
+    }
+
+    #[test]
+    fn test_sasl_plain() {
+        let mut caps = Caps::new(Some(Sasl {
+            mechanism: "PLAIN".to_string(),
+            username: "jilles".to_string(),
+            password: "sesame".to_string(),
+        }));
+        let ls = ":irc.example.com CAP * LS :sasl=PLAIN,EXTERNAL";
+        assert_eq!(handle(&mut caps, ls), vec!["CAP REQ sasl\r\n"]);
+        let ack = ":irc.example.com CAP me ACK :sasl";
+        assert_eq!(handle(&mut caps, ack), vec!["AUTHENTICATE PLAIN\r\n"]);
+        assert_eq!(
+            handle(&mut caps, "AUTHENTICATE +"),
+            vec!["AUTHENTICATE amlsbGVzAGppbGxlcwBzZXNhbWU=\r\n"]
+        );
+        let success = ":irc.example.com 903 me :SASL authentication successful";
+        assert_eq!(handle(&mut caps, success), vec!["CAP END\r\n"]);
+    }
+
+    #[test]
+    fn test_sasl_only_with_credentials() {
+        let mut caps = Caps::default();
+        let ls = ":irc.example.com CAP * LS :sasl";
+        assert_eq!(handle(&mut caps, ls), vec!["CAP END\r\n"]);
+    }
+
+    #[test]
+    fn test_nothing_wanted() {
+        let mut caps = Caps::default();
+        let ls = ":irc.example.com CAP * LS :userhost-in-names";
+        assert_eq!(handle(&mut caps, ls), vec!["CAP END\r\n"]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Keeping the connection to the server alive: noticing when it has gone
+//! quiet and spacing out attempts to reconnect.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Silence for this long gets a PING from us...
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(120);
+/// ...and no answer within this long counts as a dropped connection.
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(60);
+
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Delays between reconnect attempts. Each one doubles up to a limit, with
+/// jitter so a netsplit doesn't have every client reconnecting at once.
+#[derive(Debug)]
+pub struct Backoff {
+    attempt: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(INITIAL_DELAY, MAX_DELAY)
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            attempt: 0,
+            initial,
+            max,
+        }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// How long to wait before the next attempt.
+    pub fn next_delay(&mut self) -> Duration {
+        self.delay(random_fraction())
+    }
+
+    /// Starts over once a connection has been registered.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// The delay for the next attempt with a `jitter` between 0 and 1. Half
+    /// the delay is fixed and the rest is scaled by the jitter.
+    fn delay(&mut self, jitter: f64) -> Duration {
+        let base = self
+            .initial
+            .checked_mul(2u32.saturating_pow(self.attempt))
+            .map_or(self.max, |d| d.min(self.max));
+        self.attempt = self.attempt.saturating_add(1);
+        base / 2 + (base / 2).mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos % 1_000_000) / 1_000_000.0
+}
+
+/// Changes to the connection the UI gets told about.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Connecting {
+        server: String,
+        attempt: u32,
+    },
+    Connected,
+    Disconnected {
+        reason: String,
+    },
+    Retrying {
+        delay: Duration,
+    },
+    /// Registered again after a reconnect, rejoining `channels`.
+    Restored {
+        channels: Vec<String>,
+        queries: Vec<String>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Liveness {
+    Alive,
+    /// Nothing heard in a while, time to send a PING.
+    Ping,
+    /// The PING went unanswered.
+    TimedOut,
+}
+
+/// Watches for a connection that has gone quiet.
+#[derive(Debug)]
+pub struct PingTimer {
+    last_data: Instant,
+    ping_sent: Option<Instant>,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl PingTimer {
+    pub fn new(now: Instant, interval: Duration, timeout: Duration) -> Self {
+        Self {
+            last_data: now,
+            ping_sent: None,
+            interval,
+            timeout,
+        }
+    }
+
+    /// Anything from the server shows the connection is alive.
+    pub fn received(&mut self, now: Instant) {
+        self.last_data = now;
+        self.ping_sent = None;
+    }
+
+    pub fn check(&mut self, now: Instant) -> Liveness {
+        match self.ping_sent {
+            Some(sent) if now.duration_since(sent) >= self.timeout => Liveness::TimedOut,
+            Some(_) => Liveness::Alive,
+            None if now.duration_since(self.last_data) >= self.interval => {
+                self.ping_sent = Some(now);
+                Liveness::Ping
+            }
+            None => Liveness::Alive,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(10));
+        let delays: Vec<u64> = (0..6).map(|_| backoff.delay(1.0).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.attempt(), 6);
+
+        backoff.reset();
+        assert_eq!(backoff.delay(1.0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let mut backoff = Backoff::new(Duration::from_secs(8), Duration::from_secs(60));
+        assert_eq!(backoff.delay(0.0), Duration::from_secs(4));
+        assert_eq!(backoff.delay(0.5), Duration::from_secs(12));
+        assert_eq!(backoff.delay(1.0), Duration::from_secs(32));
+        for _ in 0..10 {
+            let delay = backoff.next_delay();
+            assert!(delay >= Duration::from_secs(30) && delay <= Duration::from_secs(60));
+        }
+    }
+
+    #[test]
+    fn test_ping_timer() {
+        let start = Instant::now();
+        let (interval, timeout) = (Duration::from_secs(30), Duration::from_secs(10));
+        let mut timer = PingTimer::new(start, interval, timeout);
+        assert_eq!(timer.check(start + interval / 2), Liveness::Alive);
+
+        let ping = start + interval;
+        assert_eq!(timer.check(ping), Liveness::Ping);
+        assert_eq!(timer.check(ping + timeout / 2), Liveness::Alive);
+        assert_eq!(timer.check(ping + timeout), Liveness::TimedOut);
+
+        timer.received(ping + timeout);
+        assert_eq!(timer.check(ping + timeout), Liveness::Alive);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use crate::message::ctcp::Ctcp;
+use crate::message::{Command, Message};
+
+pub const VERSION: &str = concat!("yairc ", env!("CARGO_PKG_VERSION"));
+
+/// CTCP commands we understand, sorted for CLIENTINFO.
+pub const SUPPORTED: &[&str] = &["ACTION", "CLIENTINFO", "DCC", "PING", "TIME", "VERSION"];
+
+/// No more than `MAX_REPLIES` replies go out in any `WINDOW`, and each nick
+/// gets at most one reply per `NICK_INTERVAL`. Anything over is ignored so a
+/// CTCP flood can't get us disconnected for flooding the server ourselves.
+const MAX_REPLIES: usize = 4;
+const WINDOW: Duration = Duration::from_secs(10);
+const NICK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Answers CTCP requests sent to us, with who to send the reply to.
+#[derive(Default)]
+pub struct Responder {
+    sent: VecDeque<Instant>,
+    last_reply: HashMap<String, Instant>,
+}
+
+impl Responder {
+    pub fn reply(&mut self, message: &Message, now: Instant) -> Option<(String, Ctcp)> {
+        if message.command() != &Command::PrivMsg {
+            return None;
+        }
+        let nick = message.source()?.nick.clone();
+        let reply = answer(&message.ctcp()?)?;
+
+        while let Some(sent) = self.sent.front() {
+            if now.duration_since(*sent) < WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= MAX_REPLIES {
+            return None;
+        }
+        // Only nicks still waiting out their interval are kept
+        self.last_reply
+            .retain(|_, last| now.duration_since(*last) < NICK_INTERVAL);
+        if self.last_reply.contains_key(&nick) {
+            return None;
+        }
+
+        self.sent.push_back(now);
+        self.last_reply.insert(nick.clone(), now);
+        Some((nick, reply))
+    }
+}
+
+fn answer(request: &Ctcp) -> Option<Ctcp> {
+    let params = match request.command.as_str() {
+        "VERSION" => VERSION.to_string(),
+        "PING" => request.params.clone().unwrap_or_default(),
+        "TIME" => Local::now().to_rfc2822(),
+        "CLIENTINFO" => SUPPORTED.join(" "),
+        _ => return None,
+    };
+    Some(Ctcp::new(&request.command, Some(&params)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn request(nick: &str, ctcp: &str) -> Message {
+        parse_line(&format!(":{}!u@host PRIVMSG me :\u{1}{}\u{1}", nick, ctcp))
+    }
+
+    #[test]
+    fn test_replies() {
+        let mut responder = Responder::default();
+        let now = Instant::now();
+
+        let reply = responder.reply(&request("alice", "VERSION"), now).unwrap();
+        assert_eq!(
+            reply,
+            ("alice".to_string(), Ctcp::new("VERSION", Some(VERSION)))
+        );
+
+        let (_, reply) = responder.reply(&request("bob", "PING 42"), now).unwrap();
+        assert_eq!(reply, Ctcp::new("PING", Some("42")));
+
+        let (_, reply) = responder
+            .reply(&request("carol", "CLIENTINFO"), now)
+            .unwrap();
+        assert_eq!(
+            reply,
+            Ctcp::new(
+                "CLIENTINFO",
+                Some("ACTION CLIENTINFO DCC PING TIME VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn test_ignores_actions_and_unknown_requests() {
+        let mut responder = Responder::default();
+        let now = Instant::now();
+        assert_eq!(
+            responder.reply(&request("alice", "ACTION waves"), now),
+            None
+        );
+        assert_eq!(responder.reply(&request("alice", "FINGER"), now), None);
+    }
+
+    #[test]
+    fn test_rate_limits_each_nick() {
+        let mut responder = Responder::default();
+        let now = Instant::now();
+        assert!(responder.reply(&request("alice", "VERSION"), now).is_some());
+        assert!(responder.reply(&request("alice", "VERSION"), now).is_none());
+        assert!(responder
+            .reply(&request("alice", "VERSION"), now + NICK_INTERVAL)
+            .is_some());
+    }
+
+    #[test]
+    fn test_forgets_nicks() {
+        let mut responder = Responder::default();
+        let now = Instant::now();
+        for i in 0..100 {
+            let nick = format!("nick{}", i);
+            let then = now + WINDOW * i;
+            assert!(responder.reply(&request(&nick, "VERSION"), then).is_some());
+        }
+        assert_eq!(responder.last_reply.len(), 1);
+    }
+
+    #[test]
+    fn test_rate_limits_overall() {
+        let mut responder = Responder::default();
+        let now = Instant::now();
+        for nick in ["a", "b", "c", "d"].iter() {
+            assert!(responder.reply(&request(nick, "VERSION"), now).is_some());
+        }
+        assert!(responder.reply(&request("e", "VERSION"), now).is_none());
+        assert!(responder
+            .reply(&request("e", "VERSION"), now + WINDOW)
+            .is_some());
+    }
+}
@@ -0,0 +1,1036 @@
+//! DCC CHAT and SEND connections. Negotiation happens over CTCP, the data
+//! goes over a direct TCP connection handled on its own thread.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::client::Event as ClientEvent;
+use crate::message::dcc::Dcc;
+use crate::message::Message;
+
+/// How long to wait for the other side to connect to a port we listen on.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long to wait for the receiver to acknowledge all of a file.
+const ACK_TIMEOUT: Duration = Duration::from_secs(60);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Offer {
+        id: usize,
+        nick: String,
+        description: String,
+    },
+    Progress {
+        id: usize,
+        filename: String,
+        transferred: u64,
+        size: Option<u64>,
+    },
+    Finished {
+        id: usize,
+        filename: String,
+    },
+    Failed {
+        id: usize,
+        reason: String,
+    },
+    ChatOpened {
+        nick: String,
+    },
+    ChatLine {
+        nick: String,
+        text: String,
+    },
+    ChatClosed {
+        nick: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    /// Waiting for the user to accept or reject an offer.
+    Offered,
+    /// Waiting for the other side to connect, accept or answer.
+    Waiting,
+    Active,
+}
+
+#[derive(Debug)]
+pub struct Transfer {
+    pub id: usize,
+    pub nick: String,
+    pub direction: Direction,
+    offer: Dcc,
+    status: Status,
+    path: Option<PathBuf>,
+    /// Where to start in the file. Shared with the connection's thread, as
+    /// a RESUME can come in after an active offer is already listening.
+    position: Arc<AtomicU64>,
+}
+
+impl Transfer {
+    pub fn describe(&self) -> String {
+        match &self.offer {
+            Dcc::Chat { .. } => format!("chat with {}", self.nick),
+            Dcc::Send { filename, size, .. } => match size {
+                Some(size) => format!("{} ({}) with {}", filename, human_size(*size), self.nick),
+                None => format!("{} with {}", filename, self.nick),
+            },
+            _ => String::new(),
+        }
+    }
+
+    pub fn status(&self) -> &'static str {
+        match self.status {
+            Status::Offered => "offered",
+            Status::Waiting => "waiting",
+            Status::Active => "active",
+        }
+    }
+}
+
+/// Keeps track of DCC offers and open chats.
+pub struct Manager {
+    next_id: usize,
+    transfers: BTreeMap<usize, Transfer>,
+    chats: Arc<Mutex<HashMap<String, TcpStream>>>,
+    download_dir: PathBuf,
+    local_address: IpAddr,
+    events: Sender<ClientEvent>,
+}
+
+impl Manager {
+    pub fn new(events: Sender<ClientEvent>) -> Self {
+        Self {
+            next_id: 1,
+            transfers: BTreeMap::new(),
+            chats: Arc::new(Mutex::new(HashMap::new())),
+            download_dir: default_download_dir(),
+            local_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            events,
+        }
+    }
+
+    pub fn set_download_dir(&mut self, dir: &Path) {
+        self.download_dir = dir.to_path_buf();
+    }
+
+    /// The address we tell others to connect to, normally the local end of
+    /// the connection to the IRC server.
+    pub fn set_local_address(&mut self, address: IpAddr) {
+        self.local_address = address;
+    }
+
+    pub fn transfers(&self) -> impl Iterator<Item = &Transfer> {
+        self.transfers.values()
+    }
+
+    /// Handles a DCC request from `nick`, returning any messages to send in
+    /// response.
+    pub fn handle(&mut self, nick: &str, dcc: Dcc) -> Vec<Message> {
+        match &dcc {
+            Dcc::Chat { .. } | Dcc::Send { .. } => {
+                // An answer to one of our passive offers
+                if let Some(id) = self.find_passive(nick, dcc.token()) {
+                    if let Err(e) = self.connect_answer(id, &dcc) {
+                        self.fail(id, &e.to_string());
+                    }
+                    return vec![];
+                }
+                let id = self.add(nick, dcc, Direction::Incoming, Status::Offered);
+                let description = self.transfers[&id].describe();
+                self.emit(Event::Offer {
+                    id,
+                    nick: nick.to_string(),
+                    description,
+                });
+                vec![]
+            }
+            Dcc::Resume {
+                filename,
+                port,
+                position,
+                token,
+            } => {
+                let id = self.find_offer(nick, Direction::Outgoing, None, *port, token.as_deref());
+                let id = match id {
+                    Some(id) => id,
+                    None => return vec![],
+                };
+                self.transfers[&id]
+                    .position
+                    .store(*position, Ordering::SeqCst);
+                let accept = Dcc::Accept {
+                    filename: filename.clone(),
+                    port: *port,
+                    position: *position,
+                    token: token.clone(),
+                };
+                vec![Message::ctcp_request(nick, &accept.to_ctcp())]
+            }
+            // Only once we've asked to resume, or it would start a transfer
+            // the user never accepted
+            Dcc::Accept { port, token, .. } => {
+                let waiting = Some(Status::Waiting);
+                let id =
+                    self.find_offer(nick, Direction::Incoming, waiting, *port, token.as_deref());
+                let id = match id {
+                    Some(id) => id,
+                    None => return vec![],
+                };
+                match self.start_incoming(id) {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        self.fail(id, &e.to_string());
+                        vec![]
+                    }
+                }
+            }
+        }
+    }
+
+    /// Accepts an incoming offer. Files that were partly downloaded before
+    /// are resumed, anything else already there is kept and the download
+    /// saved under another name.
+    pub fn accept(&mut self, id: usize) -> Result<Vec<Message>, String> {
+        let transfer = match self.transfers.get_mut(&id) {
+            Some(t) if t.direction == Direction::Incoming && t.status == Status::Offered => t,
+            _ => return Err(format!("No DCC offer {}", id)),
+        };
+
+        if let Dcc::Send {
+            filename,
+            port,
+            size,
+            token,
+            ..
+        } = &transfer.offer
+        {
+            fs::create_dir_all(&self.download_dir).map_err(|e| e.to_string())?;
+            let path = self.download_dir.join(safe_filename(filename));
+            let existing = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            let resume = existing > 0 && size.is_some_and(|size| existing < size);
+            transfer.path = Some(if path.exists() && !resume {
+                unused_path(&path)
+            } else {
+                path
+            });
+
+            if resume {
+                transfer.position.store(existing, Ordering::SeqCst);
+                transfer.status = Status::Waiting;
+                let resume = Dcc::Resume {
+                    filename: filename.clone(),
+                    port: *port,
+                    position: existing,
+                    token: token.clone(),
+                };
+                return Ok(vec![Message::ctcp_request(
+                    &transfer.nick,
+                    &resume.to_ctcp(),
+                )]);
+            }
+        }
+
+        self.start_incoming(id).map_err(|e| e.to_string())
+    }
+
+    /// Forgets a transfer once its connection is done with.
+    pub fn finished(&mut self, id: usize) {
+        self.transfers.remove(&id);
+    }
+
+    pub fn reject(&mut self, id: usize) -> Result<(), String> {
+        match self.transfers.get(&id) {
+            Some(t) if t.status == Status::Offered => {
+                self.transfers.remove(&id);
+                Ok(())
+            }
+            _ => Err(format!("No DCC offer {}", id)),
+        }
+    }
+
+    /// Offers `path` to `nick`. A passive offer asks them to listen instead,
+    /// which works when we can't accept incoming connections.
+    pub fn offer_file(
+        &mut self,
+        nick: &str,
+        path: &Path,
+        passive: bool,
+    ) -> Result<Vec<Message>, String> {
+        let size = fs::metadata(path).map_err(|e| e.to_string())?.len();
+        let filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} is not a file", path.display()))?;
+        let offer = Dcc::Send {
+            filename,
+            address: self.local_address,
+            port: 0,
+            size: Some(size),
+            token: None,
+        };
+        let id = self.add(nick, offer, Direction::Outgoing, Status::Waiting);
+        self.transfers.get_mut(&id).unwrap().path = Some(path.to_path_buf());
+        self.offer(id, passive).map_err(|e| e.to_string())
+    }
+
+    pub fn offer_chat(&mut self, nick: &str, passive: bool) -> Result<Vec<Message>, String> {
+        let offer = Dcc::Chat {
+            address: self.local_address,
+            port: 0,
+            token: None,
+        };
+        let id = self.add(nick, offer, Direction::Outgoing, Status::Waiting);
+        self.offer(id, passive).map_err(|e| e.to_string())
+    }
+
+    pub fn send_chat(&self, nick: &str, text: &str) -> io::Result<()> {
+        match self.chats.lock().unwrap().get_mut(nick) {
+            Some(stream) => writeln!(stream, "{}", text),
+            None => Err(io::Error::new(ErrorKind::NotConnected, "No DCC chat open")),
+        }
+    }
+
+    pub fn close_chat(&self, nick: &str) {
+        if let Some(stream) = self.chats.lock().unwrap().remove(nick) {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    fn add(&mut self, nick: &str, offer: Dcc, direction: Direction, status: Status) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.transfers.insert(
+            id,
+            Transfer {
+                id,
+                nick: nick.to_string(),
+                direction,
+                offer,
+                status,
+                path: None,
+                position: Arc::new(AtomicU64::new(0)),
+            },
+        );
+        id
+    }
+
+    fn find_passive(&self, nick: &str, token: Option<&str>) -> Option<usize> {
+        let token = token?;
+        self.transfers
+            .values()
+            .find(|t| {
+                t.nick == nick
+                    && t.direction == Direction::Outgoing
+                    && t.offer.token() == Some(token)
+            })
+            .map(|t| t.id)
+    }
+
+    fn find_offer(
+        &self,
+        nick: &str,
+        direction: Direction,
+        status: Option<Status>,
+        port: u16,
+        token: Option<&str>,
+    ) -> Option<usize> {
+        self.transfers
+            .values()
+            .find(|t| {
+                let port_matches = match &t.offer {
+                    Dcc::Send { port: p, .. } | Dcc::Chat { port: p, .. } => *p == port,
+                    _ => false,
+                };
+                t.nick == nick
+                    && t.direction == direction
+                    && status.is_none_or(|status| t.status == status)
+                    && (port_matches || (token.is_some() && t.offer.token() == token))
+            })
+            .map(|t| t.id)
+    }
+
+    /// Sends our offer. Active offers listen straight away, passive ones
+    /// wait for the other side to answer with where to connect.
+    fn offer(&mut self, id: usize, passive: bool) -> io::Result<Vec<Message>> {
+        let local_address = self.local_address;
+        let transfer = self.transfers.get_mut(&id).unwrap();
+        if passive {
+            set_endpoint(&mut transfer.offer, local_address, 0, Some(token(id)));
+            return Ok(vec![Message::ctcp_request(
+                &transfer.nick,
+                &transfer.offer.to_ctcp(),
+            )]);
+        }
+
+        let listener = TcpListener::bind(SocketAddr::new(local_address, 0))?;
+        let port = listener.local_addr()?.port();
+        set_endpoint(&mut transfer.offer, local_address, port, None);
+        let message = Message::ctcp_request(&transfer.nick, &transfer.offer.to_ctcp());
+        self.spawn_connection(id, Connection::Listen(listener));
+        Ok(vec![message])
+    }
+
+    /// The other side answered our passive offer with an address to use.
+    fn connect_answer(&mut self, id: usize, answer: &Dcc) -> io::Result<()> {
+        let address = match answer {
+            Dcc::Chat { address, port, .. } | Dcc::Send { address, port, .. } => {
+                SocketAddr::new(*address, *port)
+            }
+            _ => return Ok(()),
+        };
+        self.spawn_connection(id, Connection::Connect(address));
+        Ok(())
+    }
+
+    fn start_incoming(&mut self, id: usize) -> io::Result<Vec<Message>> {
+        let local_address = self.local_address;
+        let transfer = self.transfers.get_mut(&id).unwrap();
+        if !transfer.offer.is_passive() {
+            let address = match &transfer.offer {
+                Dcc::Chat { address, port, .. } | Dcc::Send { address, port, .. } => {
+                    SocketAddr::new(*address, *port)
+                }
+                _ => return Ok(vec![]),
+            };
+            self.spawn_connection(id, Connection::Connect(address));
+            return Ok(vec![]);
+        }
+
+        // Passive offer, we listen and tell them where
+        let listener = TcpListener::bind(SocketAddr::new(local_address, 0))?;
+        let port = listener.local_addr()?.port();
+        let mut answer = transfer.offer.clone();
+        let token = answer.token().map(|t| t.to_string());
+        set_endpoint(&mut answer, local_address, port, token);
+        let message = Message::ctcp_request(&transfer.nick, &answer.to_ctcp());
+        self.spawn_connection(id, Connection::Listen(listener));
+        Ok(vec![message])
+    }
+
+    fn spawn_connection(&mut self, id: usize, connection: Connection) {
+        let transfer = self.transfers.get_mut(&id).unwrap();
+        transfer.status = Status::Active;
+
+        let nick = transfer.nick.clone();
+        let direction = transfer.direction;
+        let path = transfer.path.clone();
+        let position = transfer.position.clone();
+        let offer = transfer.offer.clone();
+        let events = self.events.clone();
+        let chats = self.chats.clone();
+
+        thread::spawn(move || {
+            let emit = |event| {
+                let _ = events.send(ClientEvent::Dcc(event));
+            };
+            // Only known for sure once the other side has connected
+            let result = connection.open().and_then(|stream| {
+                let position = position.load(Ordering::SeqCst);
+                match (&offer, direction) {
+                    (Dcc::Chat { .. }, _) => run_chat(stream, &nick, &chats, &emit),
+                    (Dcc::Send { filename, size, .. }, Direction::Incoming) => {
+                        let path = path.unwrap();
+                        receive_file(stream, &path, position, *size, |transferred| {
+                            emit(Event::Progress {
+                                id,
+                                filename: filename.clone(),
+                                transferred,
+                                size: *size,
+                            })
+                        })
+                        .map(|_| {
+                            emit(Event::Finished {
+                                id,
+                                filename: path.display().to_string(),
+                            })
+                        })
+                    }
+                    (Dcc::Send { filename, size, .. }, Direction::Outgoing) => {
+                        let path = path.unwrap();
+                        send_file(stream, &path, position, ACK_TIMEOUT, |transferred| {
+                            emit(Event::Progress {
+                                id,
+                                filename: filename.clone(),
+                                transferred,
+                                size: *size,
+                            })
+                        })
+                        .map(|_| {
+                            emit(Event::Finished {
+                                id,
+                                filename: filename.clone(),
+                            })
+                        })
+                    }
+                    _ => Ok(()),
+                }
+            });
+            if let Err(e) = result {
+                emit(Event::Failed {
+                    id,
+                    reason: e.to_string(),
+                });
+            }
+        });
+    }
+
+    fn fail(&mut self, id: usize, reason: &str) {
+        self.transfers.remove(&id);
+        self.emit(Event::Failed {
+            id,
+            reason: reason.to_string(),
+        });
+    }
+
+    fn emit(&self, event: Event) {
+        let _ = self.events.send(ClientEvent::Dcc(event));
+    }
+}
+
+enum Connection {
+    Listen(TcpListener),
+    Connect(SocketAddr),
+}
+
+impl Connection {
+    fn open(self) -> io::Result<TcpStream> {
+        match self {
+            Connection::Connect(address) => TcpStream::connect_timeout(&address, CONNECT_TIMEOUT),
+            Connection::Listen(listener) => {
+                listener.set_nonblocking(true)?;
+                let deadline = Instant::now() + CONNECT_TIMEOUT;
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            stream.set_nonblocking(false)?;
+                            return Ok(stream);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                            if Instant::now() > deadline {
+                                return Err(io::Error::new(
+                                    ErrorKind::TimedOut,
+                                    "Nobody connected",
+                                ));
+                            }
+                            thread::sleep(Duration::from_millis(100));
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn set_endpoint(offer: &mut Dcc, address: IpAddr, port: u16, token: Option<String>) {
+    match offer {
+        Dcc::Chat {
+            address: a,
+            port: p,
+            token: t,
+        }
+        | Dcc::Send {
+            address: a,
+            port: p,
+            token: t,
+            ..
+        } => {
+            *a = address;
+            *p = port;
+            *t = token;
+        }
+        _ => {}
+    }
+}
+
+fn run_chat<F: Fn(Event)>(
+    stream: TcpStream,
+    nick: &str,
+    chats: &Mutex<HashMap<String, TcpStream>>,
+    emit: &F,
+) -> io::Result<()> {
+    chats
+        .lock()
+        .unwrap()
+        .insert(nick.to_string(), stream.try_clone()?);
+    emit(Event::ChatOpened {
+        nick: nick.to_string(),
+    });
+    for line in BufReader::new(stream).lines() {
+        match line {
+            Ok(text) => emit(Event::ChatLine {
+                nick: nick.to_string(),
+                text: text.trim_end_matches('\r').to_string(),
+            }),
+            Err(_) => break,
+        }
+    }
+    chats.lock().unwrap().remove(nick);
+    emit(Event::ChatClosed {
+        nick: nick.to_string(),
+    });
+    Ok(())
+}
+
+/// Reads a file from `stream` into `path`, appending when resuming from
+/// `position`. Every chunk is acknowledged with the total received so far as
+/// a 32-bit big-endian number, as senders expect.
+fn receive_file<F: FnMut(u64)>(
+    mut stream: TcpStream,
+    path: &Path,
+    position: u64,
+    size: Option<u64>,
+    mut progress: F,
+) -> io::Result<()> {
+    let mut file = if position > 0 {
+        OpenOptions::new().append(true).open(path)?
+    } else {
+        // Never over something that turned up since it was accepted
+        OpenOptions::new().write(true).create_new(true).open(path)?
+    };
+    let mut received = position;
+    let mut buf = [0u8; CHUNK_SIZE];
+    let mut last_progress = Instant::now();
+
+    while size.is_none_or(|size| received < size) {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n])?;
+        received += n as u64;
+        stream.write_all(&(received as u32).to_be_bytes())?;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            progress(received);
+            last_progress = Instant::now();
+        }
+    }
+    progress(received);
+
+    match size {
+        Some(size) if received < size => Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("Connection closed after {} of {} bytes", received, size),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Writes `path` to `stream` starting at `position`, then waits up to
+/// `ack_timeout` for the receiver to acknowledge everything or hang up so
+/// no data is lost when the connection closes.
+fn send_file<F: FnMut(u64)>(
+    mut stream: TcpStream,
+    path: &Path,
+    position: u64,
+    ack_timeout: Duration,
+    mut progress: F,
+) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(position))?;
+
+    let mut acks = stream.try_clone()?;
+    let (done, acknowledged) = mpsc::channel();
+    thread::spawn(move || {
+        let mut ack = [0u8; 4];
+        while acks.read_exact(&mut ack).is_ok() {
+            if u32::from_be_bytes(ack) == size as u32 {
+                break;
+            }
+        }
+        let _ = done.send(());
+    });
+
+    let mut sent = position;
+    let mut buf = [0u8; CHUNK_SIZE];
+    let mut last_progress = Instant::now();
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n])?;
+        sent += n as u64;
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            progress(sent);
+            last_progress = Instant::now();
+        }
+    }
+    progress(sent);
+
+    if acknowledged.recv_timeout(ack_timeout).is_err() {
+        // Also ends the thread reading acks
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+        return Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "The receiver never acknowledged all of it",
+        ));
+    }
+    Ok(())
+}
+
+/// Tokens for passive offers only have to be unique between us and the
+/// other side, so mix in the time in case they use the same numbering.
+fn token(id: usize) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!("{}{:04}", id, nanos % 10_000)
+}
+
+/// Only keep the last component of a filename someone sent us, so a file
+/// can't be written outside the download directory.
+fn safe_filename(filename: &str) -> String {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or("")
+        .trim_start_matches('.');
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `path` with a number added to its name, `notes (1).txt`, that isn't
+/// taken yet.
+fn unused_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// `$YAIRC_DOWNLOAD_DIR`, otherwise `~/Downloads`.
+fn default_download_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("YAIRC_DOWNLOAD_DIR") {
+        return PathBuf::from(dir);
+    }
+    match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join("Downloads"),
+        None => PathBuf::from("."),
+    }
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+
+    use crate::message::ctcp::Ctcp;
+
+    fn manager(dir: &Path) -> (Manager, Receiver<ClientEvent>) {
+        let (sender, receiver) = channel();
+        let mut manager = Manager::new(sender);
+        manager.set_download_dir(dir);
+        (manager, receiver)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("yairc-dcc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn dcc(message: &Message) -> Dcc {
+        Dcc::parse(&message.ctcp().unwrap()).unwrap()
+    }
+
+    fn wait_for_finish(events: &Receiver<ClientEvent>) -> Event {
+        loop {
+            match events.recv_timeout(Duration::from_secs(10)).unwrap() {
+                ClientEvent::Dcc(event @ Event::Finished { .. })
+                | ClientEvent::Dcc(event @ Event::Failed { .. }) => return event,
+                _ => {}
+            }
+        }
+    }
+
+    fn wait_for_offer(events: &Receiver<ClientEvent>) -> usize {
+        match events.recv_timeout(Duration::from_secs(10)).unwrap() {
+            ClientEvent::Dcc(Event::Offer { id, .. }) => id,
+            event => panic!("Expected an offer, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_safe_filename() {
+        assert_eq!(safe_filename("../../etc/passwd"), "passwd");
+        assert_eq!(safe_filename("C:\\temp\\notes.txt"), "notes.txt");
+        assert_eq!(safe_filename(".bashrc"), "bashrc");
+        assert_eq!(safe_filename(".."), "download");
+    }
+
+    #[test]
+    fn test_keeps_existing_files() {
+        let dir = temp_dir("existing");
+        fs::write(dir.join("notes.txt"), "mine").unwrap();
+        fs::write(dir.join("notes (1).txt"), "mine too").unwrap();
+        fs::write(dir.join("README"), "also mine").unwrap();
+        assert_eq!(
+            unused_path(&dir.join("notes.txt")),
+            dir.join("notes (2).txt")
+        );
+        assert_eq!(unused_path(&dir.join("README")), dir.join("README (1)"));
+
+        // As big as what's offered, and of unknown size, aren't resumed
+        let (mut bob, bob_events) = manager(&dir);
+        let ids: Vec<usize> = ["SEND notes.txt 2130706433 1 4", "SEND README 2130706433 1"]
+            .iter()
+            .map(|offer| {
+                bob.handle("alice", Dcc::parse(&Ctcp::new("DCC", Some(offer))).unwrap());
+                wait_for_offer(&bob_events)
+            })
+            .collect();
+        for id in ids {
+            assert!(bob.accept(id).unwrap().is_empty());
+        }
+        let paths: Vec<PathBuf> = bob.transfers().filter_map(|t| t.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![dir.join("notes (2).txt"), dir.join("README (1)")]
+        );
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "mine");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KB");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0 MB");
+    }
+
+    #[test]
+    fn test_send_between_managers() {
+        let dir = temp_dir("send");
+        let source = dir.join("source.bin");
+        let data: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let downloads = dir.join("downloads");
+
+        let (mut alice, alice_events) = manager(&dir);
+        let (mut bob, bob_events) = manager(&downloads);
+
+        let offer = alice.offer_file("bob", &source, false).unwrap();
+        assert!(bob.handle("alice", dcc(&offer[0])).is_empty());
+        let id = wait_for_offer(&bob_events);
+        assert!(bob.accept(id).unwrap().is_empty());
+
+        assert!(matches!(
+            wait_for_finish(&bob_events),
+            Event::Finished { .. }
+        ));
+        assert!(matches!(
+            wait_for_finish(&alice_events),
+            Event::Finished { .. }
+        ));
+        assert_eq!(fs::read(downloads.join("source.bin")).unwrap(), data);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_send_without_acks() {
+        let dir = temp_dir("acks");
+        let source = dir.join("notes.txt");
+        fs::write(&source, b"0123456789").unwrap();
+
+        // Reads it all, but never says so or hangs up
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut receiver, _) = listener.accept().unwrap();
+        let reader = thread::spawn(move || {
+            let mut data = [0u8; 10];
+            receiver.read_exact(&mut data).unwrap();
+            receiver
+        });
+
+        let result = send_file(stream, &source, 0, Duration::from_millis(200), |_| {});
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+        drop(reader.join());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_passive_send_with_resume() {
+        let dir = temp_dir("passive");
+        let source = dir.join("notes.txt");
+        let data = b"0123456789abcdefghij".to_vec();
+        fs::write(&source, &data).unwrap();
+        let downloads = dir.join("downloads");
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(downloads.join("notes.txt"), &data[..8]).unwrap();
+
+        let (mut alice, alice_events) = manager(&dir);
+        let (mut bob, bob_events) = manager(&downloads);
+
+        let offer = alice.offer_file("bob", &source, true).unwrap();
+        assert!(dcc(&offer[0]).is_passive());
+        bob.handle("alice", dcc(&offer[0]));
+        let id = wait_for_offer(&bob_events);
+
+        // Bob has part of the file already so asks to resume
+        let resume = bob.accept(id).unwrap();
+        assert!(matches!(dcc(&resume[0]), Dcc::Resume { position: 8, .. }));
+        let accept = alice.handle("bob", dcc(&resume[0]));
+        assert!(matches!(dcc(&accept[0]), Dcc::Accept { position: 8, .. }));
+
+        // Bob listens and tells Alice where, Alice connects and sends
+        let answer = bob.handle("alice", dcc(&accept[0]));
+        assert!(matches!(dcc(&answer[0]), Dcc::Send { port, .. } if port != 0));
+        assert!(alice.handle("bob", dcc(&answer[0])).is_empty());
+
+        assert!(matches!(
+            wait_for_finish(&bob_events),
+            Event::Finished { .. }
+        ));
+        assert!(matches!(
+            wait_for_finish(&alice_events),
+            Event::Finished { .. }
+        ));
+        assert_eq!(fs::read(downloads.join("notes.txt")).unwrap(), data);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_active_send_with_resume() {
+        let dir = temp_dir("active");
+        let source = dir.join("log.txt");
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &data).unwrap();
+        let downloads = dir.join("downloads");
+        fs::create_dir_all(&downloads).unwrap();
+        fs::write(downloads.join("log.txt"), &data[..1000]).unwrap();
+
+        let (mut alice, alice_events) = manager(&dir);
+        let (mut bob, bob_events) = manager(&downloads);
+
+        // Alice is already listening by the time Bob asks to resume
+        let offer = alice.offer_file("bob", &source, false).unwrap();
+        bob.handle("alice", dcc(&offer[0]));
+        let id = wait_for_offer(&bob_events);
+        let resume = bob.accept(id).unwrap();
+        assert!(matches!(
+            dcc(&resume[0]),
+            Dcc::Resume { position: 1000, .. }
+        ));
+        let accept = alice.handle("bob", dcc(&resume[0]));
+        assert!(bob.handle("alice", dcc(&accept[0])).is_empty());
+
+        assert!(matches!(
+            wait_for_finish(&bob_events),
+            Event::Finished { .. }
+        ));
+        assert!(matches!(
+            wait_for_finish(&alice_events),
+            Event::Finished { .. }
+        ));
+        assert_eq!(fs::read(downloads.join("log.txt")).unwrap(), data);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_chat() {
+        let dir = temp_dir("chat");
+        let (mut alice, alice_events) = manager(&dir);
+        let (mut bob, bob_events) = manager(&dir);
+
+        let offer = alice.offer_chat("bob", false).unwrap();
+        bob.handle("alice", dcc(&offer[0]));
+        let id = wait_for_offer(&bob_events);
+        bob.accept(id).unwrap();
+
+        let opened =
+            |events: &Receiver<ClientEvent>| events.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(
+            opened(&bob_events),
+            ClientEvent::Dcc(Event::ChatOpened { .. })
+        ));
+        assert!(matches!(
+            opened(&alice_events),
+            ClientEvent::Dcc(Event::ChatOpened { .. })
+        ));
+
+        bob.send_chat("alice", "hi alice").unwrap();
+        assert_eq!(
+            opened(&alice_events),
+            ClientEvent::Dcc(Event::ChatLine {
+                nick: "bob".to_string(),
+                text: "hi alice".to_string()
+            })
+        );
+        bob.close_chat("alice");
+        assert_eq!(
+            opened(&alice_events),
+            ClientEvent::Dcc(Event::ChatClosed {
+                nick: "bob".to_string()
+            })
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reject() {
+        let dir = temp_dir("reject");
+        let (mut bob, bob_events) = manager(&dir);
+        let offer = Ctcp::new("DCC", Some("SEND x.bin 2130706433 5000 10"));
+        bob.handle("alice", Dcc::parse(&offer).unwrap());
+        let id = wait_for_offer(&bob_events);
+
+        // Nobody asked to resume it
+        let accept = Ctcp::new("DCC", Some("ACCEPT x.bin 10 0"));
+        assert!(bob.handle("alice", Dcc::parse(&accept).unwrap()).is_empty());
+        assert_eq!(bob.transfers().next().unwrap().status(), "offered");
+
+        assert!(bob.reject(id).is_ok());
+        assert!(bob.reject(id).is_err());
+        assert!(bob.accept(id).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,427 @@
+//! IRCv3 chat history, for catching up on what was said while we weren't
+//! there.
+//!
+//! <https://ircv3.net/specs/extensions/chathistory>
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+
+use crate::client::state::State;
+use crate::message::{Command, Message};
+
+pub const CAP: &str = "draft/chathistory";
+
+/// The batch types history comes back in.
+pub const HISTORY_BATCH: &str = "chathistory";
+pub const TARGETS_BATCH: &str = "draft/chathistory-targets";
+
+/// How many messages to ask for at a time, unless the server allows fewer.
+const DEFAULT_LIMIT: usize = 50;
+
+/// How many msgids are remembered for spotting duplicates.
+const SEEN_LIMIT: usize = 10_000;
+
+/// A point in a conversation to fetch history from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reference {
+    /// `*`, no bound at all. Only LATEST takes it.
+    Any,
+    MsgId(String),
+    Timestamp(DateTime<Utc>),
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::Any => write!(f, "*"),
+            Reference::MsgId(id) => write!(f, "msgid={}", id),
+            Reference::Timestamp(time) => write!(
+                f,
+                "timestamp={}",
+                time.to_rfc3339_opts(SecondsFormat::Millis, true)
+            ),
+        }
+    }
+}
+
+impl FromStr for Reference {
+    type Err = String;
+
+    /// Takes the wire forms, or a local `2026-01-31` or `2026-01-31T12:00`.
+    /// Anything else is a msgid.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "*" {
+            return Ok(Reference::Any);
+        }
+        if let Some(id) = text.strip_prefix("msgid=") {
+            return Ok(Reference::MsgId(id.to_string()));
+        }
+        if let Some(time) = text.strip_prefix("timestamp=") {
+            return parse_time(time)
+                .map(Reference::Timestamp)
+                .ok_or_else(|| format!("{} isn't a timestamp", time));
+        }
+        let local = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M"))
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+            });
+        match local.and_then(|l| Local.from_local_datetime(&l).earliest()) {
+            Some(time) => Ok(Reference::Timestamp(time.with_timezone(&Utc))),
+            None if text.is_empty() => Err("expected a msgid or a time".to_string()),
+            None => Ok(Reference::MsgId(text.to_string())),
+        }
+    }
+}
+
+/// What to ask the server for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subcommand {
+    /// The newest messages, after the reference if it isn't `*`.
+    Latest(Reference),
+    Before(Reference),
+    After(Reference),
+    Around(Reference),
+    Between(Reference, Reference),
+    /// Conversations with messages between two times, rather than the
+    /// messages themselves.
+    Targets(Reference, Reference),
+}
+
+impl Subcommand {
+    /// The CHATHISTORY request for up to `limit` messages in `target`, which
+    /// TARGETS doesn't take.
+    pub fn message(&self, target: &str, limit: usize) -> Message {
+        let limit = limit.to_string();
+        let (name, references) = match self {
+            Subcommand::Latest(from) => ("LATEST", vec![from]),
+            Subcommand::Before(from) => ("BEFORE", vec![from]),
+            Subcommand::After(from) => ("AFTER", vec![from]),
+            Subcommand::Around(from) => ("AROUND", vec![from]),
+            Subcommand::Between(from, to) => ("BETWEEN", vec![from, to]),
+            Subcommand::Targets(from, to) => ("TARGETS", vec![from, to]),
+        };
+        let references: Vec<String> = references.iter().map(|r| r.to_string()).collect();
+        let mut params = vec![name];
+        if name != "TARGETS" {
+            params.push(target);
+        }
+        params.extend(references.iter().map(|r| r.as_str()));
+        params.push(&limit);
+        Message::new(Command::ChatHistory, params)
+    }
+}
+
+/// The first and last message we've seen in a buffer.
+#[derive(Clone, Debug)]
+struct Mark {
+    time: DateTime<Utc>,
+    msgid: Option<String>,
+}
+
+impl Mark {
+    fn reference(&self) -> Reference {
+        match &self.msgid {
+            Some(id) => Reference::MsgId(id.clone()),
+            None => Reference::Timestamp(self.time),
+        }
+    }
+}
+
+/// Keeps track of what we've already seen, across reconnects.
+#[derive(Debug, Default)]
+pub struct History {
+    /// The oldest and newest message in each buffer, by lowercase name.
+    marks: HashMap<String, (Mark, Mark)>,
+    seen: HashSet<String>,
+    /// `seen` in the order it was filled, to forget the oldest first.
+    seen_order: VecDeque<String>,
+    /// When the newest message we've seen anywhere was sent.
+    last: Option<DateTime<Utc>>,
+}
+
+impl History {
+    /// What we hadn't seen yet of history fetched for `target`.
+    pub fn playback(&mut self, target: &str, messages: Vec<Message>) -> Vec<Message> {
+        messages
+            .into_iter()
+            .filter(|message| self.seen(target, message))
+            .collect()
+    }
+
+    /// Notes a message in `buffer`. Returns false if its msgid shows we've
+    /// seen it before.
+    pub fn seen(&mut self, buffer: &str, message: &Message) -> bool {
+        if let Some(id) = message.tag("msgid").filter(|id| !id.is_empty()) {
+            if !self.seen.insert(id.to_string()) {
+                return false;
+            }
+            self.seen_order.push_back(id.to_string());
+            if self.seen_order.len() > SEEN_LIMIT {
+                let oldest = self.seen_order.pop_front().unwrap_or_default();
+                self.seen.remove(&oldest);
+            }
+        }
+
+        let mark = Mark {
+            time: message.time().unwrap_or_else(Utc::now),
+            msgid: message.tag("msgid").map(|id| id.to_string()),
+        };
+        if self.last.is_none_or(|last| last < mark.time) {
+            self.last = Some(mark.time);
+        }
+        let marks = self
+            .marks
+            .entry(buffer.to_lowercase())
+            .or_insert_with(|| (mark.clone(), mark.clone()));
+        if mark.time < marks.0.time {
+            marks.0 = mark;
+        } else if mark.time >= marks.1.time {
+            marks.1 = mark;
+        }
+        true
+    }
+
+    /// The oldest message we've seen in `buffer`, to go further back from.
+    pub fn oldest(&self, buffer: &str) -> Option<Reference> {
+        let (oldest, _) = self.marks.get(&buffer.to_lowercase())?;
+        Some(oldest.reference())
+    }
+
+    fn newest(&self, buffer: &str) -> Option<Reference> {
+        let (_, newest) = self.marks.get(&buffer.to_lowercase())?;
+        Some(newest.reference())
+    }
+}
+
+/// The conversations in a TARGETS batch.
+pub fn targets(messages: &[Message]) -> Vec<String> {
+    // CHATHISTORY TARGETS <target> <timestamp of its latest message>
+    messages
+        .iter()
+        .filter(|m| m.command() == &Command::ChatHistory)
+        .filter_map(|m| Some(m.get_param(1)?.to_string()))
+        .collect()
+}
+
+fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// How many messages to ask for, `wanted` or our default, but no more than
+/// the server allows.
+pub fn limit(state: &State, wanted: Option<usize>) -> usize {
+    let wanted = wanted.unwrap_or(DEFAULT_LIMIT);
+    match state.isupport("CHATHISTORY").and_then(|m| m.parse().ok()) {
+        Some(max) if max > 0 => wanted.min(max),
+        _ => wanted,
+    }
+}
+
+/// Asks what was said in `target` since the last message we saw there, or
+/// since `fallback` if we haven't seen one this session. Nothing if the
+/// server doesn't keep history.
+pub fn catch_up(state: &State, target: &str, fallback: Option<Reference>) -> Option<Message> {
+    if !state.caps.is_enabled(CAP) {
+        return None;
+    }
+    let from = state
+        .history
+        .newest(target)
+        .or(fallback)
+        .unwrap_or(Reference::Any);
+    Some(Subcommand::Latest(from).message(target, limit(state, None)))
+}
+
+/// Asks which conversations had messages since the last one we saw, after
+/// a reconnect. Channels catch up when they're joined again.
+pub fn missed_targets(state: &State) -> Option<Message> {
+    let last = state.history.last.filter(|_| state.caps.is_enabled(CAP))?;
+    let subcommand =
+        Subcommand::Targets(Reference::Timestamp(last), Reference::Timestamp(Utc::now()));
+    Some(subcommand.message("", limit(state, None)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::batch::{Assembler, Batch, Item};
+    use crate::message::parse_line;
+
+    fn wire(message: Message) -> String {
+        String::from_utf8(message.as_bytes()).unwrap()
+    }
+
+    /// Plays a mock server's lines through a batch assembler, returning
+    /// what comes out of it.
+    fn replay(script: &str) -> Vec<Item> {
+        let mut assembler = Assembler::default();
+        script
+            .lines()
+            .filter_map(|line| assembler.receive(parse_line(line.trim())))
+            .collect()
+    }
+
+    fn batch(item: Item) -> Batch {
+        match item {
+            Item::Batch(batch) => batch,
+            Item::Message(m) => panic!("{:?}", m),
+        }
+    }
+
+    fn texts(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| m.get_param(1).unwrap().to_string())
+            .collect()
+    }
+
+    fn state() -> State {
+        let mut state = State::new("me");
+        let ls = ":irc.example.com CAP * LS :batch draft/chathistory message-tags";
+        state.caps.handle(&parse_line(ls));
+        let ack = ":irc.example.com CAP me ACK :batch draft/chathistory message-tags";
+        state.caps.handle(&parse_line(ack));
+        state.update(&parse_line(
+            ":irc.example.com 005 me CHATHISTORY=20 :are supported by this server",
+        ));
+        state
+    }
+
+    #[test]
+    fn test_references() {
+        let time = Utc.with_ymd_and_hms(2026, 1, 31, 12, 0, 0).unwrap();
+        assert_eq!(
+            Reference::Timestamp(time).to_string(),
+            "timestamp=2026-01-31T12:00:00.000Z"
+        );
+        assert_eq!(
+            "timestamp=2026-01-31T12:00:00.000Z".parse(),
+            Ok(Reference::Timestamp(time))
+        );
+        let local = Local.with_ymd_and_hms(2026, 1, 31, 12, 0, 0).unwrap();
+        assert_eq!(
+            "2026-01-31T12:00".parse(),
+            Ok(Reference::Timestamp(local.with_timezone(&Utc)))
+        );
+        assert_eq!("abc123".parse(), Ok(Reference::MsgId("abc123".to_string())));
+        assert_eq!("*".parse(), Ok(Reference::Any));
+        assert!("timestamp=yesterday".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn test_requests() {
+        let msgid = |id: &str| Reference::MsgId(id.to_string());
+        assert_eq!(
+            wire(Subcommand::Latest(Reference::Any).message("#ops", 50)),
+            "CHATHISTORY LATEST #ops * 50\r\n"
+        );
+        assert_eq!(
+            wire(Subcommand::Around(msgid("a")).message("alice", 10)),
+            "CHATHISTORY AROUND alice msgid=a 10\r\n"
+        );
+        assert_eq!(
+            wire(Subcommand::Between(msgid("a"), msgid("b")).message("#ops", 10)),
+            "CHATHISTORY BETWEEN #ops msgid=a msgid=b 10\r\n"
+        );
+        let time = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let targets = Subcommand::Targets(Reference::Timestamp(time), Reference::Timestamp(time));
+        assert_eq!(
+            wire(targets.message("#ignored", 5)),
+            "CHATHISTORY TARGETS timestamp=2026-01-01T00:00:00.000Z timestamp=2026-01-01T00:00:00.000Z 5\r\n"
+        );
+    }
+
+    #[test]
+    fn test_playback_skips_what_we_saw() {
+        let mut history = History::default();
+        let live = parse_line(
+            "@msgid=2;time=2026-01-01T12:01:00.000Z :bob!b@h PRIVMSG #ops :already here",
+        );
+        assert!(history.seen("#ops", &live));
+        assert!(!history.seen("#ops", &live));
+
+        let mut received = replay(
+            "@batch=outer :irc.example.com BATCH +h1 chathistory #ops
+             @batch=h1;msgid=1;time=2026-01-01T12:00:00.000Z :alice!a@h PRIVMSG #ops :before
+             @batch=h1;msgid=2;time=2026-01-01T12:01:00.000Z :bob!b@h PRIVMSG #ops :already here
+             :carol!c@h PRIVMSG #ops :live, in the middle of it
+             @batch=h1;msgid=3;time=2026-01-01T12:02:00.000Z :alice!a@h NOTICE #ops :after
+             :irc.example.com BATCH -h1",
+        );
+        assert_eq!(received.len(), 2);
+        assert!(matches!(&received[0], Item::Message(m) if m.source().unwrap().nick == "carol"));
+        let playback = batch(received.remove(1));
+        assert_eq!(playback.kind(), HISTORY_BATCH);
+        assert_eq!(playback.params(), vec!["#ops"]);
+        let messages = history.playback("#ops", playback.into_messages());
+        assert_eq!(texts(&messages), vec!["before", "after"]);
+        assert_eq!(
+            history.oldest("#OPS"),
+            Some(Reference::MsgId("1".to_string()))
+        );
+        assert_eq!(
+            history.newest("#ops"),
+            Some(Reference::MsgId("3".to_string()))
+        );
+
+        // Fetching the same stretch again turns up nothing new
+        let again = batch(
+            replay(
+                "BATCH +h2 chathistory #ops
+             @batch=h2;msgid=3;time=2026-01-01T12:02:00.000Z :alice!a@h NOTICE #ops :after
+             BATCH -h2",
+            )
+            .remove(0),
+        );
+        assert_eq!(history.playback("#ops", again.into_messages()), vec![]);
+    }
+
+    #[test]
+    fn test_catching_up() {
+        let mut state = state();
+        assert_eq!(
+            catch_up(&state, "#ops", None).map(wire).as_deref(),
+            Some("CHATHISTORY LATEST #ops * 20\r\n")
+        );
+        let fallback = Some(Reference::MsgId("stored".to_string()));
+        assert_eq!(
+            catch_up(&state, "#ops", fallback.clone())
+                .map(wire)
+                .as_deref(),
+            Some("CHATHISTORY LATEST #ops msgid=stored 20\r\n")
+        );
+        let live = parse_line("@msgid=x;time=2026-01-01T12:00:00.000Z :bob!b@h PRIVMSG #ops :hi");
+        state.history.seen("#ops", &live);
+        assert_eq!(
+            catch_up(&state, "#ops", fallback).map(wire).as_deref(),
+            Some("CHATHISTORY LATEST #ops msgid=x 20\r\n")
+        );
+        assert!(missed_targets(&state)
+            .map(wire)
+            .unwrap()
+            .starts_with("CHATHISTORY TARGETS timestamp=2026-01-01T12:00:00.000Z timestamp="));
+
+        let found = batch(
+            replay(
+                "BATCH +t draft/chathistory-targets
+             @batch=t CHATHISTORY TARGETS alice 2026-01-01T12:05:00.000Z
+             @batch=t CHATHISTORY TARGETS #ops 2026-01-01T12:03:00.000Z
+             BATCH -t",
+            )
+            .remove(0),
+        );
+        assert_eq!(found.kind(), TARGETS_BATCH);
+        assert_eq!(targets(&found.into_messages()), vec!["alice", "#ops"]);
+
+        assert_eq!(catch_up(&State::new("me"), "#ops", None), None);
+    }
+}
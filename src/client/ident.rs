@@ -0,0 +1,88 @@
+//! A small ident server (RFC 1413). Servers ask it who owns our connection
+//! while we register, and show our username without a `~` when it answers.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Servers give up on ident quickly, so we don't wait long for them either.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The connection to answer for, and who owns it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Connection {
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub username: String,
+}
+
+/// The answer to a `<our port>, <their port>` query.
+pub fn reply(query: &str, connection: Option<&Connection>) -> String {
+    let query = query.trim();
+    let ports: Vec<Option<u16>> = query
+        .split(',')
+        .map(|p| p.trim().parse().ok().filter(|p| *p > 0))
+        .collect();
+    let (local, remote) = match ports.as_slice() {
+        [Some(local), Some(remote)] => (*local, *remote),
+        _ => return format!("{} : ERROR : INVALID-PORT\r\n", query),
+    };
+    match connection {
+        Some(c) if (c.local_port, c.remote_port) == (local, remote) => {
+            format!("{}, {} : USERID : UNIX : {}\r\n", local, remote, c.username)
+        }
+        _ => format!("{}, {} : ERROR : NO-USER\r\n", local, remote),
+    }
+}
+
+/// Answers queries on `port` in the background, about whatever connection
+/// `connection` holds at the time.
+pub fn serve(port: u16, connection: Arc<Mutex<Option<Connection>>>) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let connection = connection.lock().unwrap().clone();
+            let _ = answer(stream, connection.as_ref());
+        }
+    });
+    Ok(())
+}
+
+fn answer(stream: TcpStream, connection: Option<&Connection>) -> io::Result<()> {
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    let mut query = String::new();
+    BufReader::new((&stream).take(1000)).read_line(&mut query)?;
+    (&stream).write_all(reply(&query, connection).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply() {
+        let connection = Connection {
+            local_port: 50123,
+            remote_port: 6697,
+            username: "jon".to_string(),
+        };
+        assert_eq!(
+            reply("50123 , 6697\r\n", Some(&connection)),
+            "50123, 6697 : USERID : UNIX : jon\r\n"
+        );
+        assert_eq!(
+            reply("50124, 6697\r\n", Some(&connection)),
+            "50124, 6697 : ERROR : NO-USER\r\n"
+        );
+        assert_eq!(
+            reply("50123, 6697\r\n", None),
+            "50123, 6697 : ERROR : NO-USER\r\n"
+        );
+        assert_eq!(
+            reply("hello\r\n", Some(&connection)),
+            "hello : ERROR : INVALID-PORT\r\n"
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! The channel list from LIST, filtered by the server where it supports
+//! ELIST and by us where it doesn't.
+//!
+//! <https://modern.ircdocs.horse/#list-message>
+//! <https://modern.ircdocs.horse/#elist-parameter>
+
+use std::cmp::Reverse;
+
+use crate::message::{format, Command, Message};
+
+const RPL_LISTSTART: &str = "321";
+const RPL_LIST: &str = "322";
+const RPL_LISTEND: &str = "323";
+
+/// A channel LIST told us about.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub channel: String,
+    pub users: usize,
+    pub topic: String,
+}
+
+/// Which channels to list, the ELIST way: `>n` and `<n` users, `C>n` and
+/// `C<n` minutes since creation, `T>n` and `T<n` minutes since the topic
+/// changed, a mask and a `!mask` to leave out.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    MoreUsers(usize),
+    FewerUsers(usize),
+    CreatedBefore(u64),
+    CreatedWithin(u64),
+    TopicBefore(u64),
+    TopicWithin(u64),
+    Mask(String),
+    NotMask(String),
+}
+
+impl Filter {
+    pub fn parse(word: &str) -> Option<Self> {
+        let minutes = |n: &str| n.parse().ok();
+        Some(match word.as_bytes() {
+            [b'>', ..] => Filter::MoreUsers(word[1..].parse().ok()?),
+            [b'<', ..] => Filter::FewerUsers(word[1..].parse().ok()?),
+            [b'C' | b'c', b'>', ..] => Filter::CreatedBefore(minutes(&word[2..])?),
+            [b'C' | b'c', b'<', ..] => Filter::CreatedWithin(minutes(&word[2..])?),
+            [b'T' | b't', b'>', ..] => Filter::TopicBefore(minutes(&word[2..])?),
+            [b'T' | b't', b'<', ..] => Filter::TopicWithin(minutes(&word[2..])?),
+            [b'!', _, ..] => Filter::NotMask(word[1..].to_string()),
+            [_, ..] => Filter::Mask(word.to_string()),
+            [] => return None,
+        })
+    }
+
+    /// The ELIST token letter saying the server can do this.
+    fn letter(&self) -> char {
+        match self {
+            Filter::MoreUsers(_) | Filter::FewerUsers(_) => 'U',
+            Filter::CreatedBefore(_) | Filter::CreatedWithin(_) => 'C',
+            Filter::TopicBefore(_) | Filter::TopicWithin(_) => 'T',
+            Filter::Mask(_) => 'M',
+            Filter::NotMask(_) => 'N',
+        }
+    }
+
+    /// How it goes in a LIST.
+    fn param(&self) -> String {
+        match self {
+            Filter::MoreUsers(n) => format!(">{}", n),
+            Filter::FewerUsers(n) => format!("<{}", n),
+            Filter::CreatedBefore(n) => format!("C>{}", n),
+            Filter::CreatedWithin(n) => format!("C<{}", n),
+            Filter::TopicBefore(n) => format!("T>{}", n),
+            Filter::TopicWithin(n) => format!("T<{}", n),
+            Filter::Mask(mask) => mask.clone(),
+            Filter::NotMask(mask) => format!("!{}", mask),
+        }
+    }
+
+    /// Whether we can apply it without the server. LIST replies don't say
+    /// when channels were created or their topics set.
+    fn is_local(&self) -> bool {
+        matches!(self.letter(), 'U' | 'M' | 'N')
+    }
+
+    /// Whether `entry` passes, as far as we can tell.
+    fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Filter::MoreUsers(n) => entry.users > *n,
+            Filter::FewerUsers(n) => entry.users < *n,
+            Filter::Mask(mask) => wildcard(mask, &entry.channel),
+            Filter::NotMask(mask) => !wildcard(mask, &entry.channel),
+            _ => true,
+        }
+    }
+}
+
+/// Whether `text` matches `mask`, where `*` is any run of characters and
+/// `?` any one, ignoring ASCII case.
+fn wildcard(mask: &str, text: &str) -> bool {
+    let mask: Vec<char> = mask.to_ascii_lowercase().chars().collect();
+    let text: Vec<char> = text.to_ascii_lowercase().chars().collect();
+    // Where to go back to when what followed the last * didn't match
+    let (mut m, mut t) = (0, 0);
+    let mut star = None;
+    while t < text.len() {
+        match mask.get(m) {
+            Some('*') => {
+                star = Some((m, t));
+                m += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                m += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_m, star_t)) => {
+                    m = star_m + 1;
+                    t = star_t + 1;
+                    star = Some((star_m, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    mask[m..].iter().all(|c| *c == '*')
+}
+
+#[derive(Debug, Default)]
+pub struct ChannelList {
+    entries: Vec<Entry>,
+    /// How many channels the server sent, filtered out or not.
+    received: usize,
+    /// Filters the server can't apply, which we apply as entries come in.
+    local: Vec<Filter>,
+    /// Whether a LIST is still coming in.
+    listing: bool,
+    /// What the last `show` listed, as indexes into `entries` in the order
+    /// shown, so they can be picked by number.
+    shown: Vec<usize>,
+}
+
+impl ChannelList {
+    /// Starts listing channels, forgetting the last list. `elist` is the
+    /// server's ELIST token. Filters neither it nor we can apply are an
+    /// error.
+    pub fn request(&mut self, filters: Vec<Filter>, elist: &str) -> Result<Message, String> {
+        let (remote, local): (Vec<Filter>, Vec<Filter>) = filters
+            .into_iter()
+            .partition(|f| elist.to_uppercase().contains(f.letter()));
+        if let Some(filter) = local.iter().find(|f| !f.is_local()) {
+            return Err(format!("The server can't filter by {}", filter.param()));
+        }
+        *self = Self {
+            local,
+            listing: true,
+            ..Self::default()
+        };
+        let params: Vec<String> = remote.iter().map(|f| f.param()).collect();
+        let params = match params.is_empty() {
+            true => vec![],
+            false => vec![params.join(",")],
+        };
+        Ok(Message::new(Command::List, vec![]).with_params(params))
+    }
+
+    /// Collects a reply to our LIST. Returns true if it was one.
+    pub fn handle(&mut self, message: &Message) -> bool {
+        if !self.listing {
+            return false;
+        }
+        let param = |i| {
+            message
+                .get_param(i)
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        };
+        match message.command().to_string().as_str() {
+            RPL_LISTSTART => {}
+            RPL_LIST => {
+                // <client> <channel> <users> :<topic>
+                self.received += 1;
+                let entry = Entry {
+                    channel: param(1),
+                    users: param(2).parse().unwrap_or(0),
+                    topic: param(3),
+                };
+                if self.local.iter().all(|f| f.matches(&entry)) {
+                    self.entries.push(entry);
+                }
+            }
+            RPL_LISTEND => self.listing = false,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_listing(&self) -> bool {
+        self.listing
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// The channels whose name or topic contain `text`, biggest first or
+    /// by name, numbered from 1. Returns `page` of them, `per_page` long,
+    /// and how many there are in all.
+    pub fn show(
+        &mut self,
+        by_name: bool,
+        text: &str,
+        page: usize,
+        per_page: usize,
+    ) -> (Vec<(usize, &Entry)>, usize) {
+        let text = text.to_lowercase();
+        let entries = &self.entries;
+        self.shown = (0..entries.len())
+            .filter(|&i| {
+                let entry = &entries[i];
+                text.is_empty()
+                    || entry.channel.to_lowercase().contains(&text)
+                    || format::strip(&entry.topic).to_lowercase().contains(&text)
+            })
+            .collect();
+        if by_name {
+            self.shown
+                .sort_by_cached_key(|&i| entries[i].channel.to_lowercase());
+        } else {
+            self.shown
+                .sort_by_key(|&i| (Reverse(entries[i].users), entries[i].channel.clone()));
+        }
+        let start = page.saturating_sub(1) * per_page;
+        let page = self
+            .shown
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(per_page)
+            .map(|(n, &i)| (n + 1, &entries[i]))
+            .collect();
+        (page, self.shown.len())
+    }
+
+    /// The channel numbered `number` in the last `show`.
+    pub fn pick(&self, number: usize) -> Option<&Entry> {
+        let i = self.shown.get(number.checked_sub(1)?)?;
+        self.entries.get(*i)
+    }
+
+    /// A LIST still coming in won't after a reconnect.
+    pub fn disconnected(&mut self) {
+        self.listing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn handle(list: &mut ChannelList, lines: &str) {
+        for line in lines.lines() {
+            let message = parse_line(line.trim());
+            assert!(list.handle(&message), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_filters() {
+        let filters: Vec<Filter> = ">10 c<60 T>5 #rust* !*-ops"
+            .split(' ')
+            .map(|w| Filter::parse(w).unwrap())
+            .collect();
+        assert_eq!(
+            filters,
+            vec![
+                Filter::MoreUsers(10),
+                Filter::CreatedWithin(60),
+                Filter::TopicBefore(5),
+                Filter::Mask("#rust*".to_string()),
+                Filter::NotMask("*-ops".to_string()),
+            ]
+        );
+        assert_eq!(Filter::parse(">ten"), None);
+
+        assert!(wildcard("#rust*", "#Rust-beginners"));
+        assert!(wildcard("*-ops", "#infra-ops"));
+        assert!(wildcard("#r?st", "#rust"));
+        assert!(wildcard("*a*b", "#aab"));
+        assert!(!wildcard("#rust", "#rusty"));
+        assert!(!wildcard("*-ops", "#ops"));
+    }
+
+    #[test]
+    fn test_request() {
+        let mut list = ChannelList::default();
+        let filters = vec![Filter::MoreUsers(10), Filter::Mask("#rust*".to_string())];
+        let request = list.request(filters.clone(), "CMNTU").unwrap();
+        assert_eq!(request.as_bytes(), b"LIST >10,#rust*\r\n");
+        assert!(list.is_listing());
+
+        // Without ELIST we filter by users and masks ourselves
+        let request = list.request(filters, "").unwrap();
+        assert_eq!(request.as_bytes(), b"LIST\r\n");
+        handle(
+            &mut list,
+            ":irc 321 me Channel :Users Name
+             :irc 322 me #rust 120 :The Rust language
+             :irc 322 me #rust-ops 4 :
+             :irc 322 me #go 300 :Go
+             :irc 323 me :End of /LIST",
+        );
+        assert!(!list.is_listing());
+        assert_eq!((list.len(), list.received()), (1, 3));
+        let stray = parse_line(":irc 322 me #late 1 :");
+        assert!(!list.handle(&stray));
+
+        assert_eq!(
+            list.request(vec![Filter::TopicWithin(5)], "MNU"),
+            Err("The server can't filter by T<5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_show() {
+        let mut list = ChannelList::default();
+        list.request(vec![], "").unwrap();
+        handle(
+            &mut list,
+            ":irc 322 me #b 5 :Bees
+             :irc 322 me #a 5 :\u{2}Ants\u{2} and bees
+             :irc 322 me #c 50 :Cats
+             :irc 323 me :End of /LIST",
+        );
+        let channels = |shown: Vec<(usize, &Entry)>| -> Vec<(usize, String)> {
+            shown
+                .into_iter()
+                .map(|(n, e)| (n, e.channel.clone()))
+                .collect()
+        };
+        let (shown, total) = list.show(false, "", 1, 2);
+        assert_eq!(total, 3);
+        assert_eq!(
+            channels(shown),
+            vec![(1, "#c".to_string()), (2, "#a".to_string())]
+        );
+        let (shown, _) = list.show(false, "", 2, 2);
+        assert_eq!(channels(shown), vec![(3, "#b".to_string())]);
+
+        let (shown, total) = list.show(true, "ANTS AND", 1, 10);
+        assert_eq!((channels(shown), total), (vec![(1, "#a".to_string())], 1));
+        assert_eq!(list.pick(1).unwrap().channel, "#a");
+        assert_eq!(list.pick(2), None);
+        assert_eq!(list.pick(0), None);
+    }
+}
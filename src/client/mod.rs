@@ -1,40 +1,487 @@
+use crate::config::Settings;
+use crate::message::ctcp::Ctcp;
+use crate::message::dcc::Dcc;
+use crate::message::split;
 use crate::message::{Command, Message};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+pub mod batch;
+pub mod caps;
+pub mod connection;
+pub mod ctcp;
+pub mod dcc;
+pub mod history;
+pub mod ident;
+pub mod list;
+pub mod modes;
+pub mod notify;
+pub mod pending;
+pub mod queue;
+pub mod scrollback;
+pub mod snotices;
+pub mod state;
+pub mod stream;
+pub mod typing;
+pub mod url;
+pub mod whois;
+
+use scrollback::{Said, REPLY_TAG};
+use state::State;
+
+/// Everything the UI gets told about.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Message(Message),
+    Connection(connection::Event),
+    /// How many messages are waiting to be sent to the server.
+    Queued(usize),
+    Dcc(dcc::Event),
+    /// Messages fetched from the server's history of a buffer, that we
+    /// hadn't seen yet.
+    History(String, Vec<Message>),
+    Netsplit(batch::Netsplit),
+    /// How many messages the server hasn't echoed back yet.
+    Pending(usize),
+    /// A message the server wouldn't send, and why.
+    NotSent(pending::Sent, String),
+    /// Someone on the notify list came online or went offline.
+    Presence(notify::Change),
+    /// Who on the notify list is online.
+    Online(Vec<String>),
+    /// Who's typing in a buffer changed.
+    Typing(String),
+    /// How many channels a LIST has found so far, and whether it's done.
+    List(usize, bool),
+    /// A channel's ban, exception, invite or quiet list.
+    ModeList(modes::ModeList),
+    /// The whole answer to a WHOIS, WHOWAS or WHO.
+    Lookup(whois::Lookup),
+    /// A server notice or wallops that passes the filter.
+    ServerNotice(snotices::Notice),
+    /// Our user modes changed.
+    UserModes(String),
+    /// Something that went wrong on our side, like a log that can't be
+    /// written.
+    Warning(String),
+}
+
+#[derive(Clone)]
 pub struct Client {
-    sender: Arc<Mutex<Sender<Message>>>,
-    receiver: Arc<Mutex<Receiver<Message>>>,
+    sender: Arc<Mutex<Sender<Event>>>,
+    receiver: Arc<Mutex<Receiver<Event>>>,
+    state: Arc<Mutex<State>>,
+    dcc: Arc<Mutex<dcc::Manager>>,
 }
 
 impl Client {
-    pub fn new(server: &str, channel_name: &str, nick: &str) -> Self {
+    pub fn new(nick: &str) -> Self {
         let (rx, tx) = channel();
         let sender = Arc::new(Mutex::new(rx));
         let receiver = Arc::new(Mutex::new(tx));
+        let state = Arc::new(Mutex::new(State::new(nick)));
+        let dcc = Arc::new(Mutex::new(dcc::Manager::new(
+            sender.lock().unwrap().clone(),
+        )));
 
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            state,
+            dcc,
+        }
     }
 
-    pub fn sender(&self) -> Arc<Mutex<Sender<Message>>> {
+    pub fn sender(&self) -> Arc<Mutex<Sender<Event>>> {
         self.sender.clone()
     }
 
-    pub fn receiver(&self) -> Arc<Mutex<Receiver<Message>>> {
+    pub fn receiver(&self) -> Arc<Mutex<Receiver<Event>>> {
         self.receiver.clone()
     }
+
+    pub fn state(&self) -> Arc<Mutex<State>> {
+        self.state.clone()
+    }
+
+    pub fn dcc(&self) -> Arc<Mutex<dcc::Manager>> {
+        self.dcc.clone()
+    }
 }
 
-pub fn register(nick: &str) -> Vec<Message> {
-    vec![
+/// Registers as `nick`. Capability negotiation keeps registration waiting
+/// until CAP END.
+pub fn register(settings: &Settings, nick: &str) -> Vec<Message> {
+    let mut messages = Vec::new();
+    // The gateway has to vouch for the user before anything else is sent
+    if let Some(webirc) = &settings.webirc {
+        messages.push(Message::new(
+            Command::WebIrc,
+            vec![
+                &webirc.password,
+                &webirc.gateway,
+                &webirc.hostname,
+                &webirc.ip,
+            ],
+        ));
+    }
+    if let Some(password) = &settings.password {
+        messages.push(Message::new(Command::Pass, vec![password]));
+    }
+    // An empty realname would leave USER a param short
+    let realname = match settings.realname.as_str() {
+        "" => nick,
+        realname => realname,
+    };
+    messages.extend(vec![
         Message::new(Command::Cap, vec!["LS", "302"]),
         Message::new(Command::Nick, vec![nick]),
-        Message::new(Command::User, vec![&format!("{} 0 * :Developer", nick)]),
-        Message::new(Command::Cap, vec!["END"]),
-    ]
+        Message::new(Command::User, vec![&settings.username, "0", "*", realname]),
+    ]);
+    messages
+}
+
+/// Servers cut lines off after this many bytes, CRLF included.
+const MAX_LINE: usize = 512;
+
+/// Sends `text` to `target`, split into as many messages as it takes. Where
+/// the server supports `draft/multiline` the pieces go in a batch so they
+/// show up as one message.
+pub fn priv_msg(state: &State, target: &str, text: &str) -> Vec<Message> {
+    let max = max_text_len(state, &Command::PrivMsg, target);
+    if text.len() <= max {
+        return vec![Message::priv_msg(target.to_string(), text.to_string())];
+    }
+    if let Some(batch) = multiline(state, target, text, max) {
+        return batch;
+    }
+    split::lines(text, max)
+        .into_iter()
+        .map(|t| Message::priv_msg(target.to_string(), t))
+        .collect()
+}
+
+/// Sends `text` to `target` as a reply to `parent`. Servers without
+/// message tags get a plain `nick: text` instead.
+pub fn reply(state: &State, target: &str, text: &str, parent: &Said) -> Vec<Message> {
+    if !state.caps.is_enabled("message-tags") {
+        return priv_msg(state, target, &format!("{}: {}", parent.nick, text));
+    }
+    // The tag goes on the first message, which is the batch for multiline
+    priv_msg(state, target, text)
+        .into_iter()
+        .enumerate()
+        .map(|(i, message)| match i {
+            0 => message.with_tag(REPLY_TAG, Some(&parent.msgid)),
+            _ => message,
+        })
+        .collect()
+}
+
+/// Sends a `/me` action, split like `priv_msg` but without a batch.
+pub fn action(state: &State, target: &str, text: &str) -> Vec<Message> {
+    // Room for the \u{1}ACTION and \u{1} around the text
+    let max = max_text_len(state, &Command::PrivMsg, target).saturating_sub(9);
+    split::lines(text, max)
+        .iter()
+        .map(|t| Message::action(target, t))
+        .collect()
+}
+
+/// Sends a CTCP reply, split like `action` when its params don't fit.
+pub fn ctcp_reply(state: &State, target: &str, ctcp: &Ctcp) -> Vec<Message> {
+    let params = match &ctcp.params {
+        Some(params) => params,
+        None => return vec![Message::ctcp_reply(target, ctcp)],
+    };
+    // Room for the \u{1}, command and space before the params and \u{1} after
+    let max = max_text_len(state, &Command::Notice, target).saturating_sub(ctcp.command.len() + 3);
+    split::lines(params, max)
+        .iter()
+        .map(|p| Message::ctcp_reply(target, &Ctcp::new(&ctcp.command, Some(p))))
+        .collect()
+}
+
+/// How much text fits in `:<prefix> <command> <target> :<text>\r\n`.
+fn max_text_len(state: &State, command: &Command, target: &str) -> usize {
+    let overhead = state.prefix_len() + command.to_string().len() + target.len() + 7;
+    MAX_LINE.saturating_sub(overhead).max(1)
+}
+
+fn multiline(state: &State, target: &str, text: &str, max: usize) -> Option<Vec<Message>> {
+    if !state.caps.is_enabled("draft/multiline") {
+        return None;
+    }
+    // The value looks like max-bytes=4096,max-lines=24
+    let limits = state.caps.value("draft/multiline").unwrap_or_default();
+    let limit = |name: &str| {
+        limits.split(',').find_map(|l| {
+            l.strip_prefix(name)?
+                .strip_prefix('=')?
+                .parse::<usize>()
+                .ok()
+        })
+    };
+    let lines = split::chunks(text, max);
+    if text.len() > limit("max-bytes")? || lines.len() > limit("max-lines").unwrap_or(usize::MAX) {
+        return None;
+    }
+
+    let reference = batch_reference();
+    let start = format!("+{}", reference);
+    let mut messages = vec![Message::new(
+        Command::Batch,
+        vec![&start, "draft/multiline", target],
+    )];
+    for (i, line) in lines.iter().enumerate() {
+        let message = Message::priv_msg(target.to_string(), line.to_string())
+            .with_tag("batch", Some(&reference));
+        messages.push(match i {
+            0 => message,
+            _ => message.with_tag("draft/multiline-concat", None),
+        });
+    }
+    messages.push(Message::new(
+        Command::Batch,
+        vec![&format!("-{}", reference)],
+    ));
+    Some(messages)
+}
+
+fn batch_reference() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("{:x}", nanos)
+}
+
+/// Channel names without a prefix are treated as `#` channels.
+pub fn channel_name(name: &str) -> String {
+    if name.starts_with(state::CHANNEL_PREFIXES) {
+        name.to_string()
+    } else {
+        format!("#{}", name)
+    }
 }
 
 pub fn join(channel_name: &str) -> Vec<Message> {
-    let channel_name = format!("#{}", channel_name);
+    let channel_name = self::channel_name(channel_name);
     vec![Message::new(Command::Join, vec![&channel_name])]
 }
+
+pub fn join_with_key(channel_name: &str, key: &str) -> Vec<Message> {
+    let channel_name = self::channel_name(channel_name);
+    vec![Message::new(Command::Join, vec![&channel_name, key])]
+}
+
+/// Joins every channel in `state` again, with the keys they were joined with.
+pub fn rejoin(state: &State) -> Vec<Message> {
+    state
+        .channels
+        .iter()
+        .flat_map(|(name, channel)| match &channel.key {
+            Some(key) => join_with_key(name, key),
+            None => join(name),
+        })
+        .collect()
+}
+
+pub fn part(channel_name: &str, reason: Option<&str>) -> Vec<Message> {
+    let mut params = vec![channel_name];
+    params.extend(reason);
+    vec![Message::new(Command::Part, params)]
+}
+
+pub fn quit(reason: Option<&str>) -> Vec<Message> {
+    vec![Message::new(Command::Quit, reason.into_iter().collect())]
+}
+
+pub fn nick(nick: &str) -> Vec<Message> {
+    vec![Message::new(Command::Nick, vec![nick])]
+}
+
+/// Answers the CTCP requests and DCC offers in a PRIVMSG. Our own come back
+/// with echo-message, and aren't for us to answer.
+pub fn answer(
+    state: &State,
+    responder: &mut ctcp::Responder,
+    manager: &mut dcc::Manager,
+    message: &Message,
+    now: Instant,
+) -> Vec<Message> {
+    let source = match message.source() {
+        Some(source) if source.nick != state.nick => source,
+        _ => return vec![],
+    };
+    let mut messages = match responder.reply(message, now) {
+        Some((nick, reply)) => ctcp_reply(state, &nick, &reply),
+        None => vec![],
+    };
+    if let Some(dcc) = message.ctcp().and_then(|c| Dcc::parse(&c)) {
+        messages.extend(manager.handle(&source.nick, dcc));
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebIrc;
+    use crate::message::parse_line;
+
+    fn state() -> State {
+        let mut state = State::new("me");
+        let join = parse_line(":me!me@example.com JOIN #rust");
+        state.update(&join);
+        state
+    }
+
+    fn wire(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| String::from_utf8(m.as_bytes()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_register() {
+        let mut settings = Settings::new("irc.example.com");
+        settings.username = "jonk".to_string();
+        settings.realname = "Jon Grimes".to_string();
+        settings.password = Some("sesame".to_string());
+        settings.webirc = Some(WebIrc {
+            password: "pw".to_string(),
+            gateway: "kiwi".to_string(),
+            hostname: "host.example".to_string(),
+            ip: "2001:db8::1".to_string(),
+        });
+        assert_eq!(
+            wire(&register(&settings, "jon")),
+            vec![
+                "WEBIRC pw kiwi host.example 2001:db8::1\r\n",
+                "PASS sesame\r\n",
+                "CAP LS 302\r\n",
+                "NICK jon\r\n",
+                "USER jonk 0 * :Jon Grimes\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_splits_long_messages() {
+        let state = state();
+        let text = "word ".repeat(150);
+        let messages = priv_msg(&state, "#rust", text.trim_end());
+        assert_eq!(messages.len(), 2);
+        for line in wire(&messages) {
+            // What the server relays, prefix included
+            let relayed = format!(":me!me@example.com {}", line);
+            assert!(relayed.len() <= MAX_LINE, "{} bytes", relayed.len());
+            assert!(line.ends_with("word\r\n"));
+        }
+        assert_eq!(priv_msg(&state, "#rust", "short").len(), 1);
+    }
+
+    #[test]
+    fn test_splits_ctcp_replies() {
+        let state = state();
+        let ping = Ctcp::new("PING", Some(&"pong ".repeat(200)));
+        let replies = ctcp_reply(&state, "alice", &ping);
+        assert_eq!(replies.len(), 3);
+        for reply in &replies {
+            assert!(reply.as_bytes().len() + state.prefix_len() + 2 <= MAX_LINE);
+            assert_eq!(reply.command(), &Command::Notice);
+            assert_eq!(reply.ctcp().unwrap().command, "PING");
+        }
+        let version = Ctcp::new("VERSION", Some("yairc"));
+        assert_eq!(
+            wire(&ctcp_reply(&state, "alice", &version)),
+            vec!["NOTICE alice :\u{1}VERSION yairc\u{1}\r\n"]
+        );
+    }
+
+    #[test]
+    fn test_multiline_batch() {
+        let mut state = state();
+        for raw in &[
+            ":irc CAP * LS :draft/multiline=max-bytes=4096,max-lines=8",
+            ":irc CAP me ACK :draft/multiline",
+        ] {
+            state.caps.handle(&parse_line(raw));
+        }
+
+        let text = "word ".repeat(150);
+        let lines = wire(&priv_msg(&state, "#rust", &text));
+        assert_eq!(lines.len(), 4);
+        let reference = lines[0].split(' ').nth(1).unwrap()[1..].to_string();
+        assert_eq!(
+            lines[0],
+            format!("BATCH +{} draft/multiline #rust\r\n", reference)
+        );
+        assert!(lines[1].starts_with(&format!("@batch={} PRIVMSG #rust :word ", reference)));
+        assert!(lines[2].starts_with(&format!(
+            "@batch={};draft/multiline-concat PRIVMSG",
+            reference
+        )));
+        assert_eq!(lines[3], format!("BATCH -{}\r\n", reference));
+    }
+
+    #[test]
+    fn test_answer() {
+        let state = state();
+        let mut responder = ctcp::Responder::default();
+        let (events, received) = channel();
+        let mut manager = dcc::Manager::new(events);
+        let now = Instant::now();
+        let privmsg = |source: &str, ctcp: &str| {
+            parse_line(&format!(":{} PRIVMSG alice :\u{1}{}\u{1}", source, ctcp))
+        };
+
+        // Our own, echoed back
+        let version = privmsg("me!me@example.com", "VERSION");
+        let offer = privmsg("me!me@example.com", "DCC SEND notes.txt 2130706433 5000 10");
+        assert!(answer(&state, &mut responder, &mut manager, &version, now).is_empty());
+        assert!(answer(&state, &mut responder, &mut manager, &offer, now).is_empty());
+        assert!(received.try_recv().is_err());
+
+        let version = privmsg("alice!a@h", "VERSION");
+        let offer = privmsg("alice!a@h", "DCC SEND notes.txt 2130706433 5000 10");
+        assert_eq!(
+            answer(&state, &mut responder, &mut manager, &version, now).len(),
+            1
+        );
+        answer(&state, &mut responder, &mut manager, &offer, now);
+        assert!(matches!(
+            received.try_recv(),
+            Ok(Event::Dcc(dcc::Event::Offer { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_reply() {
+        let mut state = state();
+        let parent = Said {
+            msgid: "abc".to_string(),
+            buffer: "#rust".to_string(),
+            nick: "alice".to_string(),
+            text: "anyone around?".to_string(),
+            reply_to: None,
+        };
+        assert_eq!(
+            wire(&reply(&state, "#rust", "yes", &parent)),
+            vec!["PRIVMSG #rust :alice: yes\r\n"]
+        );
+        for raw in &[
+            ":irc CAP * LS :message-tags",
+            ":irc CAP me ACK :message-tags",
+        ] {
+            state.caps.handle(&parse_line(raw));
+        }
+        assert_eq!(
+            wire(&reply(&state, "#rust", "yes", &parent)),
+            vec!["@+draft/reply=abc PRIVMSG #rust yes\r\n"]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Channel modes: reading mode strings the way the server's CHANMODES and
+//! PREFIX say to, sending changes a few at a time, and the ban, exception
+//! and invite lists.
+//!
+//! <https://modern.ircdocs.horse/#channel-modes>
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::client::state::State;
+use crate::message::{Command, Message};
+
+/// What RFC 2811 servers have, for servers that don't say.
+const CHANMODES: &str = "beI,k,l,imnpst";
+const PREFIX: &str = "(ov)@+";
+const MODES: usize = 3;
+
+const RPL_INVITELIST: &str = "346";
+const RPL_ENDOFINVITELIST: &str = "347";
+const RPL_EXCEPTLIST: &str = "348";
+const RPL_ENDOFEXCEPTLIST: &str = "349";
+const RPL_BANLIST: &str = "367";
+const RPL_ENDOFBANLIST: &str = "368";
+const RPL_QUIETLIST: &str = "728";
+const RPL_ENDOFQUIETLIST: &str = "729";
+
+/// One mode set or unset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub adding: bool,
+    pub mode: char,
+    pub param: Option<String>,
+}
+
+impl Change {
+    pub fn add(mode: char, param: &str) -> Self {
+        Self {
+            adding: true,
+            mode,
+            param: Some(param.to_string()),
+        }
+    }
+
+    pub fn remove(mode: char, param: &str) -> Self {
+        Self {
+            adding: false,
+            ..Self::add(mode, param)
+        }
+    }
+}
+
+/// Which channel modes take a parameter, from the server's ISUPPORT.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeTypes {
+    /// Type A, lists like bans that always take a mask, and without one
+    /// ask for the list.
+    lists: String,
+    /// Type B, always with a parameter, like the key.
+    always: String,
+    /// Type C, with a parameter only when set, like the limit.
+    when_set: String,
+    /// Modes giving someone a status in the channel, with their prefix,
+    /// highest first.
+    prefixes: Vec<(char, char)>,
+    /// How many modes with a parameter fit in one MODE.
+    max: usize,
+}
+
+impl ModeTypes {
+    pub fn new(state: &State) -> Self {
+        let chanmodes = state.isupport("CHANMODES").unwrap_or(CHANMODES);
+        let mut types = chanmodes.split(',').map(|t| t.to_string());
+        let mut next = || types.next().unwrap_or_default();
+        let (lists, always, when_set) = (next(), next(), next());
+
+        // (ov)@+, the modes and the prefixes they give in the same order
+        let prefix = state.isupport("PREFIX").unwrap_or(PREFIX);
+        let prefixes = match prefix.strip_prefix('(').and_then(|p| p.split_once(')')) {
+            Some((modes, prefixes)) => modes.chars().zip(prefixes.chars()).collect(),
+            None => Vec::new(),
+        };
+        // MODES without a value means there's no limit
+        let max = match state.isupport("MODES") {
+            Some("") => usize::MAX,
+            Some(max) => max.parse().unwrap_or(MODES),
+            None => MODES,
+        };
+        Self {
+            lists,
+            always,
+            when_set,
+            prefixes,
+            max,
+        }
+    }
+
+    pub fn is_list(&self, mode: char) -> bool {
+        self.lists.contains(mode)
+    }
+
+    pub fn is_prefix(&self, mode: char) -> bool {
+        self.prefixes.iter().any(|(m, _)| *m == mode)
+    }
+
+    fn takes_param(&self, mode: char, adding: bool) -> bool {
+        self.is_list(mode)
+            || self.is_prefix(mode)
+            || self.always.contains(mode)
+            || (adding && self.when_set.contains(mode))
+    }
+
+    /// Splits a mode string and its parameters, like `+ov-b alice bob
+    /// *!*@x`, into one change each. A list mode without a parameter is
+    /// a request for the list.
+    pub fn parse(&self, modes: &str, params: &[String]) -> Vec<Change> {
+        let mut params = params.iter();
+        let mut adding = true;
+        let mut changes = Vec::new();
+        for mode in modes.chars() {
+            match mode {
+                '+' => adding = true,
+                '-' => adding = false,
+                mode => {
+                    let param = match self.takes_param(mode, adding) {
+                        true => params.next().cloned(),
+                        false => None,
+                    };
+                    changes.push(Change {
+                        adding,
+                        mode,
+                        param,
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// MODE messages making `changes` to `channel`, as many to a message as
+    /// the server allows.
+    pub fn messages(&self, channel: &str, changes: &[Change]) -> Vec<Message> {
+        let mut messages = Vec::new();
+        let mut rest = changes;
+        while !rest.is_empty() {
+            // Only changes with a parameter count towards the limit
+            let mut with_param = 0;
+            let len = rest
+                .iter()
+                .take_while(|c| {
+                    with_param += c.param.is_some() as usize;
+                    with_param <= self.max.max(1)
+                })
+                .count();
+            let (batch, next) = rest.split_at(len);
+            messages.push(mode_message(channel, batch));
+            rest = next;
+        }
+        messages
+    }
+
+    /// How to quiet `mask`: the `q` list on servers like Solanum, or an
+    /// extban on ones like InspIRCd.
+    pub fn quiet(&self, state: &State, mask: &str) -> Option<Change> {
+        if self.is_list('q') {
+            return Some(Change::add('q', mask));
+        }
+        // EXTBAN=<prefix>,<types>
+        let (prefix, types) = state.isupport("EXTBAN")?.split_once(',')?;
+        match types.contains('m') {
+            true => Some(Change::add('b', &format!("{}m:{}", prefix, mask))),
+            false => None,
+        }
+    }
+}
+
+fn mode_message(channel: &str, changes: &[Change]) -> Message {
+    let mut modes = String::new();
+    let mut adding = None;
+    for change in changes {
+        if adding != Some(change.adding) {
+            modes.push(if change.adding { '+' } else { '-' });
+            adding = Some(change.adding);
+        }
+        modes.push(change.mode);
+    }
+    let mut params = vec![channel.to_string(), modes];
+    params.extend(changes.iter().filter_map(|c| c.param.clone()));
+    Message::new(Command::Mode, vec![]).with_params(params)
+}
+
+/// The mask to ban someone with: as given if it's already a mask, by
+/// their host if we know it, otherwise by nick.
+pub fn ban_mask(state: &State, target: &str) -> String {
+    if target.contains(['!', '@', ':']) {
+        return target.to_string();
+    }
+    match state.user(target).and_then(|u| u.host.as_ref()) {
+        Some(host) => format!("*!*@{}", host),
+        None => format!("{}!*@*", target),
+    }
+}
+
+/// A mask on a ban, exception, invite or quiet list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListEntry {
+    pub mask: String,
+    pub set_by: Option<String>,
+    pub set_at: Option<DateTime<Utc>>,
+}
+
+/// A whole list, once the server's sent it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeList {
+    pub channel: String,
+    /// `b`, `e`, `I` or `q`.
+    pub mode: char,
+    pub entries: Vec<ListEntry>,
+}
+
+/// Whether `message` is part of a ban, exception, invite or quiet list,
+/// which are shown once they're complete.
+pub fn is_list_reply(message: &Message) -> bool {
+    matches!(
+        message.command().to_string().as_str(),
+        RPL_BANLIST
+            | RPL_ENDOFBANLIST
+            | RPL_EXCEPTLIST
+            | RPL_ENDOFEXCEPTLIST
+            | RPL_INVITELIST
+            | RPL_ENDOFINVITELIST
+            | RPL_QUIETLIST
+            | RPL_ENDOFQUIETLIST
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct ModeLists {
+    /// Lists coming in and the last of each we got, by lowercased channel
+    /// and mode.
+    lists: BTreeMap<(String, char), Vec<ListEntry>>,
+    /// Which lists are still coming in.
+    incoming: Vec<(String, char)>,
+}
+
+impl ModeLists {
+    /// Collects a list entry or the end of a list. Returns the list when
+    /// it's ended.
+    pub fn handle(&mut self, message: &Message) -> Option<ModeList> {
+        let params = message.params();
+        let param = |i: usize| params.get(i).cloned();
+        // <client> <channel> <mask> [<who> <set-ts>], the quiet list with
+        // a q before the mask
+        let (mode, entry, end) = match message.command().to_string().as_str() {
+            RPL_BANLIST => ('b', 2, false),
+            RPL_ENDOFBANLIST => ('b', 0, true),
+            RPL_EXCEPTLIST => ('e', 2, false),
+            RPL_ENDOFEXCEPTLIST => ('e', 0, true),
+            RPL_INVITELIST => ('I', 2, false),
+            RPL_ENDOFINVITELIST => ('I', 0, true),
+            RPL_QUIETLIST => ('q', 3, false),
+            RPL_ENDOFQUIETLIST => ('q', 0, true),
+            _ => return None,
+        };
+        let channel = param(1)?;
+        let key = (channel.to_lowercase(), mode);
+        if !self.incoming.contains(&key) {
+            self.incoming.push(key.clone());
+            self.lists.insert(key.clone(), Vec::new());
+        }
+        let list = self.lists.get_mut(&key)?;
+        if !end {
+            let set_at = param(entry + 2).and_then(|t| t.parse().ok());
+            list.push(ListEntry {
+                mask: param(entry)?,
+                set_by: param(entry + 1),
+                set_at: set_at.and_then(|t| Utc.timestamp_opt(t, 0).single()),
+            });
+            return None;
+        }
+        self.incoming.retain(|k| *k != key);
+        Some(ModeList {
+            channel,
+            mode,
+            entries: list.clone(),
+        })
+    }
+
+    /// The mask numbered `number` in the last list we got for `channel`.
+    pub fn get(&self, channel: &str, mode: char, number: usize) -> Option<&ListEntry> {
+        let list = self.lists.get(&(channel.to_lowercase(), mode))?;
+        list.get(number.checked_sub(1)?)
+    }
+
+    pub fn disconnected(&mut self) {
+        self.incoming.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn state(isupport: &str) -> State {
+        let mut state = State::new("me");
+        state.update(&parse_line(&format!(
+            ":irc 005 me {} :are supported",
+            isupport
+        )));
+        state
+    }
+
+    fn strings(words: &str) -> Vec<String> {
+        words.split_whitespace().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let types = ModeTypes::new(&state(
+            "CHANMODES=beIq,k,fl,imnpst PREFIX=(qaohv)~&@%+ MODES=4",
+        ));
+        let changes = types.parse("+ov-b+lk-lm", &strings("alice bob *!*@x 10 secret"));
+        assert_eq!(
+            changes,
+            vec![
+                Change::add('o', "alice"),
+                Change::add('v', "bob"),
+                Change::remove('b', "*!*@x"),
+                Change::add('l', "10"),
+                Change::add('k', "secret"),
+                Change {
+                    adding: false,
+                    mode: 'l',
+                    param: None
+                },
+                Change {
+                    adding: false,
+                    mode: 'm',
+                    param: None
+                },
+            ]
+        );
+        // Asking for the ban list
+        assert_eq!(types.parse("+b", &[])[0].param, None);
+        assert!(types.is_prefix('q') && types.is_list('q'));
+    }
+
+    #[test]
+    fn test_messages() {
+        let types = ModeTypes::new(&state("MODES=2"));
+        let changes = vec![
+            Change::add('o', "alice"),
+            Change::add('o', "bob"),
+            Change::remove('v', "carol"),
+            Change {
+                adding: true,
+                mode: 'm',
+                param: None,
+            },
+            Change::remove('b', "*!*@x"),
+        ];
+        let wire: Vec<String> = types
+            .messages("#rust", &changes)
+            .iter()
+            .map(|m| String::from_utf8(m.as_bytes()).unwrap())
+            .collect();
+        assert_eq!(
+            wire,
+            vec![
+                "MODE #rust +oo alice bob\r\n",
+                "MODE #rust -v+m-b carol *!*@x\r\n",
+            ]
+        );
+        // Defaults without ISUPPORT
+        let types = ModeTypes::new(&State::new("me"));
+        assert_eq!(types.messages("#rust", &changes).len(), 2);
+    }
+
+    #[test]
+    fn test_masks() {
+        let mut state = state("EXTBAN=,ACOQRSTUacjmnprswz");
+        let join = parse_line(":me!u@h JOIN #rust");
+        state.update(&join);
+        let join = parse_line(":alice!a@alice.example JOIN #rust");
+        state.update(&join);
+        assert_eq!(ban_mask(&state, "alice"), "*!*@alice.example");
+        assert_eq!(ban_mask(&state, "bob"), "bob!*@*");
+        assert_eq!(ban_mask(&state, "*!*@spam.example"), "*!*@spam.example");
+
+        let types = ModeTypes::new(&state);
+        assert_eq!(
+            types.quiet(&state, "bob!*@*"),
+            Some(Change::add('b', "m:bob!*@*"))
+        );
+        let solanum = self::state("CHANMODES=eIbq,k,flj,CFLMPQScgimnprstuz");
+        let types = ModeTypes::new(&solanum);
+        assert_eq!(
+            types.quiet(&solanum, "bob!*@*"),
+            Some(Change::add('q', "bob!*@*"))
+        );
+        let plain = State::new("me");
+        assert_eq!(ModeTypes::new(&plain).quiet(&plain, "bob!*@*"), None);
+    }
+
+    #[test]
+    fn test_lists() {
+        let mut lists = ModeLists::default();
+        let mut handle = |raw: &str| lists.handle(&parse_line(raw));
+        assert_eq!(
+            handle(":irc 367 me #rust *!*@spam.example alice 1760000000"),
+            None
+        );
+        assert_eq!(handle(":irc 367 me #rust bob!*@*"), None);
+        assert_eq!(
+            handle(":irc 728 me #rust q troll!*@* alice 1760000000"),
+            None
+        );
+        let bans = handle(":irc 368 me #rust :End of channel ban list").unwrap();
+        assert_eq!(bans.mode, 'b');
+        assert_eq!(bans.entries.len(), 2);
+        assert_eq!(bans.entries[0].set_by.as_deref(), Some("alice"));
+        assert_eq!(bans.entries[0].set_at.unwrap().timestamp(), 1760000000);
+        assert_eq!(bans.entries[1].set_by, None);
+        let quiets = handle(":irc 729 me #rust q :End of quiet list").unwrap();
+        assert_eq!(quiets.entries[0].mask, "troll!*@*");
+
+        // An empty list
+        let excepts = handle(":irc 349 me #rust :End of exception list").unwrap();
+        assert!(excepts.entries.is_empty());
+
+        assert_eq!(lists.get("#Rust", 'b', 2).unwrap().mask, "bob!*@*");
+        assert_eq!(lists.get("#rust", 'b', 3), None);
+
+        // A new list replaces the old one
+        lists.handle(&parse_line(":irc 367 me #rust new!*@*"));
+        lists.handle(&parse_line(":irc 368 me #rust :End"));
+        assert_eq!(lists.get("#rust", 'b', 1).unwrap().mask, "new!*@*");
+        assert_eq!(lists.get("#rust", 'b', 2), None);
+    }
+}
@@ -0,0 +1,480 @@
+//! Telling us when people on the notify list come online or go offline.
+//!
+//! MONITOR is the way to ask. Servers without it may have WATCH instead,
+//! and failing both we ask with ISON every so often. ISON also covers
+//! whoever doesn't fit in the server's MONITOR list.
+//!
+//! <https://ircv3.net/specs/extensions/monitor>
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+use crate::client::state::State;
+use crate::message::{Command, Message};
+
+/// How often ISON asks who's online.
+const ISON_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Nicks are sent in lines of at most this many bytes.
+const LINE_LEN: usize = 400;
+
+const RPL_ISON: &str = "303";
+const RPL_LOGON: &str = "600";
+const RPL_LOGOFF: &str = "601";
+const RPL_NOWON: &str = "604";
+const RPL_NOWOFF: &str = "605";
+const RPL_MONONLINE: &str = "730";
+const RPL_MONOFFLINE: &str = "731";
+const ERR_MONLISTFULL: &str = "734";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Method {
+    Monitor,
+    Watch,
+    Ison,
+}
+
+/// Someone on the list coming online or going offline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub nick: String,
+    pub online: bool,
+    /// Their `user@host`, when the server says.
+    pub userhost: Option<String>,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.online, &self.userhost) {
+            (true, Some(userhost)) => write!(f, "{} is online ({})", self.nick, userhost),
+            (true, None) => write!(f, "{} is online", self.nick),
+            (false, _) => write!(f, "{} went offline", self.nick),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NotifyList {
+    /// Everyone on the list by lowercased nick, with the nick as given and
+    /// whether they're online once we know.
+    nicks: BTreeMap<String, (String, Option<bool>)>,
+    /// How we're asking, once we've started.
+    method: Option<Method>,
+    /// How many nicks the server will MONITOR for us, if it says.
+    limit: Option<usize>,
+    /// The lowercased nicks the server is monitoring. The rest are asked
+    /// about with ISON.
+    monitored: BTreeSet<String>,
+    /// The nicks in each ISON the server hasn't answered yet.
+    asked: VecDeque<Vec<String>>,
+    next_poll: Option<Instant>,
+}
+
+impl NotifyList {
+    pub fn new(nicks: &[String]) -> Self {
+        let mut list = Self::default();
+        for nick in nicks {
+            list.nicks.insert(nick.to_lowercase(), (nick.clone(), None));
+        }
+        list
+    }
+
+    pub fn contains(&self, nick: &str) -> bool {
+        self.nicks.contains_key(&nick.to_lowercase())
+    }
+
+    /// Everyone on the list, with whether they're online if we know.
+    pub fn nicks(&self) -> Vec<(&str, Option<bool>)> {
+        self.nicks
+            .values()
+            .map(|(nick, online)| (nick.as_str(), *online))
+            .collect()
+    }
+
+    pub fn online(&self) -> Vec<String> {
+        self.nicks
+            .values()
+            .filter(|(_, online)| *online == Some(true))
+            .map(|(nick, _)| nick.clone())
+            .collect()
+    }
+
+    /// Adds `nick`, returning what asks the server about them.
+    pub fn add(&mut self, nick: &str) -> Vec<Message> {
+        if self.contains(nick) {
+            return Vec::new();
+        }
+        self.nicks
+            .insert(nick.to_lowercase(), (nick.to_string(), None));
+        match self.method {
+            // Over the limit, the next poll asks about them
+            Some(Method::Monitor) if self.is_full() => Vec::new(),
+            Some(Method::Monitor) => {
+                self.monitored.insert(nick.to_lowercase());
+                vec![Message::new(Command::Monitor, vec!["+", nick])]
+            }
+            Some(Method::Watch) => vec![Message::new(Command::Watch, vec![&format!("+{}", nick)])],
+            Some(Method::Ison) => self.ison(vec![nick.to_string()]),
+            None => Vec::new(),
+        }
+    }
+
+    /// Removes `nick`, returning what stops the server telling us about
+    /// them.
+    pub fn remove(&mut self, nick: &str) -> Vec<Message> {
+        if self.nicks.remove(&nick.to_lowercase()).is_none() {
+            return Vec::new();
+        }
+        match self.method {
+            Some(Method::Monitor) if self.monitored.remove(&nick.to_lowercase()) => {
+                vec![Message::new(Command::Monitor, vec!["-", nick])]
+            }
+            Some(Method::Watch) => vec![Message::new(Command::Watch, vec![&format!("-{}", nick)])],
+            _ => Vec::new(),
+        }
+    }
+
+    fn start(&mut self, method: Method, now: Instant) -> Vec<Message> {
+        // Only once per connection, the MOTD can be asked for again
+        if self.method.is_some() {
+            return Vec::new();
+        }
+        self.method = Some(method);
+        let nicks: Vec<String> = self.nicks.values().map(|(n, _)| n.clone()).collect();
+        match method {
+            Method::Monitor => {
+                let nicks: Vec<String> = nicks
+                    .into_iter()
+                    .take(self.limit.unwrap_or(usize::MAX))
+                    .collect();
+                self.monitored = nicks.iter().map(|n| n.to_lowercase()).collect();
+                lines(&nicks)
+                    .iter()
+                    .map(|line| Message::new(Command::Monitor, vec!["+", &line.join(",")]))
+                    .collect()
+            }
+            Method::Watch => lines(&nicks)
+                .iter()
+                .map(|line| {
+                    let params = line.iter().map(|n| format!("+{}", n)).collect();
+                    Message::new(Command::Watch, vec![]).with_params(params)
+                })
+                .collect(),
+            Method::Ison => {
+                self.next_poll = Some(now + ISON_INTERVAL);
+                self.ison(nicks)
+            }
+        }
+    }
+
+    fn ison(&mut self, nicks: Vec<String>) -> Vec<Message> {
+        let lines = lines(&nicks);
+        self.asked.extend(lines.iter().cloned());
+        lines
+            .into_iter()
+            .map(|line| Message::new(Command::Ison, vec![]).with_params(line))
+            .collect()
+    }
+
+    fn is_full(&self) -> bool {
+        self.limit
+            .is_some_and(|limit| self.monitored.len() >= limit)
+    }
+
+    /// Asks who's online again when polling with ISON and it's time to.
+    /// With MONITOR, only those it doesn't cover are asked about, starting
+    /// straight away.
+    pub fn poll(&mut self, now: Instant) -> Vec<Message> {
+        let nicks: Vec<String> = match self.method {
+            Some(Method::Ison) => self.nicks.values().map(|(n, _)| n.clone()).collect(),
+            Some(Method::Monitor) => self
+                .nicks
+                .iter()
+                .filter(|(lower, _)| !self.monitored.contains(*lower))
+                .map(|(_, (n, _))| n.clone())
+                .collect(),
+            _ => return Vec::new(),
+        };
+        match self.next_poll {
+            _ if nicks.is_empty() => return Vec::new(),
+            Some(next) if now < next => return Vec::new(),
+            _ => {}
+        }
+        self.next_poll = Some(now + ISON_INTERVAL);
+        self.ison(nicks)
+    }
+
+    /// The connection is gone, so we no longer know who's online.
+    pub fn disconnected(&mut self) {
+        self.method = None;
+        self.monitored.clear();
+        self.asked.clear();
+        self.next_poll = None;
+        for (_, online) in self.nicks.values_mut() {
+            *online = None;
+        }
+    }
+
+    /// Who came online or went offline, from the server's replies to
+    /// MONITOR, WATCH or ISON.
+    pub fn handle(&mut self, message: &Message) -> Vec<Change> {
+        let param = |i| {
+            message
+                .get_param(i)
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        };
+        match message.command().to_string().as_str() {
+            RPL_MONONLINE => param(1)
+                .split(',')
+                .filter_map(|target| match target.split_once('!') {
+                    Some((nick, userhost)) => self.set(nick, true, Some(userhost.to_string())),
+                    None => self.set(target, true, None),
+                })
+                .collect(),
+            RPL_MONOFFLINE => param(1)
+                .split(',')
+                .filter_map(|nick| self.set(nick, false, None))
+                .collect(),
+            // <client> <limit> <targets> :Monitor list is full
+            ERR_MONLISTFULL => {
+                if let Ok(limit) = param(1).parse() {
+                    self.limit = Some(limit);
+                }
+                for nick in param(2).split(',') {
+                    self.monitored.remove(&nick.to_lowercase());
+                }
+                Vec::new()
+            }
+            // <client> <nick> <user> <host> <time> :<text>
+            RPL_LOGON | RPL_NOWON => {
+                let userhost = format!("{}@{}", param(2), param(3));
+                self.set(&param(1), true, Some(userhost))
+                    .into_iter()
+                    .collect()
+            }
+            RPL_LOGOFF | RPL_NOWOFF => self.set(&param(1), false, None).into_iter().collect(),
+            // Only those online are listed, so it's compared with who we
+            // asked about
+            RPL_ISON => {
+                let asked = match self.asked.pop_front() {
+                    Some(asked) => asked,
+                    None => return Vec::new(),
+                };
+                let online: BTreeSet<String> = param(1)
+                    .split_whitespace()
+                    .map(|n| n.to_lowercase())
+                    .collect();
+                asked
+                    .iter()
+                    .filter_map(|nick| self.set(nick, online.contains(&nick.to_lowercase()), None))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Notes whether `nick` is online, returning the change if it is one.
+    /// Being offline is only news for someone we knew was online.
+    fn set(&mut self, nick: &str, online: bool, userhost: Option<String>) -> Option<Change> {
+        let (given, known) = self.nicks.get_mut(&nick.to_lowercase())?;
+        let was_online = known.replace(online) == Some(true);
+        Some(Change {
+            nick: given.clone(),
+            online,
+            userhost,
+        })
+        .filter(|_| was_online != online)
+    }
+}
+
+/// Starts asking about everyone on the list once we're registered, the
+/// best way the server supports.
+pub fn start(state: &mut State, now: Instant) -> Vec<Message> {
+    let method = if let Some(limit) = state.isupport("MONITOR") {
+        // MONITOR=<limit>, or no limit without one
+        state.notify.limit = limit.parse().ok();
+        Method::Monitor
+    } else if state.isupport("WATCH").is_some() {
+        Method::Watch
+    } else {
+        Method::Ison
+    };
+    state.notify.start(method, now)
+}
+
+/// Whether a message is the server's answer to asking who's online, which
+/// is shown as the changes it brings instead.
+pub fn is_reply(message: &Message) -> bool {
+    matches!(
+        message.command().to_string().as_str(),
+        RPL_ISON
+            | RPL_LOGON
+            | RPL_LOGOFF
+            | RPL_NOWON
+            | RPL_NOWOFF
+            | RPL_MONONLINE
+            | RPL_MONOFFLINE
+            | ERR_MONLISTFULL
+    )
+}
+
+/// Splits `nicks` into groups that fit on a line.
+fn lines(nicks: &[String]) -> Vec<Vec<String>> {
+    let mut lines: Vec<Vec<String>> = Vec::new();
+    let mut len = 0;
+    for nick in nicks {
+        match lines.last_mut() {
+            Some(line) if len + 1 + nick.len() <= LINE_LEN => {
+                line.push(nick.clone());
+                len += 1 + nick.len();
+            }
+            _ => {
+                lines.push(vec![nick.clone()]);
+                len = nick.len();
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn wire(messages: Vec<Message>) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| String::from_utf8(m.as_bytes()).unwrap())
+            .collect()
+    }
+
+    fn list() -> NotifyList {
+        NotifyList::new(&["Alice".to_string(), "bob".to_string()])
+    }
+
+    #[test]
+    fn test_monitor() {
+        let mut state = State::new("me");
+        state.update(&parse_line(":irc 005 me MONITOR=100 :are supported"));
+        state.notify = list();
+        let now = Instant::now();
+        assert_eq!(
+            wire(start(&mut state, now)),
+            vec!["MONITOR + Alice,bob\r\n"]
+        );
+        assert!(start(&mut state, now).is_empty());
+
+        let notify = &mut state.notify;
+        let changes = notify.handle(&parse_line(":irc 730 me :alice!a@host,carol!c@h"));
+        assert_eq!(
+            changes,
+            vec![Change {
+                nick: "Alice".to_string(),
+                online: true,
+                userhost: Some("a@host".to_string()),
+            }]
+        );
+        // Not news the first time, only once they'd been online
+        assert!(notify.handle(&parse_line(":irc 731 me :bob")).is_empty());
+        let changes = notify.handle(&parse_line(":irc 731 me :alice"));
+        assert_eq!(changes[0].to_string(), "Alice went offline");
+
+        assert_eq!(wire(notify.add("carol")), vec!["MONITOR + carol\r\n"]);
+        assert_eq!(wire(notify.remove("BOB")), vec!["MONITOR - BOB\r\n"]);
+        assert_eq!(
+            notify.nicks(),
+            vec![("Alice", Some(false)), ("carol", None)]
+        );
+    }
+
+    #[test]
+    fn test_monitor_limit() {
+        let mut state = State::new("me");
+        state.update(&parse_line(":irc 005 me MONITOR=2 :are supported"));
+        state.notify =
+            NotifyList::new(&["alice".to_string(), "bob".to_string(), "carol".to_string()]);
+        let now = Instant::now();
+        assert_eq!(
+            wire(start(&mut state, now)),
+            vec!["MONITOR + alice,bob\r\n"]
+        );
+        let notify = &mut state.notify;
+        // Whoever doesn't fit is asked about with ISON
+        assert!(notify.add("dave").is_empty());
+        assert_eq!(wire(notify.poll(now)), vec!["ISON carol dave\r\n"]);
+        assert!(notify.poll(now + Duration::from_secs(1)).is_empty());
+        let changes = notify.handle(&parse_line(":irc 303 me :dave"));
+        assert_eq!(changes[0].to_string(), "dave is online");
+
+        // Room for one more once someone's removed
+        assert_eq!(wire(notify.remove("bob")), vec!["MONITOR - bob\r\n"]);
+        assert!(notify.remove("carol").is_empty());
+        assert_eq!(wire(notify.add("erin")), vec!["MONITOR + erin\r\n"]);
+
+        // The server's list can be fuller than we thought
+        let full = ":irc 734 me 1 erin :Monitor list is full.";
+        assert!(is_reply(&parse_line(full)));
+        assert!(notify.handle(&parse_line(full)).is_empty());
+        let later = now + ISON_INTERVAL;
+        assert_eq!(wire(notify.poll(later)), vec!["ISON dave erin\r\n"]);
+        assert!(notify.add("frank").is_empty());
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut notify = list();
+        assert_eq!(
+            wire(notify.start(Method::Watch, Instant::now())),
+            vec!["WATCH +Alice +bob\r\n"]
+        );
+        let now_on = ":irc 604 me bob b example.com 1700000000 :is online";
+        assert_eq!(
+            notify.handle(&parse_line(now_on))[0].to_string(),
+            "bob is online (b@example.com)"
+        );
+        assert!(notify
+            .handle(&parse_line(":irc 605 me Alice * * 0 :is offline"))
+            .is_empty());
+        let logoff = ":irc 601 me bob b example.com 1700000100 :logged offline";
+        assert!(!notify.handle(&parse_line(logoff))[0].online);
+    }
+
+    #[test]
+    fn test_ison() {
+        let mut notify = list();
+        let now = Instant::now();
+        assert_eq!(
+            wire(notify.start(Method::Ison, now)),
+            vec!["ISON Alice bob\r\n"]
+        );
+        let changes = notify.handle(&parse_line(":irc 303 me :bob"));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(notify.online(), vec!["bob"]);
+
+        assert!(notify.poll(now + Duration::from_secs(1)).is_empty());
+        let later = now + ISON_INTERVAL;
+        assert_eq!(wire(notify.poll(later)), vec!["ISON Alice bob\r\n"]);
+        let changes = notify.handle(&parse_line(":irc 303 me :ALICE"));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(notify.online(), vec!["Alice"]);
+
+        // Answers to ISONs we didn't send are left alone
+        assert!(notify.handle(&parse_line(":irc 303 me :bob")).is_empty());
+
+        notify.disconnected();
+        assert!(notify.online().is_empty());
+        assert!(notify.poll(later + ISON_INTERVAL).is_empty());
+    }
+
+    #[test]
+    fn test_lines() {
+        let nicks: Vec<String> = (0..100).map(|n| format!("nick{:05}", n)).collect();
+        let lines = lines(&nicks);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| l.join(" ").len() <= LINE_LEN));
+        assert_eq!(lines.concat(), nicks);
+    }
+}
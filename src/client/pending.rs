@@ -0,0 +1,270 @@
+//! Messages we've sent that the server hasn't echoed back yet.
+//!
+//! With `echo-message` the server sends our PRIVMSGs and NOTICEs back once
+//! it has accepted them, and with `labeled-response` it tags the echo, or
+//! the error it sends instead, with the label we gave the message.
+//!
+//! <https://ircv3.net/specs/extensions/echo-message>
+//! <https://ircv3.net/specs/extensions/labeled-response>
+
+use crate::client::batch::MULTILINE;
+use crate::message::{format, Command, Message};
+
+/// A message waiting for the server to echo it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sent {
+    pub label: Option<String>,
+    pub target: String,
+    pub text: String,
+    /// The reference of the multiline batch it's sent in.
+    batch: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The server echoed it, the echo is shown instead.
+    Accepted(Sent),
+    /// The server wouldn't send it, and said why.
+    Rejected(Sent, String),
+}
+
+#[derive(Debug, Default)]
+pub struct Pending {
+    sent: Vec<Sent>,
+    next_label: u64,
+}
+
+impl Pending {
+    pub fn len(&self) -> usize {
+        self.sent.len()
+    }
+
+    /// Keeps track of a message we're about to send, labelling it when
+    /// `labels` is true. Lines of a multiline batch are added to the batch's
+    /// text, the label goes on the batch itself.
+    pub fn send(&mut self, message: Message, labels: bool) -> Message {
+        let param = |i| message.get_param(i).map(|p| p.to_string());
+        let (target, text, batch) = match message.command() {
+            Command::PrivMsg | Command::Notice => match message.tag("batch") {
+                Some(reference) => {
+                    let concat = message.tag("draft/multiline-concat").is_some();
+                    let line = param(1).unwrap_or_default();
+                    if let Some(sent) = self.batch(reference) {
+                        if !sent.text.is_empty() && !concat {
+                            sent.text.push('\n');
+                        }
+                        sent.text.push_str(&line);
+                    }
+                    return message;
+                }
+                None => (param(0), param(1).unwrap_or_default(), None),
+            },
+            Command::Batch if param(1).as_deref() == Some(MULTILINE) => {
+                let reference = param(0).and_then(|r| r.strip_prefix('+').map(str::to_string));
+                if reference.is_none() {
+                    return message;
+                }
+                (param(2), String::new(), reference)
+            }
+            _ => return message,
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return message,
+        };
+
+        let label = Some(self.next_label)
+            .filter(|_| labels)
+            .map(|n| format!("yairc{}", n));
+        self.next_label += 1;
+        let message = match &label {
+            Some(label) => message.with_tag("label", Some(label)),
+            None => message,
+        };
+        self.sent.push(Sent {
+            label,
+            target,
+            text,
+            batch,
+        });
+        message
+    }
+
+    fn batch(&mut self, reference: &str) -> Option<&mut Sent> {
+        self.sent
+            .iter_mut()
+            .rev()
+            .find(|s| s.batch.as_deref() == Some(reference))
+    }
+
+    /// Matches an echo of one of our messages from `nick`, or an error
+    /// about one. Without labels, echoes are matched by what they say,
+    /// which servers may strip formatting and spaces from, and errors by
+    /// their target.
+    pub fn receive(&mut self, message: &Message, nick: &str) -> Option<Outcome> {
+        let echo = matches!(message.command(), Command::PrivMsg | Command::Notice)
+            && message.source().is_some_and(|s| s.nick == nick);
+        let position = match message.tag("label") {
+            Some(label) if echo || message.command().is_error() => self
+                .sent
+                .iter()
+                .position(|s| s.label.as_deref() == Some(label))?,
+            Some(_) => return None,
+            None if echo => {
+                let target = message.get_param(0)?.to_string();
+                let text = plain(&message.get_param(1)?.to_string());
+                self.sent.iter().position(|s| {
+                    s.label.is_none()
+                        && s.target.eq_ignore_ascii_case(&target)
+                        && plain(&s.text) == text
+                })?
+            }
+            // ERR_NOSUCHNICK, ERR_NOSUCHCHANNEL and ERR_CANNOTSENDTOCHAN
+            // name the target after our nick
+            None if message.command().is_error() => {
+                let target = message.get_param(1)?.to_string();
+                self.sent
+                    .iter()
+                    .position(|s| s.label.is_none() && s.target.eq_ignore_ascii_case(&target))?
+            }
+            None => return None,
+        };
+
+        let sent = self.sent.remove(position);
+        if echo {
+            return Some(Outcome::Accepted(sent));
+        }
+        let reason = message.params().last().cloned().unwrap_or_default();
+        Some(Outcome::Rejected(sent, reason))
+    }
+
+    /// Everything still waiting, when the connection is gone and nothing
+    /// more will be echoed.
+    pub fn clear(&mut self) -> Vec<Sent> {
+        self.sent.drain(..).collect()
+    }
+}
+
+/// `text` as an echo of it may come back, without formatting or the spaces
+/// around it.
+fn plain(text: &str) -> String {
+    format::strip(text).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::batch::{Assembler, Item};
+    use crate::message::parse_line;
+
+    #[test]
+    fn test_labels() {
+        let mut pending = Pending::default();
+        let hi = pending.send(Message::priv_msg("#ops".into(), "hi".into()), true);
+        assert_eq!(hi.tag("label"), Some("yairc0"));
+        let join = pending.send(Message::new(Command::Join, vec!["#ops"]), true);
+        assert_eq!(join.tag("label"), None);
+        pending.send(Message::priv_msg("#closed".into(), "hello?".into()), true);
+        assert_eq!(pending.len(), 2);
+
+        // Someone else's message, and a reply to some other command
+        assert_eq!(
+            pending.receive(
+                &parse_line("@label=yairc0 :alice!a@h PRIVMSG #ops :hi"),
+                "me"
+            ),
+            None
+        );
+        assert_eq!(
+            pending.receive(
+                &parse_line("@label=yairc9 :irc 401 me bob :No such nick"),
+                "me"
+            ),
+            None
+        );
+
+        let echo = parse_line("@label=yairc0;msgid=m1 :me!u@h PRIVMSG #ops :hi");
+        assert!(
+            matches!(pending.receive(&echo, "me"), Some(Outcome::Accepted(sent)) if sent.text == "hi")
+        );
+        let error = parse_line("@label=yairc1 :irc 404 me #closed :Cannot send to channel");
+        match pending.receive(&error, "me") {
+            Some(Outcome::Rejected(sent, reason)) => {
+                assert_eq!(sent.target, "#closed");
+                assert_eq!(reason, "Cannot send to channel");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(pending.len(), 0);
+    }
+
+    #[test]
+    fn test_without_labels() {
+        let mut pending = Pending::default();
+        pending.send(Message::priv_msg("#ops".into(), "one".into()), false);
+        pending.send(Message::priv_msg("bob".into(), "two".into()), false);
+        let error = parse_line(":irc 401 me Bob :No such nick/channel");
+        assert!(
+            matches!(pending.receive(&error, "me"), Some(Outcome::Rejected(sent, _)) if sent.text == "two")
+        );
+        let echo = parse_line(":me!u@h PRIVMSG #OPS :one");
+        assert!(matches!(
+            pending.receive(&echo, "me"),
+            Some(Outcome::Accepted(_))
+        ));
+        assert_eq!(pending.receive(&echo, "me"), None);
+    }
+
+    #[test]
+    fn test_stripped_echo() {
+        let mut pending = Pending::default();
+        pending.send(
+            Message::priv_msg("#ops".into(), "\u{2}bold\u{2} and \u{3}4red ".into()),
+            false,
+        );
+        let echo = parse_line(":me!u@h PRIVMSG #ops :bold and red");
+        match pending.receive(&echo, "me") {
+            Some(Outcome::Accepted(sent)) => assert_eq!(sent.text, "\u{2}bold\u{2} and \u{3}4red "),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(pending.len(), 0);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let mut pending = Pending::default();
+        let lines = [
+            "BATCH +b1 draft/multiline #ops",
+            "@batch=b1 PRIVMSG #ops :first",
+            "@batch=b1;draft/multiline-concat PRIVMSG #ops : half",
+            "@batch=b1 PRIVMSG #ops :second",
+            "BATCH -b1",
+        ];
+        let sent: Vec<Message> = lines
+            .iter()
+            .map(|raw| pending.send(parse_line(raw), true))
+            .collect();
+        assert_eq!(sent[0].tag("label"), Some("yairc0"));
+        assert_eq!(sent[1].tag("label"), None);
+        assert_eq!(pending.sent[0].text, "first half\nsecond");
+
+        let mut assembler = Assembler::default();
+        let echo = [
+            "@label=yairc0 :me!u@h BATCH +e draft/multiline #ops",
+            "@batch=e :me!u@h PRIVMSG #ops :first",
+            "@batch=e;draft/multiline-concat :me!u@h PRIVMSG #ops : half",
+            "@batch=e :me!u@h PRIVMSG #ops :second",
+            ":me!u@h BATCH -e",
+        ]
+        .iter()
+        .find_map(|raw| assembler.receive(parse_line(raw)));
+        let echo = match echo {
+            Some(Item::Batch(batch)) => batch.into_messages().remove(0),
+            other => panic!("{:?}", other),
+        };
+        assert!(matches!(
+            pending.receive(&echo, "me"),
+            Some(Outcome::Accepted(_))
+        ));
+    }
+}
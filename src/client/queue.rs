@@ -0,0 +1,134 @@
+//! Paces what we send so a paste or a burst of replies doesn't get us
+//! disconnected for flooding.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::message::{Command, Message};
+
+/// Messages that can be sent back to back before pacing kicks in.
+pub const DEFAULT_BURST: u32 = 8;
+/// Messages per second once the burst is used up.
+pub const DEFAULT_RATE: f64 = 0.5;
+
+/// Which lane a message waits in. Higher lanes are always sent first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Chat text, which is what floods in practice.
+    Bulk,
+    Normal,
+    /// Keeps the connection alive or ends it, so never waits behind a paste.
+    Urgent,
+}
+
+impl Priority {
+    pub fn of(message: &Message) -> Self {
+        match message.command() {
+            Command::Pong | Command::Quit => Priority::Urgent,
+            // Batches only wrap chat text, and have to stay in order with it
+            Command::PrivMsg | Command::Notice | Command::Batch => Priority::Bulk,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// A token bucket in front of the socket: each message takes a token, the
+/// bucket holds up to `burst` of them and refills at `rate` per second.
+#[derive(Debug)]
+pub struct Queue {
+    burst: f64,
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+    /// One lane per priority, lowest first.
+    lanes: [VecDeque<Message>; 3],
+}
+
+impl Queue {
+    pub fn new(burst: u32, rate: f64, now: Instant) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            burst,
+            rate,
+            tokens: burst,
+            refilled: now,
+            lanes: Default::default(),
+        }
+    }
+
+    pub fn push(&mut self, message: Message) {
+        self.lanes[Priority::of(&message) as usize].push_back(message);
+    }
+
+    /// The next message that may be sent at `now`, if any.
+    pub fn pop(&mut self, now: Instant) -> Option<Message> {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return None;
+        }
+
+        let message = self.lanes.iter_mut().rev().find_map(|l| l.pop_front())?;
+        self.tokens -= 1.0;
+        Some(message)
+    }
+
+    /// How many messages are waiting.
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(|l| l.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn drain(queue: &mut Queue, now: Instant) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop(now))
+            .map(|m| m.command().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_burst_then_rate() {
+        let now = Instant::now();
+        let mut queue = Queue::new(3, 2.0, now);
+        for i in 0..6 {
+            queue.push(Message::priv_msg("#rust".to_string(), i.to_string()));
+        }
+
+        assert_eq!(drain(&mut queue, now).len(), 3);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(drain(&mut queue, now + Duration::from_millis(400)).len(), 0);
+        assert_eq!(drain(&mut queue, now + Duration::from_millis(500)).len(), 1);
+        assert_eq!(drain(&mut queue, now + Duration::from_secs(10)).len(), 2);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_tokens_stop_at_burst() {
+        let now = Instant::now();
+        let mut queue = Queue::new(2, 1.0, now);
+        for _ in 0..5 {
+            queue.push(Message::priv_msg("#rust".to_string(), "hi".to_string()));
+        }
+        assert_eq!(drain(&mut queue, now + Duration::from_secs(60)).len(), 2);
+    }
+
+    #[test]
+    fn test_priority_lanes() {
+        let now = Instant::now();
+        let mut queue = Queue::new(1, 1.0, now);
+        queue.push(Message::priv_msg("#rust".to_string(), "paste".to_string()));
+        queue.push(Message::new(Command::Join, vec!["#ops"]));
+        queue.push(Message::new(Command::Quit, vec![]));
+        queue.push(Message::pong("irc.example.com".into()));
+
+        let sent: Vec<String> = (0..4)
+            .flat_map(|i| drain(&mut queue, now + Duration::from_secs(i)))
+            .collect();
+        assert_eq!(sent, vec!["QUIT", "PONG", "JOIN", "PRIVMSG"]);
+    }
+}
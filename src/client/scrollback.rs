@@ -0,0 +1,158 @@
+//! Recent messages by their `msgid`, so reactions and replies can show
+//! what they're about.
+//!
+//! <https://ircv3.net/specs/client-tags/reply>
+//! <https://ircv3.net/specs/client-tags/react>
+
+use std::collections::VecDeque;
+
+use crate::message::{format, Command, Message};
+
+/// The message a reaction or reply is about.
+pub const REPLY_TAG: &str = "+draft/reply";
+pub const REACT_TAG: &str = "+draft/react";
+
+/// How many messages are kept.
+const LIMIT: usize = 1000;
+
+/// A message someone said.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Said {
+    pub msgid: String,
+    pub buffer: String,
+    pub nick: String,
+    pub text: String,
+    /// The msgid of the message this one replies to.
+    pub reply_to: Option<String>,
+}
+
+impl Said {
+    /// The start of what was said, to quote it on one line.
+    pub fn excerpt(&self, len: usize) -> String {
+        let text = self.text.lines().next().unwrap_or_default();
+        match text.char_indices().nth(len) {
+            Some((i, _)) => format!("{}…", text[..i].trim_end()),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    messages: VecDeque<Said>,
+}
+
+impl Scrollback {
+    /// Keeps a PRIVMSG or NOTICE said in `buffer`, if it has a msgid.
+    pub fn push(&mut self, buffer: &str, message: &Message) {
+        if !matches!(message.command(), Command::PrivMsg | Command::Notice) {
+            return;
+        }
+        let (msgid, source, text) =
+            match (message.tag("msgid"), message.source(), message.get_param(1)) {
+                (Some(msgid), Some(source), Some(text)) => (msgid, source, text),
+                _ => return,
+            };
+        let text = match message.ctcp() {
+            Some(ctcp) if ctcp.is_action() => format!("* {}", ctcp.params.unwrap_or_default()),
+            Some(_) => return,
+            None => text.to_string(),
+        };
+        self.messages.push_back(Said {
+            msgid: msgid.to_string(),
+            buffer: buffer.to_string(),
+            nick: source.nick.clone(),
+            text: format::strip(&text),
+            reply_to: message.tag(REPLY_TAG).map(|m| m.to_string()),
+        });
+        if self.messages.len() > LIMIT {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn get(&self, msgid: &str) -> Option<&Said> {
+        self.messages.iter().rev().find(|s| s.msgid == msgid)
+    }
+
+    /// What was said in `buffer`, newest first.
+    pub fn in_buffer(&self, buffer: &str) -> impl Iterator<Item = &Said> {
+        let buffer = buffer.to_string();
+        self.messages
+            .iter()
+            .rev()
+            .filter(move |s| s.buffer.eq_ignore_ascii_case(&buffer))
+    }
+
+    /// A message in `buffer` by how far back it is, 1 being the newest, by
+    /// whose latest message it is, or by its msgid.
+    pub fn pick(&self, buffer: &str, choice: &str) -> Option<&Said> {
+        if let Ok(n) = choice.parse::<usize>() {
+            return self.in_buffer(buffer).nth(n.checked_sub(1)?);
+        }
+        self.in_buffer(buffer)
+            .find(|s| s.nick.eq_ignore_ascii_case(choice))
+            .or_else(|| self.get(choice))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    #[test]
+    fn test_scrollback() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push(
+            "#rust",
+            &parse_line("@msgid=a :alice!a@h PRIVMSG #rust :\u{2}hello\u{2}"),
+        );
+        scrollback.push("#rust", &parse_line(":bob!b@h PRIVMSG #rust :no msgid"));
+        scrollback.push("#rust", &parse_line("@msgid=b :bob!b@h TAGMSG #rust"));
+        scrollback.push(
+            "#rust",
+            &parse_line("@msgid=c :bob!b@h PRIVMSG #rust :\u{1}ACTION waves\u{1}"),
+        );
+        assert_eq!(scrollback.get("a").unwrap().text, "hello");
+        assert_eq!(scrollback.get("b"), None);
+        assert_eq!(scrollback.get("c").unwrap().text, "* waves");
+
+        let said = Said {
+            msgid: "d".to_string(),
+            buffer: "#rust".to_string(),
+            nick: "alice".to_string(),
+            text: "a rather long line\nand another".to_string(),
+            reply_to: None,
+        };
+        assert_eq!(said.excerpt(8), "a rather…");
+        assert_eq!(said.excerpt(80), "a rather long line");
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut scrollback = Scrollback::default();
+        for raw in [
+            "@msgid=a :alice!a@h PRIVMSG #rust :first",
+            "@msgid=b :bob!b@h PRIVMSG #go :elsewhere",
+            "@msgid=c;+draft/reply=a :bob!b@h PRIVMSG #Rust :second",
+        ] {
+            let target = parse_line(raw).get_param(0).unwrap().to_string();
+            scrollback.push(&target, &parse_line(raw));
+        }
+        let msgids = |buffer| -> Vec<_> {
+            let said = scrollback.in_buffer(buffer);
+            said.map(|s| s.msgid.as_str()).collect()
+        };
+        assert_eq!(msgids("#rust"), vec!["c", "a"]);
+        assert_eq!(scrollback.get("c").unwrap().reply_to.as_deref(), Some("a"));
+
+        let pick = |choice| scrollback.pick("#rust", choice).map(|s| s.msgid.as_str());
+        assert_eq!(pick("1"), Some("c"));
+        assert_eq!(pick("2"), Some("a"));
+        assert_eq!(pick("3"), None);
+        assert_eq!(pick("0"), None);
+        assert_eq!(pick("Alice"), Some("a"));
+        assert_eq!(pick("b"), Some("b"));
+        assert_eq!(pick("carol"), None);
+    }
+}
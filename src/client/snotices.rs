@@ -0,0 +1,216 @@
+//! Server notices, the `*** ` NOTICEs servers send opers with user mode
+//! `+s`, and WALLOPS, kept together in a buffer of their own.
+//!
+//! <https://modern.ircdocs.horse/#wallops-message>
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+
+use crate::client::state::State;
+use crate::message::{Command, Message};
+
+/// The buffer they're shown in, a name no channel or nick can have.
+pub const BUFFER: &str = "*snotices*";
+
+/// How many are kept to show on switching to the buffer.
+const KEPT: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notice {
+    pub time: DateTime<Utc>,
+    /// The server, or who sent the wallops.
+    pub from: String,
+    /// What kind of notice it is, like InspIRCd's `CONNECT` or `OPER`, or
+    /// `WALLOPS`.
+    pub kind: Option<String>,
+    pub text: String,
+}
+
+impl Notice {
+    /// The server notice or wallops in `message`, if it's one.
+    pub fn parse(message: &Message, state: &State) -> Option<Self> {
+        let source = message.source()?;
+        let param = |i| message.get_param(i).map(|p| p.to_string());
+        let notice = |kind: Option<&str>, text: &str| Self {
+            time: message.time().unwrap_or_else(Utc::now),
+            from: source.nick.clone(),
+            kind: kind.map(|k| k.to_string()),
+            text: text.to_string(),
+        };
+        match message.command() {
+            Command::Wallops => Some(notice(Some("WALLOPS"), &param(0)?)),
+            // Only with +s, servers send everyone `*** ` notices while
+            // they register too
+            Command::Notice if source.user.is_none() && state.has_user_mode('s') => {
+                if param(0)? != state.nick {
+                    return None;
+                }
+                let text = param(1)?;
+                let text = text.strip_prefix("*** ")?;
+                // Charybdis and Solanum start them all alike
+                let text = text.strip_prefix("Notice -- ").unwrap_or(text);
+                match text.split_once(": ") {
+                    Some((kind, rest)) if is_kind(kind) => Some(notice(Some(kind), rest)),
+                    _ => Some(notice(None, text)),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        let kind = self.kind.as_deref().unwrap_or_default();
+        kind.eq_ignore_ascii_case(word) || self.text.to_lowercase().contains(word)
+    }
+}
+
+/// Whether the start of a notice says what kind it is, as InspIRCd's do.
+fn is_kind(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[derive(Debug, Default)]
+pub struct ServerNotices {
+    notices: VecDeque<Notice>,
+    /// Lowercase words a notice's kind or text has to match one of to be
+    /// shown, and with a `-` in front, must not.
+    filter: Vec<String>,
+    /// How many that pass the filter came in while the buffer wasn't shown.
+    unseen: usize,
+}
+
+impl ServerNotices {
+    /// Keeps `notice`. Returns whether it passes the filter.
+    pub fn add(&mut self, notice: Notice) -> bool {
+        let shown = self.passes(&notice);
+        if shown {
+            self.unseen += 1;
+        }
+        self.notices.push_back(notice);
+        if self.notices.len() > KEPT {
+            self.notices.pop_front();
+        }
+        shown
+    }
+
+    fn passes(&self, notice: &Notice) -> bool {
+        let (without, with): (Vec<&String>, Vec<&String>) =
+            self.filter.iter().partition(|w| w.starts_with('-'));
+        !without.iter().any(|w| notice.matches(&w[1..]))
+            && (with.is_empty() || with.iter().any(|w| notice.matches(w)))
+    }
+
+    /// Only shows notices matching one of `words`, and none of those
+    /// starting with `-`. No words shows them all.
+    pub fn set_filter(&mut self, words: &[String]) {
+        self.filter = words.iter().map(|w| w.to_lowercase()).collect();
+        self.unseen = 0;
+    }
+
+    pub fn filter(&self) -> &[String] {
+        &self.filter
+    }
+
+    pub fn unseen(&self) -> usize {
+        self.unseen
+    }
+
+    /// The newest `count` notices passing the filter, oldest first, and
+    /// marks them all seen.
+    pub fn recent(&mut self, count: usize) -> Vec<&Notice> {
+        self.unseen = 0;
+        let mut recent: Vec<&Notice> = self
+            .notices
+            .iter()
+            .rev()
+            .filter(|n| self.passes(n))
+            .take(count)
+            .collect();
+        recent.reverse();
+        recent
+    }
+
+    /// The buffer's being shown, so what's come in has been seen.
+    pub fn seen(&mut self) {
+        self.unseen = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn parse(state: &State, raw: &str) -> Option<Notice> {
+        Notice::parse(&parse_line(raw), state)
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut state = State::new("me");
+        let connect = ":irc.example.com NOTICE me :*** CONNECT: Client connecting on port 6697";
+        // Not an oper yet, so it's the hostname lookup or some such
+        assert_eq!(parse(&state, connect), None);
+
+        state.update(&parse_line(":me MODE me :+is"));
+        let notice = parse(&state, connect).unwrap();
+        assert_eq!(notice.from, "irc.example.com");
+        assert_eq!(notice.kind.as_deref(), Some("CONNECT"));
+        assert_eq!(notice.text, "Client connecting on port 6697");
+
+        let solanum = ":irc.example.com NOTICE me :*** Notice -- Client exiting: bob (Quit)";
+        let notice = parse(&state, solanum).unwrap();
+        assert_eq!(notice.kind, None);
+        assert_eq!(notice.text, "Client exiting: bob (Quit)");
+
+        assert_eq!(
+            parse(&state, ":alice!a@h NOTICE me :*** CONNECT: fake"),
+            None
+        );
+        assert_eq!(parse(&state, ":irc.example.com NOTICE me :Hello"), None);
+        assert_eq!(
+            parse(&state, ":irc.example.com NOTICE #ops :*** LINK: x"),
+            None
+        );
+
+        let wallops = parse(&state, ":alice!a@h WALLOPS :Restarting soon").unwrap();
+        assert_eq!(wallops.from, "alice");
+        assert_eq!(wallops.kind.as_deref(), Some("WALLOPS"));
+        assert_eq!(wallops.text, "Restarting soon");
+    }
+
+    #[test]
+    fn test_filter() {
+        let notice = |kind: &str, text: &str| Notice {
+            time: Utc::now(),
+            from: "irc.example.com".to_string(),
+            kind: Some(kind.to_string()),
+            text: text.to_string(),
+        };
+        let mut notices = ServerNotices::default();
+        assert!(notices.add(notice("CONNECT", "Client connecting: alice")));
+        notices.set_filter(&["connect".to_string(), "-Bot".to_string()]);
+        assert!(notices.add(notice("CONNECT", "Client connecting: bob")));
+        assert!(!notices.add(notice("CONNECT", "Client connecting: bot7")));
+        assert!(!notices.add(notice("QUIT", "Client exiting: bob")));
+        // Matches the text as well as the kind
+        assert!(notices.add(notice("OPER", "carol used OPER to connect")));
+        assert_eq!(notices.unseen(), 2);
+
+        let texts = |recent: Vec<&Notice>| -> Vec<String> {
+            recent.into_iter().map(|n| n.text.clone()).collect()
+        };
+        assert_eq!(
+            texts(notices.recent(2)),
+            vec!["Client connecting: bob", "carol used OPER to connect"]
+        );
+        assert_eq!(notices.unseen(), 0);
+
+        notices.set_filter(&[]);
+        assert_eq!(notices.recent(10).len(), 5);
+    }
+}
@@ -0,0 +1,607 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::client::caps::Caps;
+use crate::client::history::History;
+use crate::client::list::ChannelList;
+use crate::client::modes::{ModeLists, ModeTypes};
+use crate::client::notify::NotifyList;
+use crate::client::pending::Pending;
+use crate::client::scrollback::Scrollback;
+use crate::client::snotices::ServerNotices;
+use crate::client::typing::Typing;
+use crate::client::whois::Whois;
+use crate::message::{Command, Message};
+
+/// Prefixes servers put in front of nicks in NAMES replies to show their
+/// channel status.
+const MEMBER_PREFIXES: &[char] = &['~', '&', '@', '%', '+'];
+
+/// Usual limits on the user and host parts of a prefix.
+const USERLEN: usize = 10;
+const HOSTLEN: usize = 63;
+
+pub const CHANNEL_PREFIXES: &[char] = &['#', '&', '+', '!'];
+
+/// RFC 2812 limits channel names to 50 characters, prefix included.
+const CHANNELLEN: usize = 50;
+
+pub const RPL_UMODEIS: &str = "221";
+
+/// Whether `nick` fits the RFC 2812 grammar. The 9 character limit is left
+/// out, servers advertise their own and most allow far longer.
+pub fn is_nick(nick: &str) -> bool {
+    let special = |c: char| ('\x5b'..='\x60').contains(&c) || ('\x7b'..='\x7d').contains(&c);
+    let mut chars = nick.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || special(c) => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || special(c) || c == '-')
+}
+
+/// Whether `username` can go in a USER message: one word, and no `@`
+/// since it ends up in front of our host.
+pub fn is_username(username: &str) -> bool {
+    !username.is_empty()
+        && !username
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '@')
+}
+
+/// Whether `channel` fits the RFC 2812 grammar, including the `!` channel
+/// id and a `:` mask.
+pub fn is_channel(channel: &str) -> bool {
+    let chanstring = |s: &str| {
+        !s.is_empty()
+            && !s
+                .chars()
+                .any(|c| matches!(c, '\0' | '\x07' | '\r' | '\n' | ' ' | ',' | ':'))
+    };
+    if channel.len() > CHANNELLEN {
+        return false;
+    }
+    let name = match channel.chars().next() {
+        Some('#') | Some('&') | Some('+') => &channel[1..],
+        Some('!') => {
+            let id = channel.get(1..6).unwrap_or_default();
+            if id.len() != 5
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            {
+                return false;
+            }
+            &channel[6..]
+        }
+        _ => return false,
+    };
+    match name.split_once(':') {
+        Some((name, mask)) => chanstring(name) && chanstring(mask),
+        None => chanstring(name),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Channel {
+    pub members: BTreeSet<String>,
+    /// The key we joined with, or it was changed to since, needed to
+    /// rejoin after a reconnect.
+    pub key: Option<String>,
+}
+
+/// What we know about someone in a channel with us, kept up to date by
+/// extended-join, account-notify, away-notify, chghost and setname.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct User {
+    pub user: Option<String>,
+    pub host: Option<String>,
+    pub realname: Option<String>,
+    /// The services account they're logged in to.
+    pub account: Option<String>,
+    /// Their away message, when they're away.
+    pub away: Option<String>,
+}
+
+/// What we know about the connection, kept up to date from incoming
+/// messages. Shared with the UI so it can offer completions.
+#[derive(Debug, Default)]
+pub struct State {
+    pub nick: String,
+    pub channels: BTreeMap<String, Channel>,
+    pub queries: BTreeSet<String>,
+    pub caps: Caps,
+    pub history: History,
+    /// What we've sent that the server hasn't echoed yet.
+    pub pending: Pending,
+    pub notify: NotifyList,
+    pub typing: Typing,
+    pub scrollback: Scrollback,
+    /// Channels from the last LIST.
+    pub list: ChannelList,
+    /// The last ban, exception, invite and quiet lists we got.
+    pub mode_lists: ModeLists,
+    /// WHOIS, WHOWAS and WHO replies still coming in.
+    pub whois: Whois,
+    /// Our `user@host` as others see it, once the server has shown us.
+    pub userhost: Option<String>,
+    /// Server notices and wallops, for the `*snotices*` buffer.
+    pub snotices: ServerNotices,
+    /// Our user modes, like `o` once we're an oper.
+    user_modes: BTreeSet<char>,
+    /// Everyone in the channels we're in, by nick.
+    users: BTreeMap<String, User>,
+    /// Keys from JOINs we've sent, until the server confirms the join.
+    pending_keys: BTreeMap<String, String>,
+    /// What the server told us it supports in RPL_ISUPPORT, by name.
+    isupport: BTreeMap<String, String>,
+}
+
+impl State {
+    pub fn new(nick: &str) -> Self {
+        Self {
+            nick: nick.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn members(&self, channel: &str) -> Vec<String> {
+        self.channels
+            .get(channel)
+            .map(|c| c.members.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn user(&self, nick: &str) -> Option<&User> {
+        self.users.get(nick)
+    }
+
+    /// We've left `channel`, so forget it and whoever we only saw there.
+    fn left(&mut self, channel: &str) {
+        self.channels.remove(channel);
+        let users: Vec<String> = self.users.keys().cloned().collect();
+        for user in users {
+            self.forget(&user);
+        }
+    }
+
+    /// Forgets about `nick` once we no longer share a channel.
+    fn forget(&mut self, nick: &str) {
+        if !self.channels.values().any(|c| c.members.contains(nick)) {
+            self.users.remove(nick);
+        }
+    }
+
+    pub fn has_user_mode(&self, mode: char) -> bool {
+        self.user_modes.contains(&mode)
+    }
+
+    /// Our user modes as a mode string, like `+iow`, or empty with none.
+    pub fn user_modes(&self) -> String {
+        match self.user_modes.is_empty() {
+            true => String::new(),
+            false => format!("+{}", self.user_modes.iter().collect::<String>()),
+        }
+    }
+
+    /// Whether `message` tells us our user modes, or changes them.
+    pub fn is_user_mode(&self, message: &Message) -> bool {
+        let command = message.command();
+        (command == &Command::Mode && message.get_param(0).is_some_and(|p| p == &self.nick))
+            || command.to_string() == RPL_UMODEIS
+    }
+
+    /// The value of an RPL_ISUPPORT token, empty if it has none.
+    pub fn isupport(&self, name: &str) -> Option<&str> {
+        self.isupport.get(name).map(|v| v.as_str())
+    }
+
+    /// Remembers what we need from messages we send ourselves.
+    pub fn sent(&mut self, message: &Message) {
+        let param = |i| message.get_param(i).map(|p| p.to_string());
+        match message.command() {
+            Command::Join => {
+                if let (Some(channels), Some(keys)) = (param(0), param(1)) {
+                    for (channel, key) in channels.split(',').zip(keys.split(',')) {
+                        self.pending_keys
+                            .insert(channel.to_string(), key.to_string());
+                    }
+                }
+            }
+            Command::PrivMsg => {
+                if let Some(target) = param(0).filter(|t| !t.starts_with(CHANNEL_PREFIXES)) {
+                    self.queries.insert(target);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The connection is gone. Channels and queries are kept so they can be
+    /// restored, but who's in the channels will be sent again on rejoining.
+    pub fn disconnected(&mut self) {
+        for channel in self.channels.values_mut() {
+            channel.members.clear();
+        }
+        self.users.clear();
+        self.user_modes.clear();
+        self.notify.disconnected();
+        self.typing.disconnected();
+        self.whois.disconnected();
+        self.list.disconnected();
+        self.mode_lists.disconnected();
+    }
+
+    /// How long the `nick!user@host` prefix the server puts on our messages
+    /// is, guessing the longest likely one until we know.
+    pub fn prefix_len(&self) -> usize {
+        let userhost = match &self.userhost {
+            Some(userhost) => userhost.len(),
+            None => USERLEN + 1 + HOSTLEN,
+        };
+        self.nick.len() + 1 + userhost
+    }
+
+    pub fn update(&mut self, message: &Message) {
+        let nick = message.source().map(|s| s.nick.clone()).unwrap_or_default();
+        let param = |i| {
+            message
+                .get_param(i)
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        };
+
+        if let Some(source) = message.source().filter(|s| s.nick == self.nick) {
+            if let (Some(user), Some(host)) = (&source.user, &source.host) {
+                self.userhost = Some(format!("{}@{}", user, host));
+            }
+        }
+
+        match message.command() {
+            Command::Join => {
+                let channel = param(0);
+                if nick == self.nick {
+                    let key = self.pending_keys.remove(&channel);
+                    let c = self.channels.entry(channel.clone()).or_default();
+                    c.key = key.or_else(|| c.key.take());
+                }
+                if let Some(c) = self.channels.get_mut(&channel) {
+                    c.members.insert(nick.clone());
+                    let user = self.users.entry(nick).or_default();
+                    if let Some(source) = message.source() {
+                        user.user = source.user.clone().or(user.user.take());
+                        user.host = source.host.clone().or(user.host.take());
+                    }
+                    // extended-join adds the account, * for none, and realname
+                    if let Some(account) = message.get_param(1) {
+                        user.account = Some(account.to_string()).filter(|a| a != "*");
+                        user.realname = Some(param(2));
+                    }
+                }
+            }
+            Command::Part => {
+                let channel = param(0);
+                if nick == self.nick {
+                    self.left(&channel);
+                } else if let Some(c) = self.channels.get_mut(&channel) {
+                    c.members.remove(&nick);
+                    self.forget(&nick);
+                }
+            }
+            // KICK <channel> <nick> [:reason], leaving it like a PART
+            Command::Kick => {
+                let (channel, kicked) = (param(0), param(1));
+                if kicked == self.nick {
+                    self.left(&channel);
+                } else if let Some(c) = self.channels.get_mut(&channel) {
+                    c.members.remove(&kicked);
+                    self.forget(&kicked);
+                }
+            }
+            // MODE <channel> <modes> [params], only the key is kept
+            Command::Mode if self.channels.contains_key(&param(0)) => {
+                let params = message.params();
+                let changes =
+                    ModeTypes::new(self).parse(&param(1), params.get(2..).unwrap_or_default());
+                let c = self.channels.get_mut(&param(0)).unwrap();
+                for change in changes.into_iter().filter(|c| c.mode == 'k') {
+                    c.key = if change.adding { change.param } else { None };
+                }
+            }
+            Command::Quit => {
+                for channel in self.channels.values_mut() {
+                    channel.members.remove(&nick);
+                }
+                self.users.remove(&nick);
+            }
+            Command::Nick => {
+                let new_nick = param(0);
+                if nick == self.nick {
+                    self.nick = new_nick.clone();
+                }
+                for channel in self.channels.values_mut() {
+                    if channel.members.remove(&nick) {
+                        channel.members.insert(new_nick.clone());
+                    }
+                }
+                if let Some(user) = self.users.remove(&nick) {
+                    self.users.insert(new_nick, user);
+                }
+            }
+            Command::Account => {
+                if let Some(user) = self.users.get_mut(&nick) {
+                    user.account = Some(param(0)).filter(|a| a != "*");
+                }
+            }
+            Command::Away => {
+                if let Some(user) = self.users.get_mut(&nick) {
+                    user.away = message.get_param(0).map(|p| p.to_string());
+                }
+            }
+            Command::ChgHost => {
+                if let Some(user) = self.users.get_mut(&nick) {
+                    user.user = Some(param(0));
+                    user.host = Some(param(1));
+                }
+            }
+            Command::SetName => {
+                if let Some(user) = self.users.get_mut(&nick) {
+                    user.realname = Some(param(0));
+                }
+            }
+            Command::RplNamReply => {
+                // <client> <symbol> <channel> :[prefix]<nick>{ [prefix]<nick>}
+                if let Some(c) = self.channels.get_mut(&param(2)) {
+                    for member in param(3).split_whitespace() {
+                        let member = member.trim_start_matches(MEMBER_PREFIXES).to_string();
+                        self.users.entry(member.clone()).or_default();
+                        c.members.insert(member);
+                    }
+                }
+            }
+            Command::RplISupport => {
+                // <client> <token>... :are supported by this server
+                let params = message.params();
+                let tokens = params.get(1..params.len().saturating_sub(1));
+                for token in tokens.unwrap_or_default() {
+                    if let Some(name) = token.strip_prefix('-') {
+                        self.isupport.remove(name);
+                        continue;
+                    }
+                    let (name, value) = token.split_once('=').unwrap_or((token, ""));
+                    self.isupport.insert(name.to_string(), value.to_string());
+                }
+            }
+            Command::PrivMsg => {
+                let target = param(0);
+                if target == self.nick && !nick.is_empty() {
+                    self.queries.insert(nick);
+                }
+            }
+            // MODE <nick> <modes>, or <client> <modes> in RPL_UMODEIS.
+            // Parameters, like InspIRCd's server notice mask, are left out.
+            _ if self.is_user_mode(message) => {
+                if message.command() != &Command::Mode {
+                    self.user_modes.clear();
+                }
+                let mut adding = true;
+                for c in param(1).chars() {
+                    match c {
+                        '+' => adding = true,
+                        '-' => adding = false,
+                        _ if adding => {
+                            self.user_modes.insert(c);
+                        }
+                        _ => {
+                            self.user_modes.remove(&c);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    #[test]
+    fn test_names() {
+        for nick in &["jon", "[away]", "a-b", "^_^", "Jon2"] {
+            assert!(is_nick(nick), "{}", nick);
+        }
+        for nick in &["", "2jon", "-jon", "jon smith", "jon!x", "#jon"] {
+            assert!(!is_nick(nick), "{}", nick);
+        }
+        for channel in &[
+            "#rust",
+            "&local",
+            "+modeless",
+            "!12ABCops",
+            "#eu:*.fi",
+            "#ünï",
+        ] {
+            assert!(is_channel(channel), "{}", channel);
+        }
+        let long = format!("#{}", "a".repeat(50));
+        for channel in &["rust", "#", "#a b", "#a,b", "!abc", "#a:", &long] {
+            assert!(!is_channel(channel), "{}", channel);
+        }
+    }
+
+    fn update(state: &mut State, raw: &str) {
+        state.update(&parse_line(raw));
+    }
+
+    #[test]
+    fn test_tracks_members() {
+        let mut state = State::new("me");
+        update(&mut state, ":me!u@h JOIN #rust");
+        update(
+            &mut state,
+            ":irc.example.com 353 me = #rust :me @alice +bob",
+        );
+        update(&mut state, ":carol!u@h JOIN #rust");
+        update(&mut state, ":bob!u@h PART #rust :bye");
+        update(&mut state, ":alice!u@h NICK alicia");
+
+        assert_eq!(state.members("#rust"), vec!["alicia", "carol", "me"]);
+    }
+
+    #[test]
+    fn test_tracks_users() {
+        let mut state = State::new("me");
+        update(&mut state, ":me!u@h JOIN #rust * :Me");
+        update(&mut state, ":me!u@h JOIN #ops * :Me");
+        update(&mut state, ":irc.example.com 353 me = #rust :me @alice");
+        update(&mut state, ":bob!b@example.com JOIN #rust bobby :Bob B");
+        update(&mut state, ":bob!b@example.com JOIN #ops bobby :Bob B");
+
+        let bob = state.user("bob").unwrap();
+        assert_eq!(bob.account.as_deref(), Some("bobby"));
+        assert_eq!(bob.realname.as_deref(), Some("Bob B"));
+        assert_eq!(bob.host.as_deref(), Some("example.com"));
+
+        update(&mut state, ":alice!a@h ACCOUNT alice");
+        update(&mut state, ":alice!a@h AWAY :lunch");
+        update(&mut state, ":bob!b@example.com CHGHOST bob cloak/bob");
+        update(&mut state, ":bob!bob@cloak/bob SETNAME :Robert");
+        update(&mut state, ":bob!bob@cloak/bob ACCOUNT *");
+        let alice = state.user("alice").unwrap();
+        assert_eq!(alice.account.as_deref(), Some("alice"));
+        assert_eq!(alice.away.as_deref(), Some("lunch"));
+        let bob = state.user("bob").unwrap();
+        assert_eq!(bob.user.as_deref(), Some("bob"));
+        assert_eq!(bob.host.as_deref(), Some("cloak/bob"));
+        assert_eq!(bob.realname.as_deref(), Some("Robert"));
+        assert_eq!(bob.account, None);
+
+        update(&mut state, ":alice!a@h AWAY");
+        assert_eq!(state.user("alice").unwrap().away, None);
+        update(&mut state, ":bob!bob@cloak/bob NICK rob");
+        assert!(state.user("bob").is_none());
+        // Still shares #ops with us after leaving #rust
+        update(&mut state, ":rob!bob@cloak/bob PART #rust");
+        assert!(state.user("rob").is_some());
+        update(&mut state, ":me!u@h PART #rust");
+        assert!(state.user("alice").is_none());
+        update(&mut state, ":rob!bob@cloak/bob QUIT :bye");
+        assert!(state.user("rob").is_none());
+    }
+
+    #[test]
+    fn test_own_part_forgets_channel() {
+        let mut state = State::new("me");
+        update(&mut state, ":me!u@h JOIN #rust");
+        update(&mut state, ":me!u@h PART #rust");
+
+        assert!(state.channels.is_empty());
+    }
+
+    #[test]
+    fn test_kick() {
+        let mut state = State::new("me");
+        update(&mut state, ":me!u@h JOIN #rust");
+        update(&mut state, ":me!u@h JOIN #ops");
+        update(&mut state, ":irc.example.com 353 me = #rust :me @alice bob");
+        update(&mut state, ":irc.example.com 353 me = #ops :me @alice");
+        update(&mut state, ":alice!a@h KICK #rust bob :spam");
+        assert_eq!(state.members("#rust"), vec!["alice", "me"]);
+        assert!(state.user("bob").is_none());
+
+        // Not rejoined on reconnecting
+        update(&mut state, ":alice!a@h KICK #ops me");
+        assert!(!state.channels.contains_key("#ops"));
+        assert!(state.user("alice").is_some());
+    }
+
+    #[test]
+    fn test_tracks_key_changes() {
+        let mut state = State::new("me");
+        state.sent(&Message::new(Command::Join, vec!["#ops", "secret"]));
+        update(&mut state, ":me!u@h JOIN #ops");
+        update(&mut state, ":alice!a@h MODE #ops +lk 10 hunter2");
+        assert_eq!(state.channels["#ops"].key.as_deref(), Some("hunter2"));
+        update(&mut state, ":alice!a@h MODE #ops +o-k bob hunter2");
+        assert_eq!(state.channels["#ops"].key, None);
+        update(&mut state, ":alice!a@h MODE #ops +k new");
+        update(&mut state, ":alice!a@h MODE #ops -l");
+        assert_eq!(state.channels["#ops"].key.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_keeps_channels_and_keys_on_disconnect() {
+        let mut state = State::new("me");
+        state.sent(&Message::new(Command::Join, vec!["#ops,#rust", "secret"]));
+        update(&mut state, ":me!u@h JOIN #ops");
+        update(&mut state, ":me!u@h JOIN #rust");
+        update(&mut state, ":irc.example.com 353 me = #ops :me @alice");
+
+        state.disconnected();
+        assert_eq!(state.members("#ops"), Vec::<String>::new());
+        assert_eq!(state.channels["#ops"].key.as_deref(), Some("secret"));
+        assert_eq!(state.channels["#rust"].key, None);
+
+        // Rejoining keeps the key
+        update(&mut state, ":me!u@h JOIN #ops");
+        assert_eq!(state.channels["#ops"].key.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_prefix_len() {
+        let mut state = State::new("me");
+        assert_eq!(state.prefix_len(), 2 + USERLEN + 1 + HOSTLEN + 1);
+        update(&mut state, ":me!~me@example.com JOIN #rust");
+        assert_eq!(state.prefix_len(), "me!~me@example.com".len());
+    }
+
+    #[test]
+    fn test_isupport() {
+        let mut state = State::new("me");
+        update(
+            &mut state,
+            ":irc.example.com 005 me CHATHISTORY=100 EXCEPTS :are supported by this server",
+        );
+        assert_eq!(state.isupport("CHATHISTORY"), Some("100"));
+        assert_eq!(state.isupport("EXCEPTS"), Some(""));
+        update(
+            &mut state,
+            ":irc.example.com 005 me -EXCEPTS :are supported",
+        );
+        assert_eq!(state.isupport("EXCEPTS"), None);
+    }
+
+    #[test]
+    fn test_user_modes() {
+        let mut state = State::new("me");
+        update(&mut state, ":irc.example.com 221 me +iw");
+        assert_eq!(state.user_modes(), "+iw");
+        update(&mut state, ":me MODE me :+os-w");
+        update(&mut state, ":irc.example.com MODE me +s +cC");
+        assert_eq!(state.user_modes(), "+ios");
+        assert!(state.has_user_mode('o'));
+        // Someone else's, and a channel's
+        update(&mut state, ":alice!a@h MODE alice -i");
+        update(&mut state, ":alice!a@h MODE #rust -i");
+        update(&mut state, ":irc.example.com 221 me +i");
+        assert_eq!(state.user_modes(), "+i");
+
+        state.disconnected();
+        assert_eq!(state.user_modes(), "");
+    }
+
+    #[test]
+    fn test_private_message_opens_query() {
+        let mut state = State::new("me");
+        update(&mut state, ":alice!u@h PRIVMSG me :hi");
+        state.sent(&Message::priv_msg("bob".to_string(), "hi".to_string()));
+        state.sent(&Message::priv_msg("#rust".to_string(), "hi".to_string()));
+
+        assert_eq!(
+            state.queries.iter().collect::<Vec<_>>(),
+            vec!["alice", "bob"]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Who's typing, from `+typing` client tags, and telling others when we
+//! are.
+//!
+//! <https://ircv3.net/specs/client-tags/typing>
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::message::{Command, Message};
+
+pub const TAG: &str = "+typing";
+
+/// How long someone is believed to be typing after saying so, unless they
+/// say otherwise.
+const ACTIVE_TIMEOUT: Duration = Duration::from_secs(6);
+const PAUSED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// We say we're typing at most this often.
+const THROTTLE: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+pub struct Typing {
+    /// Who's typing in each buffer, by lowercased buffer name, and until
+    /// when.
+    others: BTreeMap<String, BTreeMap<String, Instant>>,
+    /// The target we last told we're typing, and when.
+    own: Option<(String, Instant)>,
+}
+
+impl Typing {
+    /// Who's typing in `buffer`.
+    pub fn nicks(&self, buffer: &str) -> Vec<String> {
+        self.others
+            .get(&buffer.to_lowercase())
+            .map(|nicks| nicks.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Notes someone typing in `buffer`, or sending what they typed.
+    /// Returns true if who's typing there changed.
+    pub fn receive(&mut self, message: &Message, buffer: &str, now: Instant) -> bool {
+        let nick = match message.source() {
+            Some(source) => source.nick.clone(),
+            None => return false,
+        };
+        let until = match (message.command(), message.tag(TAG)) {
+            (Command::TagMsg, Some("active")) => Some(now + ACTIVE_TIMEOUT),
+            (Command::TagMsg, Some("paused")) => Some(now + PAUSED_TIMEOUT),
+            (Command::TagMsg, Some(_)) | (Command::PrivMsg, _) | (Command::Notice, _) => None,
+            _ => return false,
+        };
+        let buffer = buffer.to_lowercase();
+        match until {
+            Some(until) => {
+                let nicks = self.others.entry(buffer).or_default();
+                // Only the first time is news, later ones keep it going
+                nicks.insert(nick, until).is_none()
+            }
+            None => {
+                let nicks = match self.others.get_mut(&buffer) {
+                    Some(nicks) => nicks,
+                    None => return false,
+                };
+                let removed = nicks.remove(&nick).is_some();
+                if nicks.is_empty() {
+                    self.others.remove(&buffer);
+                }
+                removed
+            }
+        }
+    }
+
+    /// Forgets whoever hasn't said they're still typing in time. Returns
+    /// the buffers where that changed who's typing.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let mut changed = Vec::new();
+        for (buffer, nicks) in &mut self.others {
+            let before = nicks.len();
+            nicks.retain(|_, until| *until > now);
+            if nicks.len() != before {
+                changed.push(buffer.clone());
+            }
+        }
+        self.others.retain(|_, nicks| !nicks.is_empty());
+        changed
+    }
+
+    /// What to tell `target` now that the input line is `line`: that we're
+    /// typing, at most every few seconds, or that we stopped once it's
+    /// cleared. Commands don't count as typing. Whoever we told before is
+    /// told we're done when it's someone else now.
+    pub fn typed(&mut self, target: &str, line: &str, now: Instant) -> Vec<Message> {
+        let typing = !line.is_empty() && (!line.starts_with('/') || line.starts_with("//"));
+        if !typing {
+            return self
+                .own
+                .take()
+                .map(|(target, _)| tagmsg(&target, "done"))
+                .into_iter()
+                .collect();
+        }
+        let mut messages = Vec::new();
+        match &self.own {
+            Some((own, since)) if own == target && now < *since + THROTTLE => return messages,
+            Some((own, _)) if own != target => messages.push(tagmsg(own, "done")),
+            _ => {}
+        }
+        self.own = Some((target.to_string(), now));
+        messages.push(tagmsg(target, "active"));
+        messages
+    }
+
+    /// We sent what we typed, which says we're done by itself.
+    pub fn sent(&mut self) {
+        self.own = None;
+    }
+
+    pub fn disconnected(&mut self) {
+        self.others.clear();
+        self.own = None;
+    }
+}
+
+fn tagmsg(target: &str, typing: &str) -> Message {
+    Message::new(Command::TagMsg, vec![target]).with_tag(TAG, Some(typing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    #[test]
+    fn test_others() {
+        let mut typing = Typing::default();
+        let now = Instant::now();
+        let bob = parse_line("@+typing=active :bob!b@h TAGMSG #rust");
+        assert!(typing.receive(&bob, "#rust", now));
+        let alice = parse_line("@+typing=paused :alice!a@h TAGMSG #rust");
+        assert!(typing.receive(&alice, "#Rust", now));
+        assert!(!typing.receive(&bob, "#rust", now));
+        assert_eq!(typing.nicks("#rust"), vec!["alice", "bob"]);
+
+        // Sending the message ends it
+        let said = parse_line(":bob!b@h PRIVMSG #rust :hi");
+        assert!(typing.receive(&said, "#rust", now));
+        assert!(!typing.receive(&said, "#rust", now));
+        assert_eq!(typing.nicks("#rust"), vec!["alice"]);
+
+        assert!(typing.expire(now + ACTIVE_TIMEOUT).is_empty());
+        assert_eq!(typing.expire(now + PAUSED_TIMEOUT), vec!["#rust"]);
+        assert!(typing.nicks("#rust").is_empty());
+    }
+
+    #[test]
+    fn test_own() {
+        let mut typing = Typing::default();
+        let now = Instant::now();
+        let wire = |messages: Vec<Message>| -> Vec<String> {
+            messages
+                .iter()
+                .map(|m| String::from_utf8(m.as_bytes()).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            wire(typing.typed("#rust", "h", now)),
+            vec!["@+typing=active TAGMSG #rust\r\n"]
+        );
+        assert!(typing
+            .typed("#rust", "he", now + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(typing.typed("#rust", "hel", now + THROTTLE).len(), 1);
+        assert_eq!(
+            wire(typing.typed("#rust", "", now + THROTTLE)),
+            vec!["@+typing=done TAGMSG #rust\r\n"]
+        );
+        assert!(typing.typed("#rust", "", now + THROTTLE).is_empty());
+        assert!(typing.typed("#rust", "/join", now).is_empty());
+
+        // Switching buffers with something typed
+        typing.typed("#rust", "hi", now);
+        assert_eq!(
+            wire(typing.typed("bob", "hi", now)),
+            vec![
+                "@+typing=done TAGMSG #rust\r\n",
+                "@+typing=active TAGMSG bob\r\n"
+            ]
+        );
+        typing.sent();
+        assert!(typing.typed("bob", "", now).is_empty());
+    }
+}
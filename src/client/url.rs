@@ -0,0 +1,254 @@
+//! `irc://` and `ircs://` URLs.
+//!
+//! <https://datatracker.ietf.org/doc/html/draft-butcher-irc-url-04>, along
+//! with the common `?key` and several channels separated by commas.
+
+use std::str::FromStr;
+
+use crate::client::{self, state};
+use crate::config::{Config, Settings, DEFAULT_PORT, DEFAULT_TLS_PORT};
+
+/// A parsed URL, `ircs://irc.example.org:6697/#ops,needkey?key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: Option<u16>,
+    pub tls: bool,
+    /// Channels to join, with their keys.
+    pub channels: Vec<(String, Option<String>)>,
+    /// Who to open a query with, from an `isnick` URL.
+    pub nick: Option<String>,
+    /// Set by `isserver`, the host is a server even if a configured network
+    /// has the same name.
+    pub is_server: bool,
+}
+
+impl FromStr for Url {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| format!("{} is not a URL", url))?;
+        let tls = match scheme.to_lowercase().as_str() {
+            "irc" => false,
+            "ircs" => true,
+            _ => {
+                return Err(format!(
+                    "expected an irc:// or ircs:// URL, not {}://",
+                    scheme
+                ))
+            }
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+
+        // An IPv6 address is in brackets so its colons aren't taken for a port
+        let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("missing ] in {}", url))?;
+            (host, rest.strip_prefix(':'))
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(format!("no host in {}", url));
+        }
+        let port = match port {
+            Some(port) => match port.parse::<u16>() {
+                Ok(port) if port > 0 => Some(port),
+                _ => return Err(format!("{} is not a port", port)),
+            },
+            None => None,
+        };
+
+        // <entity>[,<flag or channel>...][?<options>]
+        let (path, options) = match path.split_once('?') {
+            Some((path, options)) => (path, options),
+            None => (path, ""),
+        };
+        let mut names = Vec::new();
+        let mut is_nick = false;
+        let mut is_server = false;
+        for part in path.split(',').filter(|p| !p.is_empty()) {
+            match part.to_lowercase().as_str() {
+                "isnick" | "isuser" => is_nick = true,
+                "isserver" => is_server = true,
+                // We ask for nothing up front, keys come from the options
+                // and passwords from the config
+                "ischannel" | "isnetwork" | "needkey" | "needpass" => {}
+                _ => names.push(percent_decode(part)),
+            }
+        }
+
+        let mut keys = Vec::new();
+        for option in options.split('&').filter(|o| !o.is_empty()) {
+            match option.split_once('=') {
+                Some((name, value)) if name.eq_ignore_ascii_case("key") => {
+                    keys.extend(value.split(',').map(percent_decode))
+                }
+                Some(_) => {}
+                None => keys.extend(option.split(',').map(percent_decode)),
+            }
+        }
+
+        let mut nick = None;
+        let mut channels = Vec::new();
+        if is_nick {
+            match names.as_slice() {
+                [name] if state::is_nick(name) => nick = Some(name.clone()),
+                [name] => return Err(format!("{} is not a nick", name)),
+                _ => return Err("isnick URLs name exactly one nick".to_string()),
+            }
+        } else {
+            let mut keys = keys.into_iter();
+            for name in names {
+                let channel = client::channel_name(&name);
+                if !state::is_channel(&channel) {
+                    return Err(format!("{} is not a channel name", channel));
+                }
+                channels.push((channel, keys.next().filter(|k| !k.is_empty())));
+            }
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            tls,
+            channels,
+            nick,
+            is_server,
+        })
+    }
+}
+
+impl Url {
+    /// Settings for connecting to this URL. A configured network with the
+    /// host's name is used as it is, otherwise the URL says how to connect.
+    pub fn settings(&self, config: &Config) -> Result<Settings, String> {
+        let network = !self.is_server && config.networks.contains_key(&self.host);
+        let mut settings = if network {
+            config.settings(Some(&self.host))?
+        } else {
+            config.server(&self.host)
+        };
+        if !network || self.port.is_some() {
+            let default = if self.tls {
+                DEFAULT_TLS_PORT
+            } else {
+                DEFAULT_PORT
+            };
+            settings.tls = self.tls;
+            settings.port = self.port.unwrap_or(default);
+        }
+        if !self.channels.is_empty() {
+            settings.autojoin = self.channels.clone();
+        }
+        settings.query = self.nick.clone();
+        Ok(settings)
+    }
+}
+
+/// Decodes `%XX` escapes, mostly `%23` for `#`, leaving malformed ones be.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str, key: Option<&str>) -> (String, Option<String>) {
+        (name.to_string(), key.map(|k| k.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        let url: Url = "ircs://irc.example.org:6697/#ops,needkey?key"
+            .parse()
+            .unwrap();
+        assert_eq!(url.host, "irc.example.org");
+        assert_eq!((url.port, url.tls), (Some(6697), true));
+        assert_eq!(url.channels, vec![channel("#ops", Some("key"))]);
+
+        let url: Url = "irc://irc.example.org/%23rust,yairc?key=,s%2Cecret"
+            .parse()
+            .unwrap();
+        assert_eq!((url.port, url.tls), (None, false));
+        assert_eq!(
+            url.channels,
+            vec![channel("#rust", None), channel("#yairc", Some("s,ecret"))]
+        );
+
+        let url: Url = "irc://[::1]:6667/jon,isnick,isserver".parse().unwrap();
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.nick.as_deref(), Some("jon"));
+        assert!(url.is_server && url.channels.is_empty());
+
+        assert_eq!("irc://example.org".parse::<Url>().unwrap().channels, vec![]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for url in &[
+            "http://example.org",
+            "example.org",
+            "irc:///#rust",
+            "irc://example.org:0",
+            "irc://example.org:port",
+            "irc://[::1/",
+            "irc://example.org/#a b",
+            "irc://example.org/2jon,isnick",
+        ] {
+            assert!(url.parse::<Url>().is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_settings() {
+        let config = Config::parse(
+            r##"[networks.libera]
+host = "irc.libera.chat"
+autojoin = ["#rust"]
+"##,
+        )
+        .unwrap();
+
+        let url: Url = "irc://libera/#yairc".parse().unwrap();
+        let settings = url.settings(&config).unwrap();
+        assert_eq!(settings.host, "irc.libera.chat");
+        assert_eq!((settings.tls, settings.port), (true, 6697));
+        assert_eq!(settings.autojoin, vec![channel("#yairc", None)]);
+
+        let url: Url = "irc://libera/jon,isnick,isserver".parse().unwrap();
+        let settings = url.settings(&config).unwrap();
+        assert_eq!(settings.host, "libera");
+        assert_eq!((settings.tls, settings.port), (false, 6667));
+        assert_eq!(settings.query.as_deref(), Some("jon"));
+    }
+}
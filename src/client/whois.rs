@@ -0,0 +1,446 @@
+//! Collecting the numerics WHOIS, WHOWAS and WHO answer with into one
+//! result each.
+//!
+//! <https://modern.ircdocs.horse/#whois-message>
+//! <https://ircv3.net/specs/extensions/whox>
+
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::message::{Command, Message};
+
+/// The WHOX fields we ask for: token, channel, user, host, nick, flags,
+/// account and realname. Servers send them in this order whatever order
+/// they're asked for in, which is why it's this one.
+const WHOX_FIELDS: &str = "tcuhnfar";
+/// Tells our WHOX replies apart from anyone else's.
+const WHOX_TOKEN: &str = "47";
+
+const RPL_AWAY: &str = "301";
+const RPL_WHOISUSER: &str = "311";
+const RPL_WHOISSERVER: &str = "312";
+const RPL_WHOISOPERATOR: &str = "313";
+const RPL_WHOWASUSER: &str = "314";
+const RPL_ENDOFWHO: &str = "315";
+const RPL_WHOISIDLE: &str = "317";
+const RPL_ENDOFWHOIS: &str = "318";
+const RPL_WHOISCHANNELS: &str = "319";
+const RPL_WHOISACCOUNT: &str = "330";
+const RPL_WHOISACTUALLY: &str = "338";
+const RPL_WHOREPLY: &str = "352";
+const RPL_WHOSPCRPL: &str = "354";
+const RPL_ENDOFWHOWAS: &str = "369";
+const ERR_NOSUCHNICK: &str = "401";
+const ERR_NOSUCHSERVER: &str = "402";
+const ERR_WASNOSUCHNICK: &str = "406";
+const RPL_WHOISSECURE: &str = "671";
+
+/// Other numerics servers send in answer to WHOIS, like RPL_WHOISHOST and
+/// RPL_WHOISBOT, shown as they come.
+const RPL_WHOIS_OTHER: &[&str] = &["276", "307", "310", "320", "335", "378", "379"];
+
+/// What a WHOIS or WHOWAS says about someone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WhoisInfo {
+    pub nick: String,
+    pub user: Option<String>,
+    pub host: Option<String>,
+    pub realname: Option<String>,
+    /// The server they're on, or were on for WHOWAS, and what it says
+    /// about itself.
+    pub server: Option<(String, String)>,
+    /// The services account they're logged in to.
+    pub account: Option<String>,
+    /// The channels they're in, with their status in each like `@#rust`.
+    pub channels: Vec<String>,
+    /// How many seconds they've been idle.
+    pub idle: Option<u64>,
+    pub signon: Option<DateTime<Utc>>,
+    pub away: Option<String>,
+    /// What the server says about them being an operator.
+    pub operator: Option<String>,
+    /// Whether they're connected with TLS.
+    pub secure: bool,
+    /// The host or IP they're really connecting from, when we may see it.
+    pub actually: Option<String>,
+    /// Whatever else the server said, one line each.
+    pub other: Vec<String>,
+}
+
+/// Someone matching a WHO.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WhoReply {
+    /// A channel they're in, if the server picked one.
+    pub channel: Option<String>,
+    pub user: String,
+    pub host: String,
+    pub nick: String,
+    /// `H` or `G` for here or gone, then `*` for operators and their status
+    /// in `channel` like `@`.
+    pub flags: String,
+    /// Only known from WHOX.
+    pub account: Option<String>,
+    pub realname: String,
+}
+
+impl WhoReply {
+    pub fn is_away(&self) -> bool {
+        self.flags.starts_with('G')
+    }
+}
+
+/// A complete answer to a WHOIS, WHOWAS or WHO.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lookup {
+    Whois(Box<WhoisInfo>),
+    /// Newest first, servers remember a few.
+    Whowas(Vec<WhoisInfo>),
+    Who(String, Vec<WhoReply>),
+    /// The server knows nothing of the nick, and says why.
+    NotFound(String, String),
+}
+
+/// A WHOIS or WHOWAS still being answered.
+#[derive(Debug)]
+struct Asked {
+    was: bool,
+    entries: Vec<WhoisInfo>,
+    error: Option<String>,
+}
+
+impl Asked {
+    /// What we're learning about `nick`, the latest of it for WHOWAS.
+    fn entry(&mut self, nick: &str) -> &mut WhoisInfo {
+        if self.entries.is_empty() {
+            self.entries.push(WhoisInfo {
+                nick: nick.to_string(),
+                ..WhoisInfo::default()
+            });
+        }
+        self.entries.last_mut().unwrap()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Whois {
+    /// WHOIS and WHOWAS we've sent and not seen the end of, by lowercased
+    /// nick.
+    asked: BTreeMap<String, Asked>,
+    /// WHOs we've sent, in order, with the replies so far. Their replies
+    /// don't say which WHO they're for, but servers answer in order.
+    who: VecDeque<(String, Vec<WhoReply>)>,
+    finished: Vec<Lookup>,
+}
+
+impl Whois {
+    pub fn whois(&mut self, nick: &str) -> Message {
+        self.ask(nick, false);
+        Message::new(Command::Whois, vec![nick])
+    }
+
+    pub fn whowas(&mut self, nick: &str) -> Message {
+        self.ask(nick, true);
+        Message::new(Command::Whowas, vec![nick])
+    }
+
+    fn ask(&mut self, nick: &str, was: bool) {
+        let asked = Asked {
+            was,
+            entries: Vec::new(),
+            error: None,
+        };
+        self.asked.insert(nick.to_lowercase(), asked);
+    }
+
+    /// Asks who matches `mask`, with WHOX when the server has it.
+    pub fn who(&mut self, mask: &str, whox: bool) -> Message {
+        self.who.push_back((mask.to_string(), Vec::new()));
+        let mut params = vec![mask.to_string()];
+        if whox {
+            params.push(format!("%{},{}", WHOX_FIELDS, WHOX_TOKEN));
+        }
+        Message::new(Command::Who, vec![]).with_params(params)
+    }
+
+    /// Collects a reply to something we asked. Returns true if it was one,
+    /// the whole answer being in `finished` once it's all in.
+    pub fn handle(&mut self, message: &Message) -> bool {
+        let command = message.command().to_string();
+        let param = |i| {
+            message
+                .get_param(i)
+                .map(|p| p.to_string())
+                .unwrap_or_default()
+        };
+        if let Some((_, replies)) = self.who.front_mut() {
+            let reply = match command.as_str() {
+                RPL_WHOREPLY => Some(who_reply(message)),
+                RPL_WHOSPCRPL if param(1) == WHOX_TOKEN => Some(whox_reply(message)),
+                _ => None,
+            };
+            if let Some(reply) = reply {
+                replies.push(reply);
+                return true;
+            }
+            if command == RPL_ENDOFWHO {
+                if let Some((mask, replies)) = self.who.pop_front() {
+                    self.finished.push(Lookup::Who(mask, replies));
+                }
+                return true;
+            }
+        }
+
+        // <client> <nick> ...
+        let nick = param(1);
+        let asked = match self.asked.get_mut(&nick.to_lowercase()) {
+            Some(asked) => asked,
+            None => return false,
+        };
+        let last = message.params().last().cloned().unwrap_or_default();
+        match command.as_str() {
+            RPL_WHOISUSER | RPL_WHOWASUSER => {
+                // <client> <nick> <user> <host> * :<realname>, WHOWAS
+                // repeating it for each time it remembers
+                asked.entries.push(WhoisInfo {
+                    nick: nick.clone(),
+                    user: Some(param(2)),
+                    host: Some(param(3)),
+                    realname: Some(param(5)),
+                    ..WhoisInfo::default()
+                });
+            }
+            RPL_WHOISSERVER => asked.entry(&nick).server = Some((param(2), param(3))),
+            RPL_WHOISOPERATOR => asked.entry(&nick).operator = Some(last),
+            RPL_WHOISIDLE => {
+                // <client> <nick> <secs> <signon> :seconds idle, signon time
+                let info = asked.entry(&nick);
+                info.idle = param(2).parse().ok();
+                let signon = param(3).parse().ok();
+                info.signon = signon.and_then(|s| Utc.timestamp_opt(s, 0).single());
+            }
+            RPL_WHOISCHANNELS => {
+                let channels = param(2);
+                let info = asked.entry(&nick);
+                info.channels
+                    .extend(channels.split_whitespace().map(|c| c.to_string()));
+            }
+            RPL_WHOISACCOUNT => asked.entry(&nick).account = Some(param(2)),
+            RPL_WHOISSECURE => asked.entry(&nick).secure = true,
+            RPL_AWAY => asked.entry(&nick).away = Some(last),
+            RPL_WHOISACTUALLY => {
+                // <client> <nick> [<host>|<ip>] :is actually using host
+                let info = asked.entry(&nick);
+                info.actually = message.get_param(3).map(|_| param(2));
+            }
+            ERR_NOSUCHNICK | ERR_NOSUCHSERVER | ERR_WASNOSUCHNICK => asked.error = Some(last),
+            RPL_ENDOFWHOIS | RPL_ENDOFWHOWAS => {
+                let asked = self.asked.remove(&nick.to_lowercase()).unwrap();
+                self.finished.push(match (asked.was, asked.error) {
+                    (_, Some(error)) if asked.entries.is_empty() => Lookup::NotFound(nick, error),
+                    (true, _) => Lookup::Whowas(asked.entries),
+                    (false, _) => match asked.entries.into_iter().next() {
+                        Some(info) => Lookup::Whois(Box::new(info)),
+                        None => Lookup::NotFound(nick, last),
+                    },
+                });
+            }
+            other if RPL_WHOIS_OTHER.contains(&other) => {
+                let params = message.params();
+                let text = params.get(2..).unwrap_or_default().join(" ");
+                asked.entry(&nick).other.push(text);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Answers that have all come in since last asked.
+    pub fn finished(&mut self) -> Vec<Lookup> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Answers still coming won't after a reconnect.
+    pub fn disconnected(&mut self) {
+        self.asked.clear();
+        self.who.clear();
+    }
+}
+
+/// RPL_WHOREPLY: <client> <channel> <user> <host> <server> <nick> <flags>
+/// :<hopcount> <realname>
+fn who_reply(message: &Message) -> WhoReply {
+    let params = message.params();
+    let param = |i: usize| params.get(i).cloned().unwrap_or_default();
+    let last = param(7);
+    let realname = last.split_once(' ').map(|(_, r)| r).unwrap_or_default();
+    WhoReply {
+        channel: Some(param(1)).filter(|c| c != "*"),
+        user: param(2),
+        host: param(3),
+        nick: param(5),
+        flags: param(6),
+        account: None,
+        realname: realname.to_string(),
+    }
+}
+
+/// RPL_WHOSPCRPL: <client> followed by the fields we asked for.
+fn whox_reply(message: &Message) -> WhoReply {
+    let params = message.params();
+    let mut reply = WhoReply::default();
+    for (field, value) in WHOX_FIELDS.chars().zip(params.into_iter().skip(1)) {
+        match field {
+            'c' => reply.channel = Some(value).filter(|c| c != "*"),
+            'u' => reply.user = value,
+            'h' => reply.host = value,
+            'n' => reply.nick = value,
+            'f' => reply.flags = value,
+            // 0 means logged out
+            'a' => reply.account = Some(value).filter(|a| a != "0"),
+            'r' => reply.realname = value,
+            _ => {}
+        }
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn handle(whois: &mut Whois, lines: &str) {
+        for line in lines.lines() {
+            let message = parse_line(line.trim());
+            assert!(whois.handle(&message), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_whois() {
+        let mut whois = Whois::default();
+        let sent = whois.whois("Alice");
+        assert_eq!(sent.as_bytes(), b"WHOIS Alice\r\n");
+        handle(
+            &mut whois,
+            ":irc 311 me alice ~a host.example * :Alice Liddell
+             :irc 319 me alice :@#rust +#ops
+             :irc 312 me alice irc.example.com :Example server
+             :irc 301 me alice :gone fishing
+             :irc 313 me alice :is an IRC operator
+             :irc 330 me alice alice_ :is logged in as
+             :irc 671 me alice :is using a secure connection
+             :irc 338 me alice 192.0.2.1 :is actually using host
+             :irc 378 me alice :is connecting from *@host.example 192.0.2.1
+             :irc 317 me alice 65 1760000000 :seconds idle, signon time",
+        );
+        assert!(whois.finished().is_empty());
+        handle(&mut whois, ":irc 318 me alice :End of /WHOIS list.");
+
+        let info = match whois.finished().pop() {
+            Some(Lookup::Whois(info)) => info,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(info.nick, "alice");
+        assert_eq!(info.user.as_deref(), Some("~a"));
+        assert_eq!(info.realname.as_deref(), Some("Alice Liddell"));
+        assert_eq!(info.channels, vec!["@#rust", "+#ops"]);
+        let server = ("irc.example.com".to_string(), "Example server".to_string());
+        assert_eq!(info.server, Some(server));
+        assert_eq!(info.away.as_deref(), Some("gone fishing"));
+        assert_eq!(info.operator.as_deref(), Some("is an IRC operator"));
+        assert_eq!(info.account.as_deref(), Some("alice_"));
+        assert!(info.secure);
+        assert_eq!(info.actually.as_deref(), Some("192.0.2.1"));
+        assert_eq!(
+            info.other,
+            vec!["is connecting from *@host.example 192.0.2.1"]
+        );
+        assert_eq!(info.idle, Some(65));
+        assert_eq!(info.signon.unwrap().timestamp(), 1760000000);
+
+        // Once it's over, an away reply is for a message we sent
+        let away = parse_line(":irc 301 me alice :gone fishing");
+        assert!(!whois.handle(&away));
+    }
+
+    #[test]
+    fn test_not_found() {
+        let mut whois = Whois::default();
+        whois.whois("nobody");
+        handle(
+            &mut whois,
+            ":irc 401 me nobody :No such nick/channel
+             :irc 318 me nobody :End of /WHOIS list.",
+        );
+        assert_eq!(
+            whois.finished(),
+            vec![Lookup::NotFound(
+                "nobody".to_string(),
+                "No such nick/channel".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_whowas() {
+        let mut whois = Whois::default();
+        whois.whowas("bob");
+        handle(
+            &mut whois,
+            ":irc 314 me bob ~b old.example * :Bob
+             :irc 312 me bob irc.example.com :Sat Oct 17 10:00:00 2026
+             :irc 314 me bob ~b older.example * :Bob
+             :irc 369 me bob :End of WHOWAS",
+        );
+        let entries = match whois.finished().pop() {
+            Some(Lookup::Whowas(entries)) => entries,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].host.as_deref(), Some("old.example"));
+        assert!(entries[0].server.is_some());
+        assert_eq!(entries[1].host.as_deref(), Some("older.example"));
+    }
+
+    #[test]
+    fn test_who() {
+        let mut whois = Whois::default();
+        assert_eq!(whois.who("#rust", false).as_bytes(), b"WHO #rust\r\n");
+        assert_eq!(
+            whois.who("#ops", true).as_bytes(),
+            b"WHO #ops %tcuhnfar,47\r\n"
+        );
+        handle(
+            &mut whois,
+            ":irc 352 me #rust ~a host.example irc.example.com alice H@ :0 Alice Liddell
+             :irc 352 me * ~b b.example irc.example.com bob G :2 Bob
+             :irc 315 me #rust :End of WHO list
+             :irc 354 me 47 #ops ~a host.example alice H*@ alice_ :Alice Liddell
+             :irc 354 me 47 #ops ~c c.example carol H 0 :Carol
+             :irc 315 me #ops :End of WHO list",
+        );
+        let finished = whois.finished();
+        let (mask, replies) = match &finished[0] {
+            Lookup::Who(mask, replies) => (mask, replies),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(mask, "#rust");
+        assert_eq!(replies[0].channel.as_deref(), Some("#rust"));
+        assert_eq!(replies[0].realname, "Alice Liddell");
+        assert_eq!(replies[0].flags, "H@");
+        assert_eq!(replies[1].channel, None);
+        assert!(replies[1].is_away());
+
+        let replies = match &finished[1] {
+            Lookup::Who(_, replies) => replies,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(replies[0].nick, "alice");
+        assert_eq!(replies[0].account.as_deref(), Some("alice_"));
+        assert_eq!(replies[0].realname, "Alice Liddell");
+        assert_eq!(replies[1].account, None);
+        assert!(!replies[1].is_away());
+    }
+}
@@ -0,0 +1,847 @@
+//! The config file, `$XDG_CONFIG_HOME/yairc/config.toml` by default.
+//!
+//! ```toml
+//! default_network = "libera"
+//!
+//! [identities.default]
+//! nick = "jon"
+//! alternates = ["jon_", "jon__"]
+//! realname = "Jon"
+//!
+//! [networks.libera]
+//! host = "irc.libera.chat"
+//! autojoin = ["#rust", { channel = "#ops", key = "secret" }]
+//! notify = ["alice"]
+//! sasl = { username = "jon", password = "hunter2" }
+//! ```
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use toml::Spanned;
+use toml_edit::{Array, DocumentMut};
+
+use crate::client::{self, connection, queue, state};
+
+pub const DEFAULT_TLS_PORT: u16 = 6697;
+pub const DEFAULT_PORT: u16 = 6667;
+pub const DEFAULT_IDENT_PORT: u16 = 113;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The network to connect to when none is given on the command line.
+    pub default_network: Option<Spanned<String>>,
+    /// Timestamps are shown and logged in this time zone.
+    pub timezone: Zone,
+    pub networks: BTreeMap<String, Network>,
+    pub identities: BTreeMap<String, Identity>,
+    pub ui: Ui,
+    pub flood: Flood,
+    pub ping: Ping,
+    pub dcc: Dcc,
+    pub ident: Ident,
+    pub log: Log,
+    pub search: Search,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Network {
+    pub host: String,
+    /// 6697 with TLS, 6667 without.
+    pub port: Option<Spanned<u16>>,
+    #[serde(default = "default_true")]
+    pub tls: bool,
+    /// Sent with PASS when registering.
+    pub password: Option<String>,
+    /// For servers that let a trusted gateway pass on the real address of
+    /// the user it connects for.
+    pub webirc: Option<Spanned<WebIrc>>,
+    pub sasl: Option<Spanned<Sasl>>,
+    /// Which of the `identities` to use, `default` if there is one.
+    pub identity: Option<Spanned<String>>,
+    #[serde(default)]
+    pub autojoin: Vec<Spanned<AutoJoin>>,
+    /// Who to tell us about when they come online or go offline.
+    #[serde(default)]
+    pub notify: Vec<Spanned<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Sasl {
+    #[serde(default = "default_mechanism")]
+    pub mechanism: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+/// Sent as `WEBIRC <password> <gateway> <hostname> <ip>` before anything
+/// else.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WebIrc {
+    pub password: String,
+    pub gateway: String,
+    pub hostname: String,
+    pub ip: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum AutoJoin {
+    Channel(String),
+    WithKey { channel: String, key: String },
+}
+
+impl AutoJoin {
+    fn channel(&self) -> &str {
+        match self {
+            AutoJoin::Channel(channel) | AutoJoin::WithKey { channel, .. } => channel,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Identity {
+    pub nick: Option<Spanned<String>>,
+    /// Tried in order when the nick is taken.
+    pub alternates: Vec<Spanned<String>>,
+    /// Sent in USER, the nick by default. Servers put `~` in front of it
+    /// unless ident confirms it.
+    pub username: Option<Spanned<String>>,
+    pub realname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ui {
+    /// Show formatting and colors in messages, as if `NO_COLOR` was set
+    /// when false.
+    pub colors: bool,
+    /// Ask before sending a paste of more than one line.
+    pub confirm_paste: bool,
+    /// Shown in front of messages, in strftime format. Empty to show none.
+    pub timestamp: Spanned<String>,
+    /// Tell others when we're typing, on servers that pass it on.
+    pub typing: bool,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self {
+            colors: true,
+            confirm_paste: true,
+            timestamp: Spanned::new(0..0, "%H:%M".to_string()),
+            typing: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flood {
+    pub burst: Spanned<u32>,
+    pub rate: Spanned<f64>,
+}
+
+impl Default for Flood {
+    fn default() -> Self {
+        Self {
+            burst: Spanned::new(0..0, queue::DEFAULT_BURST),
+            rate: Spanned::new(0..0, queue::DEFAULT_RATE),
+        }
+    }
+}
+
+/// Noticing a connection that has gone quiet, in seconds.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ping {
+    /// Silence for this long gets a PING from us.
+    pub interval: Spanned<u64>,
+    /// No answer within this long counts as a dropped connection.
+    pub timeout: Spanned<u64>,
+}
+
+impl Default for Ping {
+    fn default() -> Self {
+        Self {
+            interval: Spanned::new(0..0, connection::DEFAULT_PING_INTERVAL.as_secs()),
+            timeout: Spanned::new(0..0, connection::DEFAULT_PING_TIMEOUT.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dcc {
+    pub download_dir: Option<PathBuf>,
+}
+
+/// Chat logs on disk, one file per network, buffer and day.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    pub enabled: bool,
+    /// `$XDG_DATA_HOME/yairc/logs` by default.
+    pub dir: Option<PathBuf>,
+    pub format: LogFormat,
+    /// In front of each line of the plain and weechat formats.
+    pub timestamp: Spanned<String>,
+    /// Gzip each day's log once the day is over.
+    pub compress: bool,
+    /// Settings for single buffers, by name or as `network/name`. The
+    /// server's own messages are in the `server` buffer.
+    pub buffers: BTreeMap<String, BufferLog>,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            format: LogFormat::Plain,
+            timestamp: Spanned::new(0..0, "%Y-%m-%d %H:%M:%S".to_string()),
+            compress: false,
+            buffers: BTreeMap::new(),
+        }
+    }
+}
+
+/// The message store behind /search.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Search {
+    pub enabled: bool,
+    /// `$XDG_DATA_HOME/yairc/search.db` by default.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[2026-01-01 12:00:00] <jon> hello`
+    Plain,
+    /// Tab separated like WeeChat's, for tools that read those.
+    Weechat,
+    /// One JSON object per line, with the message's tags.
+    Jsonl,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BufferLog {
+    pub enabled: Option<bool>,
+    pub format: Option<LogFormat>,
+}
+
+/// `local` for the system's time zone, otherwise a name from the tz
+/// database like `UTC` or `Europe/Berlin`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Zone {
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn convert(&self, time: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Local => time.with_timezone(&Local).fixed_offset(),
+            Zone::Named(tz) => time.with_timezone(tz).fixed_offset(),
+        }
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        match name.parse() {
+            Ok(tz) => Ok(Zone::Named(tz)),
+            Err(_) => Err(format!("Unknown time zone {}", name)),
+        }
+    }
+}
+
+/// Whether chrono can format times with `format`, it panics on ones it
+/// can't.
+fn is_time_format(format: &str) -> bool {
+    StrftimeItems::new(format).all(|item| item != Item::Error)
+}
+
+/// Answers ident (RFC 1413) queries about our connection with the
+/// username, so servers show it without a `~`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ident {
+    pub enabled: bool,
+    /// Binding 113 needs privileges, a higher port can be forwarded to.
+    pub port: Spanned<u16>,
+}
+
+impl Default for Ident {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: Spanned::new(0..0, DEFAULT_IDENT_PORT),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_mechanism() -> String {
+    "PLAIN".to_string()
+}
+
+/// A problem with the config file, at a position in it when there is one.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Error {
+    fn at(source: &str, span: Range<usize>, message: &str) -> Self {
+        let before = &source[..span.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        Self {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Where the config file is looked for.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("yairc").join("config.toml"))
+}
+
+/// Where logs go unless the config says otherwise.
+pub fn default_log_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("logs"))
+}
+
+pub fn default_search_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("search.db"))
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("share"),
+    };
+    Some(base.join("yairc"))
+}
+
+/// Writes `nicks` as `network`'s notify list in the config at `path`.
+pub fn save_notify(path: &Path, network: &str, nicks: &[&str]) -> io::Result<()> {
+    let source = fs::read_to_string(path)?;
+    let source = set_notify(&source, network, nicks)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, source)
+}
+
+/// `source` with `network`'s notify list replaced by `nicks`, keeping the
+/// rest of it, comments included, as it was.
+fn set_notify(source: &str, network: &str, nicks: &[&str]) -> Result<String, String> {
+    let mut document: DocumentMut = source.parse().map_err(|e| format!("{}", e))?;
+    let table = document
+        .get_mut("networks")
+        .and_then(|networks| networks.get_mut(network))
+        .and_then(|network| network.as_table_like_mut())
+        .ok_or_else(|| format!("{} isn't a network in the config", network))?;
+    let nicks: Array = nicks.iter().copied().collect();
+    table.insert("notify", toml_edit::value(nicks));
+    Ok(document.to_string())
+}
+
+impl Config {
+    /// Reads the config at `path`. A missing file is an empty config.
+    pub fn load(path: &Path) -> io::Result<(Self, String)> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok((Self::default(), String::new()))
+            }
+            Err(e) => return Err(e),
+        };
+        match Self::parse(&source) {
+            Ok(config) => Ok((config, source)),
+            Err(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                let message = format!("{}: {}", path.display(), errors.join("; "));
+                Err(io::Error::new(io::ErrorKind::InvalidData, message))
+            }
+        }
+    }
+
+    /// Parses and validates a config, returning every problem found.
+    pub fn parse(source: &str) -> Result<Self, Vec<Error>> {
+        let config: Config = toml::from_str(source).map_err(|e| {
+            let span = e.span().unwrap_or(0..0);
+            vec![Error::at(source, span, e.message())]
+        })?;
+        let errors = config.validate(source);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate(&self, source: &str) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut error =
+            |span: Range<usize>, message: String| errors.push(Error::at(source, span, &message));
+
+        if let Some(name) = &self.default_network {
+            if !self.networks.contains_key(name.get_ref()) {
+                error(name.span(), format!("No network called {}", name.get_ref()));
+            }
+        }
+        for network in self.networks.values() {
+            if let Some(port) = &network.port {
+                if *port.get_ref() == 0 {
+                    error(port.span(), "Port can't be 0".to_string());
+                }
+            }
+            if let Some(identity) = &network.identity {
+                if !self.identities.contains_key(identity.get_ref()) {
+                    error(
+                        identity.span(),
+                        format!("No identity called {}", identity.get_ref()),
+                    );
+                }
+            }
+            if let Some(webirc) = &network.webirc {
+                let words = [&webirc.get_ref().gateway, &webirc.get_ref().hostname];
+                if words.iter().any(|w| w.is_empty() || w.contains(' ')) {
+                    error(
+                        webirc.span(),
+                        "WEBIRC gateway and hostname must be single words".to_string(),
+                    );
+                }
+                if webirc.get_ref().ip.parse::<IpAddr>().is_err() {
+                    error(
+                        webirc.span(),
+                        format!("{} is not an IP address", webirc.get_ref().ip),
+                    );
+                }
+            }
+            if let Some(sasl) = &network.sasl {
+                match sasl.get_ref().mechanism.to_uppercase().as_str() {
+                    "PLAIN" if sasl.get_ref().password.is_empty() => {
+                        error(sasl.span(), "SASL PLAIN needs a password".to_string())
+                    }
+                    "PLAIN" => {}
+                    other => error(sasl.span(), format!("Unsupported SASL mechanism {}", other)),
+                }
+            }
+            for autojoin in &network.autojoin {
+                let channel = autojoin.get_ref().channel();
+                if !state::is_channel(channel) {
+                    error(
+                        autojoin.span(),
+                        format!("{} is not a channel name", channel),
+                    );
+                }
+            }
+            for nick in &network.notify {
+                if !state::is_nick(nick.get_ref()) {
+                    error(nick.span(), format!("{:?} is not a nick", nick.get_ref()));
+                }
+            }
+        }
+        for identity in self.identities.values() {
+            if let Some(username) = &identity.username {
+                if !state::is_username(username.get_ref()) {
+                    error(
+                        username.span(),
+                        format!("{:?} is not a username", username.get_ref()),
+                    );
+                }
+            }
+            for nick in identity.nick.iter().chain(identity.alternates.iter()) {
+                if !state::is_nick(nick.get_ref()) {
+                    error(nick.span(), format!("{:?} is not a nick", nick.get_ref()));
+                }
+            }
+        }
+        if *self.ident.port.get_ref() == 0 {
+            error(self.ident.port.span(), "Port can't be 0".to_string());
+        }
+        if *self.flood.burst.get_ref() == 0 {
+            error(
+                self.flood.burst.span(),
+                "Burst must be at least 1".to_string(),
+            );
+        }
+        if *self.flood.rate.get_ref() <= 0.0 {
+            error(
+                self.flood.rate.span(),
+                "Rate must be more than 0".to_string(),
+            );
+        }
+        for seconds in [&self.ping.interval, &self.ping.timeout] {
+            if *seconds.get_ref() == 0 {
+                error(seconds.span(), "Must be at least 1 second".to_string());
+            }
+        }
+        for format in [&self.ui.timestamp, &self.log.timestamp] {
+            if !is_time_format(format.get_ref()) {
+                error(
+                    format.span(),
+                    format!("{:?} is not a strftime format", format.get_ref()),
+                );
+            }
+        }
+        errors
+    }
+
+    /// What to connect to for `network`, a name from the config or a host.
+    /// Without one the `default_network`, or the only network, is used.
+    pub fn settings(&self, network: Option<&str>) -> Result<Settings, String> {
+        let name = match network {
+            Some(name) => name.to_string(),
+            None => match (&self.default_network, self.networks.keys().next()) {
+                (Some(name), _) => name.get_ref().clone(),
+                (None, Some(name)) if self.networks.len() == 1 => name.clone(),
+                _ => {
+                    return Err("No network given, and no default_network in the config".to_string())
+                }
+            },
+        };
+        let network = match self.networks.get(&name) {
+            Some(network) => network,
+            None => return Ok(self.server(&name)),
+        };
+        let identity = network
+            .identity
+            .as_ref()
+            .map_or("default", |i| i.get_ref().as_str());
+        let mut settings = self.with_identity(&name, identity);
+
+        settings.host = network.host.clone();
+        settings.tls = network.tls;
+        settings.port = match &network.port {
+            Some(port) => *port.get_ref(),
+            None if network.tls => DEFAULT_TLS_PORT,
+            None => DEFAULT_PORT,
+        };
+        settings.password = network.password.clone();
+        settings.webirc = network.webirc.as_ref().map(|w| w.get_ref().clone());
+        settings.sasl = network.sasl.as_ref().map(|s| s.get_ref().clone());
+        settings.autojoin = network
+            .autojoin
+            .iter()
+            .map(|a| match a.get_ref() {
+                AutoJoin::Channel(channel) => (channel.clone(), None),
+                AutoJoin::WithKey { channel, key } => (channel.clone(), Some(key.clone())),
+            })
+            .collect();
+        settings.notify = network.notify.iter().map(|n| n.get_ref().clone()).collect();
+        Ok(settings)
+    }
+
+    /// Settings for a server that isn't one of the configured networks,
+    /// using the default identity.
+    pub fn server(&self, host: &str) -> Settings {
+        self.with_identity(host, "default")
+    }
+
+    fn with_identity(&self, name: &str, identity: &str) -> Settings {
+        let mut settings = Settings::new(name);
+        if let Some(identity) = self.identities.get(identity) {
+            if let Some(nick) = &identity.nick {
+                settings.nick = nick.get_ref().clone();
+            }
+            settings.alternates = identity
+                .alternates
+                .iter()
+                .map(|n| n.get_ref().clone())
+                .collect();
+            settings.username = identity
+                .username
+                .as_ref()
+                .map_or_else(|| settings.nick.clone(), |u| u.get_ref().clone());
+            settings.realname = identity
+                .realname
+                .clone()
+                .unwrap_or_else(|| settings.nick.clone());
+        }
+        settings.burst = *self.flood.burst.get_ref();
+        settings.rate = *self.flood.rate.get_ref();
+        settings.ping_interval = Duration::from_secs(*self.ping.interval.get_ref());
+        settings.ping_timeout = Duration::from_secs(*self.ping.timeout.get_ref());
+        settings
+    }
+}
+
+/// Everything needed to connect to one network, from the config and the
+/// command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// The network's name in the config, or its host.
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub password: Option<String>,
+    pub webirc: Option<WebIrc>,
+    pub sasl: Option<Sasl>,
+    pub nick: String,
+    pub alternates: Vec<String>,
+    pub username: String,
+    pub realname: String,
+    /// Channels to join with their keys.
+    pub autojoin: Vec<(String, Option<String>)>,
+    /// Who to tell us about when they come online or go offline.
+    pub notify: Vec<String>,
+    /// Who to open a query with once connected.
+    pub query: Option<String>,
+    pub burst: u32,
+    pub rate: f64,
+    pub ping_interval: Duration,
+    pub ping_timeout: Duration,
+}
+
+impl Settings {
+    /// Settings for connecting to `host` over TLS as the local user.
+    pub fn new(host: &str) -> Self {
+        let nick = env::var("USER").unwrap_or_else(|_| "yairc".to_string());
+        Self {
+            name: host.to_string(),
+            host: host.to_string(),
+            port: DEFAULT_TLS_PORT,
+            tls: true,
+            password: None,
+            webirc: None,
+            sasl: None,
+            username: nick.clone(),
+            realname: nick.clone(),
+            nick,
+            alternates: Vec::new(),
+            autojoin: Vec::new(),
+            notify: Vec::new(),
+            query: None,
+            burst: queue::DEFAULT_BURST,
+            rate: queue::DEFAULT_RATE,
+            ping_interval: connection::DEFAULT_PING_INTERVAL,
+            ping_timeout: connection::DEFAULT_PING_TIMEOUT,
+        }
+    }
+
+    /// The buffer to show first: the query, the first channel or the
+    /// server's own.
+    pub fn buffer(&self) -> String {
+        match (&self.query, self.autojoin.first()) {
+            (Some(nick), _) => nick.clone(),
+            (None, Some((channel, _))) => client::channel_name(channel),
+            (None, None) => self.host.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r##"
+default_network = "libera"
+
+[identities.default]
+nick = "jon"
+alternates = ["jon_"]
+realname = "Jon"
+
+[networks.libera]
+host = "irc.libera.chat"
+autojoin = ["#rust", { channel = "#ops", key = "secret" }]
+notify = ["alice", "bob"]
+sasl = { username = "jon", password = "hunter2" }
+
+[networks.local]
+host = "localhost"
+tls = false
+webirc = { password = "pw", gateway = "kiwi", hostname = "host.example", ip = "192.0.2.1" }
+
+[flood]
+burst = 4
+
+[ping]
+interval = 300
+
+[log]
+format = "jsonl"
+buffers = { "#spam" = { enabled = false } }
+"##;
+
+    #[test]
+    fn test_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+        let settings = config.settings(None).unwrap();
+        assert_eq!(settings.host, "irc.libera.chat");
+        assert_eq!(settings.port, 6697);
+        assert_eq!(settings.nick, "jon");
+        assert_eq!(settings.username, "jon");
+        assert_eq!(settings.realname, "Jon");
+        assert_eq!(settings.alternates, vec!["jon_"]);
+        assert_eq!(settings.sasl.unwrap().mechanism, "PLAIN");
+        assert_eq!(
+            settings.autojoin,
+            vec![
+                ("#rust".to_string(), None),
+                ("#ops".to_string(), Some("secret".to_string()))
+            ]
+        );
+        assert_eq!(settings.notify, vec!["alice", "bob"]);
+        assert_eq!(settings.burst, 4);
+        assert_eq!(settings.rate, queue::DEFAULT_RATE);
+        assert_eq!(settings.ping_interval, Duration::from_secs(300));
+        assert_eq!(settings.ping_timeout, connection::DEFAULT_PING_TIMEOUT);
+        assert_eq!(config.log.format, LogFormat::Jsonl);
+        assert_eq!(config.log.buffers["#spam"].enabled, Some(false));
+
+        let local = config.settings(Some("local")).unwrap();
+        assert_eq!((local.port, local.tls), (6667, false));
+        assert_eq!(local.webirc.unwrap().gateway, "kiwi");
+
+        // Anything else is a host
+        let other = config.settings(Some("irc.example.com")).unwrap();
+        assert_eq!(other.host, "irc.example.com");
+        assert_eq!(other.nick, "jon");
+    }
+
+    #[test]
+    fn test_set_notify() {
+        let source = set_notify(CONFIG, "libera", &["bob", "carol"]).unwrap();
+        assert!(source.contains("autojoin = [\"#rust\", { channel = \"#ops\", key = \"secret\" }]"));
+        let config = Config::parse(&source).unwrap();
+        assert_eq!(config.settings(None).unwrap().notify, vec!["bob", "carol"]);
+
+        let source = "[networks.oftc]\n# Friends\nhost = \"irc.oftc.net\"\n";
+        assert_eq!(
+            set_notify(source, "oftc", &["alice"]).unwrap(),
+            "[networks.oftc]\n# Friends\nhost = \"irc.oftc.net\"\nnotify = [\"alice\"]\n"
+        );
+        assert!(set_notify(CONFIG, "irc.example.com", &["alice"]).is_err());
+    }
+
+    #[test]
+    fn test_no_network() {
+        let config = Config::default();
+        assert!(config.settings(None).is_err());
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        let errors = Config::parse("[ui]\ncolors = maybe\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 10));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let errors = Config::parse("[ui]\ncolours = false\n").unwrap_err();
+        assert_eq!(errors[0].line, 2);
+        assert!(errors[0].message.contains("colours"));
+    }
+
+    #[test]
+    fn test_timezone() {
+        let config = Config::parse("timezone = \"Europe/Berlin\"\n").unwrap();
+        let time = DateTime::parse_from_rfc3339("2026-01-31T12:00:00Z").unwrap();
+        let time = config.timezone.convert(time.with_timezone(&Utc));
+        assert_eq!(time.to_rfc3339(), "2026-01-31T13:00:00+01:00");
+        assert_eq!(Config::default().timezone, Zone::Local);
+
+        let errors = Config::parse("timezone = \"Mars/Olympus\"\n").unwrap_err();
+        assert_eq!(errors[0].line, 1);
+        assert!(errors[0].message.contains("Unknown time zone Mars/Olympus"));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let source = r##"default_network = "efnet"
+
+[networks.libera]
+host = "irc.libera.chat"
+identity = "work"
+autojoin = ["rust"]
+sasl = { mechanism = "SCRAM-SHA-256" }
+webirc = { password = "pw", gateway = "kiwi", hostname = "h", ip = "nope" }
+notify = ["alice", "not a nick"]
+
+[identities.work]
+username = "jon@home"
+
+[flood]
+rate = 0
+
+[ping]
+timeout = 0
+
+[ui]
+timestamp = "%H:%"
+"##;
+        let errors = Config::parse(source).unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![1, 8, 7, 6, 9, 12, 15, 18, 21]);
+        assert_eq!(errors[0].message, "No network called efnet");
+        assert_eq!(errors[3].message, "rust is not a channel name");
+        assert_eq!(errors[4].message, "\"not a nick\" is not a nick");
+    }
+}
@@ -0,0 +1,657 @@
+//! Chat logs, one file per network, buffer and day:
+//! `<dir>/<network>/<buffer>/2026-01-01.log`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+
+use crate::client::notify::Change;
+use crate::client::state::{State, CHANNEL_PREFIXES};
+use crate::config::{self, Log, LogFormat, Zone};
+use crate::message::{format, Command, Message};
+
+/// Where messages from the server itself are logged.
+const SERVER_BUFFER: &str = "server";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub struct Logger {
+    config: Log,
+    zone: Zone,
+    dir: Option<PathBuf>,
+    network: String,
+    /// Today's file for each buffer we've logged to.
+    files: HashMap<String, (NaiveDate, File)>,
+    /// Buffers whose log couldn't be written, so the error is only shown
+    /// once.
+    failed: BTreeSet<String>,
+}
+
+impl Logger {
+    pub fn new(config: &Log, zone: Zone, network: &str) -> Self {
+        Self {
+            config: config.clone(),
+            zone,
+            dir: config.dir.clone().or_else(config::default_log_dir),
+            network: network.to_string(),
+            files: HashMap::new(),
+            failed: BTreeSet::new(),
+        }
+    }
+
+    pub fn set_network(&mut self, network: &str) {
+        self.network = network.to_string();
+        self.files.clear();
+        self.failed.clear();
+    }
+
+    /// Logs a message from the server. `state` mustn't have seen it yet, so
+    /// it still knows which channels someone quitting was in.
+    pub fn received(
+        &mut self,
+        message: &Message,
+        state: &State,
+        time: DateTime<Utc>,
+    ) -> Result<(), String> {
+        self.log(message, None, state, time)
+    }
+
+    /// Logs a message we've sent. Only chat, the server tells us about
+    /// anything else we do, like joining.
+    pub fn sent(
+        &mut self,
+        message: &Message,
+        state: &State,
+        time: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if !matches!(message.command(), Command::PrivMsg | Command::Notice) {
+            return Ok(());
+        }
+        let source = match &state.userhost {
+            Some(userhost) => format!("{}!{}", state.nick, userhost),
+            None => state.nick.clone(),
+        };
+        self.log(message, Some(&source), state, time)
+    }
+
+    fn log(
+        &mut self,
+        message: &Message,
+        own: Option<&str>,
+        state: &State,
+        time: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let time = self.zone.convert(time);
+        let source = match (own, message.source()) {
+            (Some(own), _) => own.to_string(),
+            (None, Some(source)) => source.to_string(),
+            (None, None) => String::new(),
+        };
+        let nick = source.split('!').next().unwrap_or_default();
+        let entry = match Entry::new(message, nick) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+
+        for buffer in buffers(message, nick, own.is_some(), state) {
+            let format = match self.format(&buffer) {
+                Some(format) => format,
+                None => continue,
+            };
+            let record = Record {
+                time: time.to_rfc3339(),
+                network: &self.network,
+                buffer: &buffer,
+                source: Some(source.clone()).filter(|s| !s.is_empty()),
+                command: message.command().to_string(),
+                params: message.params(),
+                tags: message
+                    .tags()
+                    .iter()
+                    .flat_map(|tags| tags.iter())
+                    .map(|t| (t.key().to_string(), t.value().to_string()))
+                    .collect(),
+            };
+            let line = self.line(format, &entry, time, &record)?;
+            self.append(&buffer, time.date_naive(), format, &line)?;
+        }
+        Ok(())
+    }
+
+    /// Logs someone on the notify list coming online or going offline, in
+    /// a buffer of their own.
+    pub fn presence(&mut self, change: &Change, time: DateTime<Utc>) -> Result<(), String> {
+        let time = self.zone.convert(time);
+        let buffer = change.nick.clone();
+        let format = match self.format(&buffer) {
+            Some(format) => format,
+            None => return Ok(()),
+        };
+        let entry = Entry::Presence(change.clone());
+        let command = if change.online { "ONLINE" } else { "OFFLINE" };
+        let record = Record {
+            time: time.to_rfc3339(),
+            network: &self.network,
+            buffer: &buffer,
+            source: None,
+            command: command.to_string(),
+            params: Some(change.nick.clone())
+                .into_iter()
+                .chain(change.userhost.clone())
+                .collect(),
+            tags: BTreeMap::new(),
+        };
+        let line = self.line(format, &entry, time, &record)?;
+        self.append(&buffer, time.date_naive(), format, &line)
+    }
+
+    /// What's written for `entry`, or for JSON Lines its `record`.
+    fn line(
+        &self,
+        format: LogFormat,
+        entry: &Entry,
+        time: DateTime<FixedOffset>,
+        record: &Record,
+    ) -> Result<String, String> {
+        let stamp = time.format(self.config.timestamp.get_ref());
+        Ok(match format {
+            LogFormat::Plain => entry
+                .lines()
+                .iter()
+                .map(|e| format!("[{}] {}", stamp, e.plain()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            LogFormat::Weechat => entry
+                .lines()
+                .iter()
+                .map(|e| format!("{}\t{}", stamp, e.weechat()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            LogFormat::Jsonl => serde_json::to_string(record).map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// Writes to `buffer`'s log, giving up on it if that fails.
+    fn append(
+        &mut self,
+        buffer: &str,
+        date: NaiveDate,
+        format: LogFormat,
+        line: &str,
+    ) -> Result<(), String> {
+        if let Err(e) = self.write(buffer, date, format, line) {
+            self.failed.insert(buffer.to_string());
+            return Err(format!("Can't log {}, stopped logging it: {}", buffer, e));
+        }
+        Ok(())
+    }
+
+    /// How `buffer` is logged, if it is.
+    fn format(&self, buffer: &str) -> Option<LogFormat> {
+        if self.dir.is_none() || self.failed.contains(buffer) {
+            return None;
+        }
+        let qualified = format!("{}/{}", self.network, buffer);
+        let settings = self
+            .config
+            .buffers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&qualified))
+            .or_else(|| {
+                self.config
+                    .buffers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(buffer))
+            })
+            .map(|(_, settings)| settings);
+
+        let enabled = settings
+            .and_then(|s| s.enabled)
+            .unwrap_or(self.config.enabled);
+        let format = settings.and_then(|s| s.format);
+        Some(format.unwrap_or(self.config.format)).filter(|_| enabled)
+    }
+
+    fn write(
+        &mut self,
+        buffer: &str,
+        date: NaiveDate,
+        format: LogFormat,
+        line: &str,
+    ) -> io::Result<()> {
+        let current = self.files.get(buffer).map(|(d, _)| *d);
+        if current != Some(date) {
+            let file = self.open(buffer, date, format)?;
+            self.files.insert(buffer.to_string(), (date, file));
+        }
+        let (_, file) = self.files.get_mut(buffer).unwrap();
+        writeln!(file, "{}", line)
+    }
+
+    /// Opens the day's file, compressing earlier days when asked to.
+    fn open(&self, buffer: &str, date: NaiveDate, format: LogFormat) -> io::Result<File> {
+        let dir = self
+            .dir
+            .as_ref()
+            .unwrap()
+            .join(file_name(&self.network))
+            .join(file_name(buffer));
+        fs::create_dir_all(&dir)?;
+        let extension = match format {
+            LogFormat::Jsonl => "jsonl",
+            LogFormat::Plain | LogFormat::Weechat => "log",
+        };
+        let day = date.format(DATE_FORMAT).to_string();
+        if self.config.compress {
+            compress_before(&dir, &day)?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(format!("{}.{}", day, extension)))
+    }
+}
+
+/// A JSON Lines log entry.
+#[derive(Serialize)]
+struct Record<'a> {
+    time: String,
+    network: &'a str,
+    buffer: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    command: String,
+    params: Vec<String>,
+    tags: BTreeMap<String, String>,
+}
+
+/// What happened, for the text formats.
+#[derive(Clone, Debug, PartialEq)]
+enum Entry {
+    Message(String, String),
+    Action(String, String),
+    Notice(String, String),
+    Join(String, String),
+    Part(String, String, Option<String>),
+    Quit(String, Option<String>),
+    Nick(String, String),
+    Error(String),
+    Presence(Change),
+}
+
+impl Entry {
+    /// Only chat and people coming and going are logged, not CTCP or
+    /// replies to commands.
+    fn new(message: &Message, nick: &str) -> Option<Self> {
+        let param = |i| message.get_param(i).map(|p| format::strip(&p.to_string()));
+        let nick = nick.to_string();
+        Some(match message.command() {
+            Command::PrivMsg => match message.ctcp() {
+                Some(ctcp) if ctcp.is_action() => {
+                    Entry::Action(nick, format::strip(&ctcp.params.unwrap_or_default()))
+                }
+                Some(_) => return None,
+                None => Entry::Message(nick, param(1)?),
+            },
+            Command::Notice if message.ctcp().is_none() => Entry::Notice(nick, param(1)?),
+            Command::Join => Entry::Join(nick, param(0)?),
+            Command::Part => Entry::Part(nick, param(0)?, param(1)),
+            Command::Quit => Entry::Quit(nick, param(0)),
+            Command::Nick => Entry::Nick(nick, param(0)?),
+            Command::Error => Entry::Error(param(0)?),
+            _ => return None,
+        })
+    }
+
+    /// A multiline message as one entry per line, so each gets its own
+    /// timestamp and nick.
+    fn lines(&self) -> Vec<Entry> {
+        let split = |nick: &str, text: &str, entry: fn(String, String) -> Entry| {
+            let lines = text.split('\n');
+            lines
+                .map(|l| entry(nick.to_string(), l.to_string()))
+                .collect()
+        };
+        match self {
+            Entry::Message(nick, text) => split(nick, text, Entry::Message),
+            Entry::Action(nick, text) => split(nick, text, Entry::Action),
+            Entry::Notice(nick, text) => split(nick, text, Entry::Notice),
+            entry => vec![entry.clone()],
+        }
+    }
+
+    fn plain(&self) -> String {
+        let reason = |r: &Option<String>| r.as_ref().map(|r| format!(" ({})", r));
+        match self {
+            Entry::Message(nick, text) => format!("<{}> {}", nick, text),
+            Entry::Action(nick, text) => format!("* {} {}", nick, text),
+            Entry::Notice(nick, text) => format!("-{}- {}", nick, text),
+            Entry::Join(nick, channel) => format!("--> {} joined {}", nick, channel),
+            Entry::Part(nick, channel, r) => format!(
+                "<-- {} left {}{}",
+                nick,
+                channel,
+                reason(r).unwrap_or_default()
+            ),
+            Entry::Quit(nick, r) => format!("<-- {} quit{}", nick, reason(r).unwrap_or_default()),
+            Entry::Nick(old, new) => format!("-- {} is now known as {}", old, new),
+            Entry::Error(text) => format!("-- {}", text),
+            Entry::Presence(change) => format!("-- {}", change),
+        }
+    }
+
+    fn weechat(&self) -> String {
+        let reason = |r: &Option<String>| r.as_ref().map(|r| format!(" ({})", r));
+        match self {
+            Entry::Message(nick, text) => format!("{}\t{}", nick, text),
+            Entry::Action(nick, text) => format!(" *\t{} {}", nick, text),
+            Entry::Notice(nick, text) => format!("--\tNotice({}): {}", nick, text),
+            Entry::Join(nick, channel) => format!("-->\t{} has joined {}", nick, channel),
+            Entry::Part(nick, channel, r) => format!(
+                "<--\t{} has left {}{}",
+                nick,
+                channel,
+                reason(r).unwrap_or_default()
+            ),
+            Entry::Quit(nick, r) => {
+                format!("<--\t{} has quit{}", nick, reason(r).unwrap_or_default())
+            }
+            Entry::Nick(old, new) => format!("--\t{} is now known as {}", old, new),
+            Entry::Error(text) => format!("=!=\t{}", text),
+            Entry::Presence(change) => format!("--\t{}", change),
+        }
+    }
+}
+
+/// The buffers a message shows up in. `nick` is who it's from.
+pub fn buffers(message: &Message, nick: &str, sent: bool, state: &State) -> Vec<String> {
+    let target = message
+        .get_param(0)
+        .map(|p| p.to_string())
+        .unwrap_or_default();
+    match message.command() {
+        Command::PrivMsg | Command::Notice | Command::TagMsg => {
+            // Messages to @#channel only reach its ops, but belong in it
+            let channel = target.trim_start_matches(['@', '+']);
+            if channel.starts_with(CHANNEL_PREFIXES) {
+                vec![channel.to_string()]
+            } else if sent || nick.eq_ignore_ascii_case(&state.nick) {
+                // Ours, maybe played back from the server's history
+                vec![target]
+            } else if target.eq_ignore_ascii_case(&state.nick)
+                && message.source().is_some_and(|s| s.user.is_some())
+            {
+                vec![nick.to_string()]
+            } else {
+                vec![SERVER_BUFFER.to_string()]
+            }
+        }
+        Command::Join | Command::Part => vec![target],
+        Command::Quit | Command::Nick => {
+            let own = nick == state.nick;
+            let mut buffers: Vec<String> = state
+                .channels
+                .iter()
+                .filter(|(_, c)| own || c.members.contains(nick))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if state.queries.contains(nick) {
+                buffers.push(nick.to_string());
+            }
+            buffers
+        }
+        _ => vec![SERVER_BUFFER.to_string()],
+    }
+}
+
+/// A buffer or network name that's safe to use as a directory.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    match name.strip_prefix('.') {
+        Some(rest) => format!("_{}", rest),
+        None => name,
+    }
+}
+
+/// Gzips the logs in `dir` from before `day`. A day written to again, as
+/// when a bouncer plays back history, is added to its archive, gzip files
+/// can be concatenated.
+fn compress_before(dir: &Path, day: &str) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let old = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => {
+                stem.to_string_lossy().as_ref() < day
+                    && (extension == "log" || extension == "jsonl")
+            }
+            _ => false,
+        };
+        if !old {
+            continue;
+        }
+        let mut compressed = path.clone().into_os_string();
+        compressed.push(".gz");
+        let archive = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&compressed)?;
+        let mut encoder = GzEncoder::new(archive, Compression::default());
+        io::copy(&mut File::open(&path)?, &mut encoder)?;
+        encoder.finish()?;
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+    use chrono::{Local, TimeZone};
+    use flate2::read::MultiGzDecoder;
+    use std::env;
+    use std::io::Read;
+    use toml::Spanned;
+
+    fn logger(name: &str, format: LogFormat) -> (Logger, PathBuf) {
+        let dir = env::temp_dir().join(format!("yairc-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = Log {
+            dir: Some(dir.clone()),
+            format,
+            ..Log::default()
+        };
+        (Logger::new(&config, Zone::Local, "Libera"), dir)
+    }
+
+    fn state() -> State {
+        let mut state = State::new("me");
+        state.update(&parse_line(":me!me@example.com JOIN #rust"));
+        state.update(&parse_line(":alice!a@example.com JOIN #rust"));
+        state
+    }
+
+    fn time(day: u32) -> DateTime<Utc> {
+        let time = Local.with_ymd_and_hms(2026, 1, day, 12, 30, 0).unwrap();
+        time.with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_plain() {
+        let (mut logger, dir) = logger("plain", LogFormat::Plain);
+        let state = state();
+        for raw in &[
+            ":alice!a@example.com PRIVMSG #rust :hello \u{2}there\u{2}",
+            ":alice!a@example.com PRIVMSG #rust :\u{1}ACTION waves\u{1}",
+            ":alice!a@example.com PRIVMSG #rust :two\nlines",
+            ":alice!a@example.com PRIVMSG me :psst",
+            ":alice!a@example.com PRIVMSG me :\u{1}VERSION\u{1}",
+            ":irc.example.com NOTICE me :Welcome",
+            ":alice!a@example.com QUIT :bye",
+        ] {
+            logger.received(&parse_line(raw), &state, time(1)).unwrap();
+        }
+        logger
+            .sent(
+                &Message::priv_msg("alice".into(), "hi".into()),
+                &state,
+                time(1),
+            )
+            .unwrap();
+        let join = Message::new(Command::Join, vec!["#rust"]);
+        logger.sent(&join, &state, time(1)).unwrap();
+        let offline = Change {
+            nick: "alice".to_string(),
+            online: false,
+            userhost: None,
+        };
+        logger.presence(&offline, time(1)).unwrap();
+
+        let read = |buffer: &str| {
+            fs::read_to_string(dir.join("libera").join(buffer).join("2026-01-01.log")).unwrap()
+        };
+        assert_eq!(
+            read("#rust"),
+            "[2026-01-01 12:30:00] <alice> hello there\n\
+             [2026-01-01 12:30:00] * alice waves\n\
+             [2026-01-01 12:30:00] <alice> two\n\
+             [2026-01-01 12:30:00] <alice> lines\n\
+             [2026-01-01 12:30:00] <-- alice quit (bye)\n"
+        );
+        assert_eq!(
+            read("alice"),
+            "[2026-01-01 12:30:00] <alice> psst\n\
+             [2026-01-01 12:30:00] <me> hi\n\
+             [2026-01-01 12:30:00] -- alice went offline\n"
+        );
+        assert_eq!(
+            read("server"),
+            "[2026-01-01 12:30:00] -irc.example.com- Welcome\n"
+        );
+    }
+
+    #[test]
+    fn test_jsonl_and_per_buffer_settings() {
+        let (mut logger, dir) = logger("jsonl", LogFormat::Jsonl);
+        logger.config.buffers.insert(
+            "libera/#rust".to_string(),
+            config::BufferLog {
+                enabled: None,
+                format: Some(LogFormat::Weechat),
+            },
+        );
+        logger.config.buffers.insert(
+            "#spam".to_string(),
+            config::BufferLog {
+                enabled: Some(false),
+                format: None,
+            },
+        );
+        let state = state();
+        let raw =
+            "@msgid=abc;time=2026-01-01T12:30:00.000Z :alice!a@example.com PRIVMSG #ops :deploy";
+        logger.received(&parse_line(raw), &state, time(1)).unwrap();
+        logger
+            .received(
+                &parse_line(":alice!a@example.com PRIVMSG #rust :hi"),
+                &state,
+                time(1),
+            )
+            .unwrap();
+        logger
+            .received(
+                &parse_line(":alice!a@example.com PRIVMSG #spam :buy"),
+                &state,
+                time(1),
+            )
+            .unwrap();
+
+        let online = Change {
+            nick: "bob".to_string(),
+            online: true,
+            userhost: Some("b@example.com".to_string()),
+        };
+        logger.presence(&online, time(1)).unwrap();
+
+        let ops = fs::read_to_string(dir.join("libera/#ops/2026-01-01.jsonl")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(ops.trim()).unwrap();
+        assert_eq!(record["source"], "alice!a@example.com");
+        assert_eq!(record["params"][1], "deploy");
+        assert_eq!(record["tags"]["msgid"], "abc");
+        let rust = fs::read_to_string(dir.join("libera/#rust/2026-01-01.log")).unwrap();
+        assert_eq!(rust, "2026-01-01 12:30:00\talice\thi\n");
+        assert!(!dir.join("libera/#spam").exists());
+        let bob = fs::read_to_string(dir.join("libera/bob/2026-01-01.jsonl")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(bob.trim()).unwrap();
+        assert_eq!(record["command"], "ONLINE");
+        assert_eq!(
+            record["params"],
+            serde_json::json!(["bob", "b@example.com"])
+        );
+    }
+
+    #[test]
+    fn test_timestamp_and_zone() {
+        let (mut logger, dir) = logger("zone", LogFormat::Plain);
+        logger.zone = Zone::Named(chrono_tz::Europe::Berlin);
+        logger.config.timestamp = Spanned::new(0..0, "%H:%M".to_string());
+        let raw = "@time=2026-01-01T23:30:00.000Z :alice!a@example.com PRIVMSG #rust :late";
+        let message = parse_line(raw);
+        logger
+            .received(&message, &state(), message.time().unwrap())
+            .unwrap();
+
+        // Already the next day in Berlin
+        let rust = fs::read_to_string(dir.join("libera/#rust/2026-01-02.log")).unwrap();
+        assert_eq!(rust, "[00:30] <alice> late\n");
+    }
+
+    #[test]
+    fn test_rotation_and_compression() {
+        let (mut logger, dir) = logger("rotate", LogFormat::Plain);
+        logger.config.compress = true;
+        let state = state();
+        let hello = parse_line(":alice!a@example.com PRIVMSG #rust :hello");
+        logger.received(&hello, &state, time(1)).unwrap();
+        logger.received(&hello, &state, time(2)).unwrap();
+
+        let rust = dir.join("libera/#rust");
+        assert!(rust.join("2026-01-01.log.gz").exists());
+        assert!(!rust.join("2026-01-01.log").exists());
+        assert!(rust.join("2026-01-02.log").exists());
+    }
+
+    #[test]
+    fn test_compression_keeps_played_back_days() {
+        let (mut logger, dir) = logger("playback", LogFormat::Plain);
+        logger.config.compress = true;
+        logger.config.timestamp = Spanned::new(0..0, "%d".to_string());
+        let state = state();
+        for (day, text) in [(1, "one"), (2, "two"), (1, "three"), (2, "four")] {
+            let message = parse_line(&format!(":alice!a@example.com PRIVMSG #rust :{}", text));
+            logger.received(&message, &state, time(day)).unwrap();
+        }
+
+        let rust = dir.join("libera/#rust");
+        let mut day_one = String::new();
+        MultiGzDecoder::new(File::open(rust.join("2026-01-01.log.gz")).unwrap())
+            .read_to_string(&mut day_one)
+            .unwrap();
+        assert_eq!(day_one, "[01] <alice> one\n[01] <alice> three\n");
+        let day_two = fs::read_to_string(rust.join("2026-01-02.log")).unwrap();
+        assert_eq!(day_two, "[02] <alice> two\n[02] <alice> four\n");
+    }
+}
@@ -1,258 +1,1460 @@
-use io::Read;
-use std::io::{stdin, Write};
-use std::net::TcpStream;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::{self, stdin, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::ptr::replace;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
-use std::{error::Error, io};
+use std::time::{Duration, Instant};
 
+use termion::event::{Event as TermEvent, Key};
 use termion::input::TermRead;
-use termion::{color, style};
 
+mod cli;
 mod client;
+mod config;
+mod logger;
 mod message;
+mod search;
+mod ui;
 
-use client::Client;
+use chrono::Utc;
+use clap::Parser;
+
+use cli::Args;
+use client::batch::{self, Batch, Item};
+use client::caps::Caps;
+use client::connection::{self, Backoff, Liveness, PingTimer};
+use client::dcc::{self, Manager};
+use client::history::{self, Reference, Subcommand};
+use client::ident;
+use client::modes::{self, Change, ModeTypes};
+use client::notify::{self, NotifyList};
+use client::pending::{Outcome, Pending};
+use client::queue::Queue;
+use client::scrollback::Said;
+use client::snotices::{self, Notice};
+use client::state::{self, State, CHANNEL_PREFIXES};
+use client::stream::Stream;
+use client::url::Url;
+use client::{Client, Event};
+use config::{Config, Settings};
+use logger::Logger;
+use message::ctcp::Ctcp;
 use message::{Command, Message};
+use search::{Hit, Store};
+use ui::completion::Candidates;
+use ui::editor::Action;
+use ui::input::{DccCommand, Input, ListCommand, NotifyCommand, COMMANDS};
+use ui::Screen;
 
-const DEFAUL_PORT: &'static str = "6697";
+/// How long a read from the server waits before we check for things to send.
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+/// How often a LIST coming in says how far it's got, in channels.
+const LIST_PROGRESS: usize = 500;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // TODO: Validate format of server
-    let server_arg = std::env::args()
-        .nth(1)
-        .expect("Need to provide a host as the first argument. Example: irc.example.com");
-    // TODO: Validate format of room
-    let channel_name = std::env::args()
-        .nth(2)
-        .expect("Need to provide a room to join. Example: test_room");
-
-    let nick = std::env::args()
-        .nth(3)
-        .expect("Need to provide a nick for the server. Example: somename");
-
-    let client = Client::new(&server_arg, &channel_name, &nick);
-    let sender = client.sender();
+    let args = Args::parse();
+    let path = args.config.clone().or_else(config::default_path);
+    if args.check_config {
+        process::exit(check_config(path.as_deref()));
+    }
+
+    let config = match &path {
+        Some(path) => match Config::load(path) {
+            Ok((config, _)) => config,
+            Err(e) => exit_with_error(&e.to_string()),
+        },
+        None => Config::default(),
+    };
+    let settings = match args.settings(&config) {
+        Ok(settings) => settings,
+        Err(e) => exit_with_error(&format!("{}, see yairc --help", e)),
+    };
+    ui::format::set_colors(config.ui.colors);
+    ui::timestamp::set(config.ui.timestamp.get_ref(), config.timezone);
+
+    let client = Client::new(&settings.nick);
     let receiver = client.receiver();
+    let state = client.state();
+    {
+        let mut state = state.lock().unwrap();
+        state.queries.extend(settings.query.clone());
+        state.notify = NotifyList::new(&settings.notify);
+    }
+    let manager = client.dcc();
+    if let Some(dir) = &config.dcc.download_dir {
+        manager.lock().unwrap().set_download_dir(dir);
+    }
 
     let ui_channel: (Sender<Message>, Receiver<Message>) = channel();
     let ui_sender = Arc::new(Mutex::new(ui_channel.0));
     let ui_receiver = Arc::new(Mutex::new(ui_channel.1));
 
-    let server = format!("{}:{}", server_arg, DEFAUL_PORT);
+    let ident = Arc::new(Mutex::new(None));
+    if config.ident.enabled {
+        let port = *config.ident.port.get_ref();
+        if let Err(e) = ident::serve(port, ident.clone()) {
+            exit_with_error(&format!(
+                "Can't answer ident queries on port {}: {}",
+                port, e
+            ));
+        }
+    }
+
+    let logger = Logger::new(&config.log, config.timezone, &settings.name);
+    let search_path = match &config.search.path {
+        _ if !config.search.enabled => None,
+        Some(path) => Some(path.clone()),
+        None => config::default_search_path(),
+    };
+    let open_store = |path: &Path| match Store::open(path) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn(&client, format!("Can't open {}: {}", path.display(), e));
+            None
+        }
+    };
+    let store = search_path.as_deref().and_then(open_store);
+    // SQLite connections stay on the thread that opened them
+    let input_store = search_path.as_deref().and_then(open_store);
+
+    // Settings from /connect, for the reader thread to switch to
+    let next: Arc<Mutex<Option<Settings>>> = Arc::new(Mutex::new(None));
+
+    let screen = match Screen::new(&settings.buffer()) {
+        Ok(screen) => Arc::new(Mutex::new(screen)),
+        Err(e) => exit_with_error(&format!("Can't start the terminal UI: {}", e)),
+    };
+    screen
+        .lock()
+        .unwrap()
+        .editor
+        .set_confirm_paste(config.ui.confirm_paste);
 
-    // Reads messages from the server
+    // Reads messages from the server, reconnecting whenever the connection
+    // drops until we quit
+    let reader_next = next.clone();
+    let network = settings.name.clone();
     let reader_thread: JoinHandle<std::result::Result<(), Box<std::io::Error>>> =
         thread::spawn(move || {
-            let mut stream = TcpStream::connect(server)?;
-            let mut buf = [0u8; 2048];
-
-            let mut reply_messages: Vec<Message> = Vec::new();
-            let mut need_to_register = true;
-            let mut can_join = false;
+            let mut backoff = Backoff::default();
+            let mut session = Session {
+                client,
+                ui_receiver,
+                ctcp: client::ctcp::Responder::default(),
+                settings,
+                next: reader_next,
+                ident,
+                logger,
+                store,
+                restoring: false,
+                quitting: false,
+            };
 
             loop {
-                match ui_receiver.lock().unwrap().try_recv() {
-                    Ok(message) => {
-                        dbg!("I received a message!!!");
-                        reply_messages.push(message)
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
+                let settings = &session.settings;
+                session.notify(connection::Event::Connecting {
+                    server: format!("{}:{}", settings.host, settings.port),
+                    attempt: backoff.attempt() + 1,
+                });
+                let stream =
+                    Stream::connect(&settings.host, settings.port, settings.tls, READ_TIMEOUT);
+                let reason = match stream {
+                    Ok(stream) => match session.run(stream, &mut backoff) {
+                        Ok(_) => "Connection closed".to_string(),
+                        Err(e) => e.to_string(),
+                    },
+                    Err(e) => e.to_string(),
+                };
+                if session.quitting {
+                    if session.switch(&mut backoff) {
+                        continue;
                     }
+                    return Ok(());
                 }
+                let unsent = {
+                    let state = session.client.state();
+                    let mut state = state.lock().unwrap();
+                    state.disconnected();
+                    state.pending.clear()
+                };
+                // Anything still queued went with the connection, and
+                // nothing more will be echoed
+                let sender = session.client.sender();
+                let sender = sender.lock().unwrap();
+                let _ = sender.send(Event::Queued(0));
+                let _ = sender.send(Event::Pending(0));
+                let _ = sender.send(Event::Online(Vec::new()));
+                let _ = sender.send(Event::UserModes(String::new()));
+                for sent in unsent {
+                    let _ = sender.send(Event::NotSent(sent, reason.clone()));
+                }
+                drop(sender);
+                session.restoring = true;
+                session.notify(connection::Event::Disconnected { reason });
 
-                if let Ok(_) = stream.peek(&mut buf) {
-                    match stream.read(&mut buf) {
-                        Ok(length) => {
-                            let data = String::from_utf8_lossy(&buf[0..length]);
-                            let messages: Vec<Result<Message, Box<dyn Error>>> = data
-                                .split_inclusive("\r\n")
-                                .map(|raw_message| Message::parse(raw_message))
-                                .collect();
-                            for message in messages {
-                                match message {
-                                    Ok(message) => match message.command() {
-                                        Command::Ping => {
-                                            let server = message.get_param(0).unwrap();
-                                            reply_messages.push(Message::pong(server.clone()));
-                                            client
-                                                .sender()
-                                                .lock()
-                                                .unwrap()
-                                                .send(message)
-                                                .expect("Unable to send data to UI thread");
-                                        }
-                                        Command::RplWelcome => {
-                                            reply_messages.push(Message::motd());
-                                            reply_messages.append(&mut client::join(&channel_name));
-                                            client
-                                                .sender()
-                                                .lock()
-                                                .unwrap()
-                                                .send(message)
-                                                .expect("Unable to send data to UI thread");
-                                        }
-                                        Command::PrivMsg => {
-                                            client
-                                                .sender()
-                                                .lock()
-                                                .unwrap()
-                                                .send(message)
-                                                .expect("Unable to send data to UI thread");
-                                        }
-                                        _ => {
-                                            client
-                                                .sender()
-                                                .lock()
-                                                .unwrap()
-                                                .send(message)
-                                                .expect("Unable to send data to UI thread");
-                                        }
-                                    },
-                                    Err(e) => {
-                                        eprintln!("Unable to parse message: {}", data);
-                                        eprintln!("Error: {}", e);
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => return Err(Box::new(e)),
-                    }
+                let delay = backoff.next_delay();
+                session.notify(connection::Event::Retrying { delay });
+                if session.wait(delay) && !session.switch(&mut backoff) {
+                    return Ok(());
                 }
+            }
+        });
+
+    // Initiailize output
+    let output_screen = screen.clone();
+    let output_state = state.clone();
+    let output_manager = manager.clone();
+    thread::spawn(move || loop {
+        let receiver = receiver.lock().unwrap();
 
-                if need_to_register {
-                    println!("Registering...");
-                    reply_messages.append(&mut client::register(&nick));
-                    need_to_register = false;
+        // Data from server TCP stream and DCC connections
+        match receiver.recv() {
+            Ok(Event::Message(message)) => {
+                let mut screen = output_screen.lock().unwrap();
+                let state = output_state.lock().unwrap();
+                let line = ui::format_message(&message, screen.editor.buffer(), &state);
+                if !line.is_empty() {
+                    screen
+                        .print(&line)
+                        .expect("Unable to write to the terminal");
+                }
+            }
+            Ok(Event::Connection(event)) => {
+                let mut screen = output_screen.lock().unwrap();
+                let status = ui::connection_status(&event);
+                screen
+                    .set_status("connection", status)
+                    .and_then(|_| screen.print(&ui::format_connection_event(&event)))
+                    .expect("Unable to write to the terminal");
+            }
+            Ok(Event::Queued(count)) => {
+                let status = Some(format!("{} queued", count)).filter(|_| count > 0);
+                output_screen
+                    .lock()
+                    .unwrap()
+                    .set_status("queue", status)
+                    .expect("Unable to write to the terminal");
+            }
+            Ok(Event::Pending(count)) => {
+                let status = Some(format!("{} pending", count)).filter(|_| count > 0);
+                output_screen
+                    .lock()
+                    .unwrap()
+                    .set_status("pending", status)
+                    .expect("Unable to write to the terminal");
+            }
+            Ok(Event::NotSent(sent, reason)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_not_sent(&sent, &reason))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Presence(change)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_presence(&change))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Online(nicks)) => output_screen
+                .lock()
+                .unwrap()
+                .set_status("online", ui::online_status(&nicks))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::History(target, messages)) => {
+                let mut screen = output_screen.lock().unwrap();
+                let state = output_state.lock().unwrap();
+                let active = screen.editor.buffer().to_string();
+                screen
+                    .print(&ui::format_history(&target, messages.len()))
+                    .expect("Unable to write to the terminal");
+                for message in &messages {
+                    let line = ui::format_message(message, &active, &state);
+                    if !line.is_empty() {
+                        screen
+                            .print(&line)
+                            .expect("Unable to write to the terminal");
+                    }
+                }
+            }
+            Ok(Event::Typing(buffer)) => {
+                let mut screen = output_screen.lock().unwrap();
+                if buffer.eq_ignore_ascii_case(screen.editor.buffer()) {
+                    let nicks = output_state.lock().unwrap().typing.nicks(&buffer);
+                    screen
+                        .set_status("typing", ui::typing_status(&nicks))
+                        .expect("Unable to write to the terminal");
+                }
+            }
+            Ok(Event::List(count, done)) => {
+                let mut screen = output_screen.lock().unwrap();
+                let status = Some(format!("listing {} channels", count)).filter(|_| !done);
+                screen
+                    .set_status("list", status)
+                    .expect("Unable to write to the terminal");
+                if done {
+                    let mut state = output_state.lock().unwrap();
+                    show_list(&mut screen, &mut state, false, 1, "")
+                        .expect("Unable to write to the terminal");
+                }
+            }
+            Ok(Event::ModeList(list)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_mode_list(&list))
+                .expect("Unable to write to the terminal"),
+            // Counted in the status line until their buffer is shown
+            Ok(Event::ServerNotice(notice)) => {
+                let mut screen = output_screen.lock().unwrap();
+                let mut state = output_state.lock().unwrap();
+                if screen.editor.buffer() == snotices::BUFFER {
+                    state.snotices.seen();
+                    screen
+                        .print(&ui::format_snotice(&notice))
+                        .expect("Unable to write to the terminal");
                 }
+                screen
+                    .set_status("snotices", ui::snotices_status(state.snotices.unseen()))
+                    .expect("Unable to write to the terminal");
+            }
+            Ok(Event::UserModes(modes)) => output_screen
+                .lock()
+                .unwrap()
+                .set_status("modes", ui::user_modes_status(&modes))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Lookup(lookup)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_lookup(&lookup))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Netsplit(netsplit)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_netsplit(&netsplit))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Warning(text)) => output_screen
+                .lock()
+                .unwrap()
+                .print(&ui::format_warning(&text))
+                .expect("Unable to write to the terminal"),
+            Ok(Event::Dcc(event)) => {
+                let mut screen = output_screen.lock().unwrap();
+                show_dcc_event(&event, &mut screen, &output_manager)
+                    .expect("Unable to write to the terminal");
+            }
+            // The reader thread is gone, main takes care of shutting down
+            Err(_) => return,
+        }
+    });
+
+    let input_screen = screen.clone();
+    let typing = config.ui.typing;
+    let mut inputs = Inputs {
+        manager,
+        sender: ui_sender,
+        connect: Box::new(move |url: &Url| {
+            let mut settings = url.settings(&config)?;
+            args.apply_identity(&mut settings);
+            Ok(settings)
+        }),
+        next,
+        network,
+        config_path: path,
+        typing,
+        store: input_store,
+        hits: Vec::new(),
+    };
+    thread::spawn(move || {
+        for event in stdin().events() {
+            let event = match event {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            let mut screen = input_screen.lock().unwrap();
+            let mut state = state.lock().unwrap();
+
+            if event == TermEvent::Key(Key::Ctrl('c')) {
+                send(&inputs.sender, client::quit(None));
+                continue;
+            }
 
-                if can_join {
-                    println!("Joining...");
-                    reply_messages.append(&mut client::join(&channel_name));
+            let candidates = candidates(&state, screen.editor.buffer());
+            let buffer = screen.editor.buffer().to_string();
+            match screen.editor.handle_event(event, &candidates) {
+                Action::Submit(lines) => {
+                    state.typing.sent();
+                    for line in lines {
+                        handle_input(&line, &mut screen, &mut state, &mut inputs)
+                            .expect("Unable to write to the terminal");
+                    }
                 }
+                _ if inputs.typing && state.caps.is_enabled("message-tags") => {
+                    let conversation =
+                        state::is_channel(&buffer) || state.queries.contains(&buffer);
+                    let line = screen.editor.line();
+                    if conversation {
+                        let typed = state.typing.typed(&buffer, &line, Instant::now());
+                        send(&inputs.sender, typed);
+                    }
+                }
+                _ => {}
+            }
+            if screen.editor.buffer() != buffer {
+                let nicks = state.typing.nicks(screen.editor.buffer());
+                screen
+                    .set_status("typing", ui::typing_status(&nicks))
+                    .expect("Unable to write to the terminal");
+            }
+            screen
+                .draw_input()
+                .expect("Unable to write to the terminal");
+        }
+    });
 
-                reply_messages
-                    .iter()
-                    .for_each(|message| match stream.write(&message.as_bytes()) {
-                        Ok(0) => {
-                            println!("Sent nothing, server connection might be closed")
-                        }
-                        Ok(n) => {
-                            println!("Sent {} bytes", n)
-                        }
-                        Err(e) => {
-                            eprintln!("Unable to send message: {}", e)
-                        }
-                    });
-                reply_messages.clear();
+    let result = reader_thread.join();
+    screen.lock().unwrap().close()?;
+    match result {
+        Ok(result) => match result {
+            Ok(_) => {
+                println!("Reader thread exited without incident")
+            }
+            Err(e) => {
+                eprintln!("Reader thread exited due to error: {}", e)
+            }
+        },
+        Err(_) => {
+            eprintln!("IRC listener thread unable to start")
+        }
+    }
+
+    Ok(())
+}
 
-                thread::sleep(Duration::from_secs(1));
+/// One connection to the server, and what carries over between them.
+struct Session {
+    client: Client,
+    ui_receiver: Arc<Mutex<Receiver<Message>>>,
+    ctcp: client::ctcp::Responder,
+    settings: Settings,
+    /// Where to go once we've quit, set by /connect.
+    next: Arc<Mutex<Option<Settings>>>,
+    /// The connection the ident server answers for.
+    ident: Arc<Mutex<Option<ident::Connection>>>,
+    logger: Logger,
+    /// Where messages are kept for /search, unless it's turned off.
+    store: Option<Store>,
+    /// Set after a disconnect, so registering again restores the channels
+    /// we were in instead of joining the configured ones.
+    restoring: bool,
+    /// Set once we've sent QUIT, so the server closing the connection
+    /// doesn't get reconnected.
+    quitting: bool,
+}
+
+impl Session {
+    fn notify(&self, event: connection::Event) {
+        // The UI only goes away when we're shutting down anyway
+        let _ = self
+            .client
+            .sender()
+            .lock()
+            .unwrap()
+            .send(Event::Connection(event));
+    }
+
+    /// Waits out `delay` before reconnecting. Returns true if the user quit
+    /// in the meantime, anything else they send is shown as not sent.
+    fn wait(&mut self, delay: Duration) -> bool {
+        let deadline = Instant::now() + delay;
+        while Instant::now() < deadline {
+            // Only to gather the lines of multiline messages
+            let mut unsent = Pending::default();
+            while let Ok(message) = self.ui_receiver.lock().unwrap().try_recv() {
+                match message.command() {
+                    Command::Quit => return true,
+                    Command::PrivMsg | Command::Notice | Command::Batch => {
+                        unsent.send(message, false);
+                    }
+                    // Nobody's missing those
+                    Command::TagMsg if message.tag(client::typing::TAG).is_some() => {}
+                    command => warn(
+                        &self.client,
+                        format!("Not connected, {} wasn't sent", command),
+                    ),
+                }
+            }
+            let sender = self.client.sender();
+            let sender = sender.lock().unwrap();
+            for sent in unsent.clear() {
+                let _ = sender.send(Event::NotSent(sent, "Not connected".to_string()));
             }
+            drop(sender);
+            thread::sleep(Duration::from_millis(200));
+        }
+        false
+    }
+
+    /// Moves on to the settings from a /connect, starting afresh. Returns
+    /// false if there are none and the user just quit.
+    fn switch(&mut self, backoff: &mut Backoff) -> bool {
+        let settings = match self.next.lock().unwrap().take() {
+            Some(settings) => settings,
+            None => return false,
+        };
+        {
+            let state = self.client.state();
+            let mut state = state.lock().unwrap();
+            *state = State::new(&settings.nick);
+            state.queries.extend(settings.query.clone());
+            state.notify = NotifyList::new(&settings.notify);
+        }
+        self.logger.set_network(&settings.name);
+        self.settings = settings;
+        self.restoring = false;
+        self.quitting = false;
+        backoff.reset();
+        true
+    }
+
+    /// Registers and handles messages until the connection is closed.
+    fn run(&mut self, mut stream: Stream, backoff: &mut Backoff) -> io::Result<()> {
+        let client = self.client.clone();
+        client
+            .dcc()
+            .lock()
+            .unwrap()
+            .set_local_address(stream.local_addr()?.ip());
+        *self.ident.lock().unwrap() = Some(ident::Connection {
+            local_port: stream.local_addr()?.port(),
+            remote_port: stream.peer_addr()?.port(),
+            username: self.settings.username.clone(),
         });
+        self.notify(connection::Event::Connected);
+
+        let mut buf = [0u8; 2048];
+        let mut pending = String::new();
+        let mut timer = PingTimer::new(
+            Instant::now(),
+            self.settings.ping_interval,
+            self.settings.ping_timeout,
+        );
+        let mut registered = false;
+        let mut queue = Queue::new(self.settings.burst, self.settings.rate, Instant::now());
+        let mut alternates = self.settings.alternates.clone().into_iter();
+        let mut queued = 0;
+        let mut batches = batch::Assembler::default();
+
+        let mut reply_messages = {
+            let state = client.state();
+            let mut state = state.lock().unwrap();
+            state.caps = Caps::new(self.settings.sasl.clone());
+            client::register(&self.settings, &state.nick)
+        };
 
-    // Initiailize output
-    let ui_thread = thread::spawn(move || {
         loop {
-            let receiver = receiver.lock().unwrap();
-
-            // Data from server TCP stream
-            match receiver.recv() {
-                Ok(message) => match message.command() {
-                    Command::Notice => {
-                        println!(
-                            "{}{}{}",
-                            color::Fg(color::Yellow),
-                            message,
-                            color::Fg(color::Reset)
-                        );
-                    }
-                    Command::RplWelcome
-                    | Command::RplMyInfo
-                    | Command::RplYourHost
-                    | Command::RplCreated => {
-                        println!(
-                            "{}{}{}{}{}",
-                            style::Bold,
-                            color::Fg(color::LightBlue),
-                            message,
-                            color::Fg(color::Reset),
-                            style::Reset
-                        );
-                    }
-                    Command::MessageOfTheDay
-                    | Command::RplMotd
-                    | Command::RplMotdStart
-                    | Command::RplEndOfMotd => {
-                        println!("{}{}{}", style::Italic, message, style::Reset);
+            while let Ok(message) = self.ui_receiver.lock().unwrap().try_recv() {
+                reply_messages.push(message)
+            }
+
+            match stream.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(length) => {
+                    timer.received(Instant::now());
+                    pending.push_str(&String::from_utf8_lossy(&buf[0..length]));
+                    // Only parse complete lines, the rest waits for the next read
+                    let complete = match pending.rfind('\n') {
+                        Some(i) => pending.drain(..=i).collect::<String>(),
+                        None => String::new(),
+                    };
+                    let mut incoming = VecDeque::new();
+                    for raw_message in complete.split_inclusive('\n') {
+                        let raw_message = format!("{}\r\n", raw_message.trim_end());
+                        let message = match Message::parse(&raw_message) {
+                            Ok(message) => message,
+                            Err(_) => continue,
+                        };
+                        incoming.extend(batches.receive(message.received_at(Utc::now())));
                     }
-                    Command::PrivMsg => {
-                        dbg!(&message);
-                        let name = match message.source() {
-                            Some(name) => name.to_string(),
-                            None => "Unknown".to_string(),
+                    while let Some(item) = incoming.pop_front() {
+                        let message = match item {
+                            Item::Message(message) => message,
+                            Item::Batch(batch) => {
+                                // What's left of the batch is handled as it came
+                                let messages = self.batch(batch, &mut reply_messages);
+                                for message in messages.into_iter().rev() {
+                                    incoming.push_front(Item::Message(message));
+                                }
+                                continue;
+                            }
                         };
-                        let message = message.get_param(1).unwrap();
-
-                        println!(
-                            "{}{}<{}>{}:{} {}",
-                            style::Bold,
-                            color::Fg(color::Green),
-                            name,
-                            color::Fg(color::Reset),
-                            style::Reset,
-                            message
-                        );
+                        {
+                            let state = client.state();
+                            let mut state = state.lock().unwrap();
+                            self.record(&mut state, &message);
+                            if state.is_user_mode(&message) {
+                                let event = Event::UserModes(state.user_modes());
+                                let _ = client.sender().lock().unwrap().send(event);
+                            }
+                            // Kept for their own buffer
+                            if let Some(notice) = Notice::parse(&message, &state) {
+                                if state.snotices.add(notice.clone()) {
+                                    let event = Event::ServerNotice(notice);
+                                    let _ = client.sender().lock().unwrap().send(event);
+                                }
+                                continue;
+                            }
+                            // Shown together once they've all come in
+                            if state.whois.handle(&message) {
+                                let sender = client.sender();
+                                let sender = sender.lock().unwrap();
+                                for lookup in state.whois.finished() {
+                                    let _ = sender.send(Event::Lookup(lookup));
+                                }
+                                continue;
+                            }
+                            if let Some(list) = state.mode_lists.handle(&message) {
+                                let _ = client.sender().lock().unwrap().send(Event::ModeList(list));
+                            }
+                            // Shown as it comes in every so many channels, to
+                            // keep up with lists of tens of thousands
+                            if state.list.handle(&message) {
+                                let done = !state.list.is_listing();
+                                if done || state.list.received().is_multiple_of(LIST_PROGRESS) {
+                                    let event = Event::List(state.list.len(), done);
+                                    let _ = client.sender().lock().unwrap().send(event);
+                                }
+                                continue;
+                            }
+                        }
+                        match message.command() {
+                            Command::Ping => {
+                                let server = message.get_param(0).unwrap();
+                                reply_messages.push(Message::pong(server.clone()));
+                            }
+                            Command::RplWelcome => {
+                                registered = true;
+                                backoff.reset();
+                                reply_messages.push(Message::motd());
+                                if self.restoring {
+                                    let state = client.state();
+                                    let state = state.lock().unwrap();
+                                    reply_messages.extend(client::rejoin(&state));
+                                    reply_messages.extend(history::missed_targets(&state));
+                                    self.notify(connection::Event::Restored {
+                                        channels: state.channels.keys().cloned().collect(),
+                                        queries: state.queries.iter().cloned().collect(),
+                                    });
+                                } else {
+                                    for (channel, key) in &self.settings.autojoin {
+                                        reply_messages.extend(match key {
+                                            Some(key) => client::join_with_key(channel, key),
+                                            None => client::join(channel),
+                                        });
+                                    }
+                                }
+                            }
+                            // Registration is done, with or without a MOTD
+                            Command::RplEndOfMotd | Command::ErrNoMotd => {
+                                let state = client.state();
+                                let mut state = state.lock().unwrap();
+                                reply_messages.extend(notify::start(&mut state, Instant::now()));
+                            }
+                            // Most likely our old connection hasn't timed out yet
+                            Command::ErrNicknameInUse if !registered => {
+                                let state = client.state();
+                                let mut state = state.lock().unwrap();
+                                match alternates.next() {
+                                    Some(nick) => state.nick = nick.clone(),
+                                    None => state.nick.push('_'),
+                                }
+                                reply_messages.append(&mut client::nick(&state.nick));
+                            }
+                            Command::Cap
+                            | Command::Authenticate
+                            | Command::RplSaslSuccess
+                            | Command::ErrSaslFail
+                            | Command::ErrSaslTooLong
+                            | Command::ErrSaslAborted
+                            | Command::ErrSaslAlready => {
+                                let state = client.state();
+                                let mut state = state.lock().unwrap();
+                                reply_messages.extend(state.caps.handle(&message));
+                            }
+                            // Catch up on what was said since we were last here
+                            Command::Join => {
+                                let state = client.state();
+                                let state = state.lock().unwrap();
+                                let channel = message.get_param(0).map(|p| p.to_string());
+                                if let (Some(channel), Some(source)) = (channel, message.source()) {
+                                    if source.nick == state.nick {
+                                        let stored = self.newest_msgid(&channel);
+                                        reply_messages
+                                            .extend(history::catch_up(&state, &channel, stored));
+                                    }
+                                }
+                            }
+                            Command::PrivMsg => {
+                                let state = client.state();
+                                let state = state.lock().unwrap();
+                                let manager = client.dcc();
+                                let mut manager = manager.lock().unwrap();
+                                reply_messages.extend(client::answer(
+                                    &state,
+                                    &mut self.ctcp,
+                                    &mut manager,
+                                    &message,
+                                    Instant::now(),
+                                ));
+                            }
+                            _ => {}
+                        }
+                        client
+                            .sender()
+                            .lock()
+                            .unwrap()
+                            .send(Event::Message(message))
+                            .expect("Unable to send data to UI thread");
                     }
-                    _ => {
-                        println!("{}", message);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => return Err(e),
+            }
+
+            match timer.check(Instant::now()) {
+                Liveness::Alive => {}
+                Liveness::Ping => reply_messages.push(Message::ping("yairc")),
+                Liveness::TimedOut => {
+                    return Err(io::Error::new(ErrorKind::TimedOut, "Ping timeout"));
+                }
+            }
+            {
+                let state = client.state();
+                let mut state = state.lock().unwrap();
+                reply_messages.extend(state.notify.poll(Instant::now()));
+                for buffer in state.typing.expire(Instant::now()) {
+                    let _ = client.sender().lock().unwrap().send(Event::Typing(buffer));
+                }
+            }
+
+            for message in reply_messages.drain(..) {
+                queue.push(message);
+            }
+            while let Some(mut message) = queue.pop(Instant::now()) {
+                if message.command() == &Command::Quit {
+                    self.quitting = true;
+                }
+                {
+                    let state = client.state();
+                    let mut state = state.lock().unwrap();
+                    state.sent(&message);
+                    // The echo is logged and stored instead, with the
+                    // server's time and msgid
+                    if state.caps.is_enabled("echo-message") {
+                        let labels = state.caps.is_enabled("labeled-response");
+                        let pending = state.pending.len();
+                        message = state.pending.send(message, labels);
+                        if state.pending.len() != pending {
+                            let count = state.pending.len();
+                            let _ = client.sender().lock().unwrap().send(Event::Pending(count));
+                        }
+                    } else {
+                        let now = Utc::now();
+                        if let Err(e) = self.logger.sent(&message, &state, now) {
+                            warn(&client, e);
+                        }
+                        if let Some(store) = &self.store {
+                            let name = &self.settings.name;
+                            if let Err(e) = store.sent(name, &message, &state, now.into()) {
+                                warn(&client, format!("Can't store a message: {}", e));
+                            }
+                        }
                     }
-                },
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    process::exit(1);
                 }
+                stream.write_all(&message.as_bytes())?;
+            }
+            if queue.len() != queued {
+                queued = queue.len();
+                let _ = client.sender().lock().unwrap().send(Event::Queued(queued));
             }
         }
-    });
+    }
+}
 
-    let input_thread = thread::spawn(move || {
-        let stdin = stdin();
-        let mut stdin = stdin.lock();
+impl Session {
+    /// Logs and stores a message from the server, and keeps track of what
+    /// it changes.
+    fn record(&mut self, state: &mut State, message: &Message) {
+        let time = message.time().unwrap_or_else(Utc::now);
+        if let Err(e) = self.logger.received(message, state, time) {
+            warn(&self.client, e);
+        }
+        if let Some(store) = &self.store {
+            if let Err(e) = store.received(&self.settings.name, message, state, time.into()) {
+                warn(&self.client, format!("Can't store a message: {}", e));
+            }
+        }
+        if let Some(outcome) = state.pending.receive(message, &state.nick) {
+            let sender = self.client.sender();
+            let sender = sender.lock().unwrap();
+            if let Outcome::Rejected(sent, reason) = outcome {
+                let _ = sender.send(Event::NotSent(sent, reason));
+            }
+            let _ = sender.send(Event::Pending(state.pending.len()));
+        }
+        let changes = state.notify.handle(message);
+        if !changes.is_empty() {
+            let sender = self.client.sender();
+            let sender = sender.lock().unwrap();
+            for change in changes {
+                if let Err(e) = self.logger.presence(&change, time) {
+                    warn(&self.client, e);
+                }
+                let _ = sender.send(Event::Presence(change));
+            }
+            let _ = sender.send(Event::Online(state.notify.online()));
+        }
+        if matches!(
+            message.command(),
+            Command::PrivMsg | Command::Notice | Command::TagMsg
+        ) {
+            let nick = message
+                .source()
+                .map(|s| s.nick.as_str())
+                .unwrap_or_default();
+            for buffer in logger::buffers(message, nick, false, state) {
+                if message.command() != &Command::TagMsg {
+                    state.history.seen(&buffer, message);
+                    state.scrollback.push(&buffer, message);
+                }
+                if nick != state.nick && state.typing.receive(message, &buffer, Instant::now()) {
+                    let _ = self
+                        .client
+                        .sender()
+                        .lock()
+                        .unwrap()
+                        .send(Event::Typing(buffer));
+                }
+            }
+        }
+        state.update(message);
+    }
 
-        loop {
-            let message = stdin.read_line().unwrap();
-            match message {
-                Some(message) => {
-                    let message = Message::priv_msg("poopie".to_string(), message);
-                    ui_sender
+    /// Handles a batch that has ended. Returns the messages in batches we
+    /// don't treat specially, to handle as if they'd come on their own.
+    fn batch(&mut self, batch: Batch, replies: &mut Vec<Message>) -> Vec<Message> {
+        let state = self.client.state();
+        let mut state = state.lock().unwrap();
+        match batch.kind().as_str() {
+            history::HISTORY_BATCH => {
+                let target = batch.params().into_iter().next().unwrap_or_default();
+                let messages = state.history.playback(&target, batch.into_messages());
+                for message in &messages {
+                    state.scrollback.push(&target, message);
+                }
+                self.play_back(&state, target, messages);
+            }
+            history::TARGETS_BATCH => {
+                let targets = history::targets(&batch.into_messages());
+                replies.extend(self.catch_up(&mut state, targets));
+            }
+            batch::NETSPLIT | batch::NETJOIN => {
+                let netsplit = batch.netsplit(&state);
+                for message in batch.into_messages() {
+                    self.record(&mut state, &message);
+                }
+                if let Some(netsplit) = netsplit {
+                    let _ = self
+                        .client
+                        .sender()
                         .lock()
                         .unwrap()
-                        .send(message)
-                        .expect("Sending message to the server failed")
+                        .send(Event::Netsplit(netsplit));
                 }
-                _ => {}
             }
+            _ => return batch.into_messages(),
         }
-    });
+        Vec::new()
+    }
 
-    match reader_thread.join() {
-        Ok(result) => match result {
-            Ok(_) => {
-                println!("Reader thread exited without incident")
+    /// Archives history from the server and shows it.
+    fn play_back(&self, state: &State, target: String, messages: Vec<Message>) {
+        if let Some(store) = &self.store {
+            for message in &messages {
+                let time = message.time().unwrap_or_else(Utc::now);
+                let stored = store.received(&self.settings.name, message, state, time.into());
+                if let Err(e) = stored {
+                    warn(&self.client, format!("Can't store a message: {}", e));
+                }
             }
-            Err(e) => {
-                eprintln!("Reader thread exited due to error: {}", e)
+        }
+        let _ = self
+            .client
+            .sender()
+            .lock()
+            .unwrap()
+            .send(Event::History(target, messages));
+    }
+
+    /// Fetches what we missed in conversations that went on while we were
+    /// disconnected. Channels do this themselves once they're joined again.
+    fn catch_up(&self, state: &mut State, targets: Vec<String>) -> Vec<Message> {
+        let mut requests = Vec::new();
+        for target in targets {
+            if target.starts_with(CHANNEL_PREFIXES) {
+                continue;
             }
-        },
+            let stored = self.newest_msgid(&target);
+            requests.extend(history::catch_up(state, &target, stored));
+            state.queries.insert(target);
+        }
+        requests
+    }
+
+    /// The newest message we have from `buffer`, from a previous run maybe.
+    fn newest_msgid(&self, buffer: &str) -> Option<Reference> {
+        let store = self.store.as_ref()?;
+        let msgid = store.newest_msgid(&self.settings.name, buffer).ok()??;
+        Some(Reference::MsgId(msgid))
+    }
+}
+
+fn warn(client: &Client, text: String) {
+    let _ = client.sender().lock().unwrap().send(Event::Warning(text));
+}
+
+/// Validates the config file, printing any mistakes with their position.
+/// Returns the exit code.
+fn check_config(path: Option<&Path>) -> i32 {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("No config file: HOME and XDG_CONFIG_HOME aren't set");
+            return 1;
+        }
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
         Err(e) => {
-            eprintln!("IRC listener thread unable to start")
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    match Config::parse(&source) {
+        Ok(_) => {
+            println!("{}: OK", path.display());
+            0
+        }
+        Err(errors) => {
+            for e in errors {
+                eprintln!("{}:{}:{}: {}", path.display(), e.line, e.column, e.message);
+            }
+            1
+        }
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("yairc: {}", message);
+    process::exit(1);
+}
+
+/// What tab can complete to in the given buffer.
+fn candidates(state: &State, buffer: &str) -> Candidates {
+    let nicks = if state.channels.contains_key(buffer) {
+        state.members(buffer)
+    } else {
+        vec![buffer.to_string()]
+    };
+    Candidates {
+        nicks,
+        channels: state.channels.keys().cloned().collect(),
+        commands: COMMANDS.iter().map(|c| c.to_string()).collect(),
+    }
+}
+
+fn send(sender: &Mutex<Sender<Message>>, messages: Vec<Message>) {
+    let sender = sender.lock().unwrap();
+    for message in messages {
+        sender
+            .send(message)
+            .expect("Sending message to the server failed")
+    }
+}
+
+fn show_dcc_event(
+    event: &dcc::Event,
+    screen: &mut Screen,
+    manager: &Mutex<Manager>,
+) -> io::Result<()> {
+    let line = ui::format_dcc_event(event, screen.editor.buffer());
+    match event {
+        dcc::Event::Offer { id, .. } => {
+            screen.print(&line)?;
+            let question = format!("Accept DCC offer {}?", id);
+            let yes = format!("/dcc accept {}", id);
+            let no = format!("/dcc reject {}", id);
+            screen.editor.ask(&question, &yes, &no);
+            screen.draw_input()
+        }
+        dcc::Event::Progress { id, .. } => screen.set_status(&format!("dcc{}", id), Some(line)),
+        dcc::Event::Finished { id, .. } | dcc::Event::Failed { id, .. } => {
+            manager.lock().unwrap().finished(*id);
+            screen.set_status(&format!("dcc{}", id), None)?;
+            screen.print(&line)
+        }
+        _ => screen.print(&line),
+    }
+}
+
+/// Changes the modes of the active channel, or one named in `/mode`.
+fn handle_modes(
+    input: Input,
+    screen: &mut Screen,
+    state: &State,
+    sender: &Arc<Mutex<Sender<Message>>>,
+) -> io::Result<()> {
+    let active = Some(screen.editor.buffer().to_string());
+    let channel = match &input {
+        Input::Mode(Some(channel), _, _) => Some(channel.clone()),
+        _ => active.filter(|b| b.starts_with(CHANNEL_PREFIXES)),
+    };
+    let channel = match channel {
+        Some(channel) => channel,
+        None => return screen.print("Not in a channel"),
+    };
+    let types = ModeTypes::new(state);
+    let changes = match input {
+        Input::Mode(_, modes, params) => types.parse(modes.as_deref().unwrap_or_default(), &params),
+        // Without a mask, asks for the list
+        Input::Ban(None) => types.parse("+b", &[]),
+        Input::Ban(Some(target)) => vec![Change::add('b', &modes::ban_mask(state, &target))],
+        Input::Unban(target) => {
+            let mask = match target.parse::<usize>() {
+                Ok(number) => match state.mode_lists.get(&channel, 'b', number) {
+                    Some(entry) => entry.mask.clone(),
+                    None => {
+                        let text = format!("No ban {} in {}, /ban lists them", number, channel);
+                        return screen.print(&text);
+                    }
+                },
+                Err(_) => modes::ban_mask(state, &target),
+            };
+            vec![Change::remove('b', &mask)]
+        }
+        Input::Quiet(target) => match types.quiet(state, &modes::ban_mask(state, &target)) {
+            Some(change) => vec![change],
+            None => return screen.print("The server has no way to quiet people"),
+        },
+        Input::Op(nicks) => nicks.iter().map(|n| Change::add('o', n)).collect(),
+        Input::Voice(nicks) => nicks.iter().map(|n| Change::add('v', n)).collect(),
+        _ => return Ok(()),
+    };
+    // Nothing to change shows the modes
+    let messages = match changes.is_empty() {
+        true => vec![Message::new(Command::Mode, vec![&channel])],
+        false => types.messages(&channel, &changes),
+    };
+    send(sender, messages);
+    Ok(())
+}
+
+/// Switches to the server notices, showing those that came in since they
+/// were last shown, or the latest few.
+fn show_snotices(screen: &mut Screen, state: &mut State) -> io::Result<()> {
+    screen.editor.set_buffer(snotices::BUFFER);
+    let count = state.snotices.unseen().max(SNOTICES_SHOWN);
+    for notice in state.snotices.recent(count) {
+        screen.print(&ui::format_snotice(notice))?;
+    }
+    screen.set_status("snotices", None)
+}
+
+fn handle_dcc(
+    command: DccCommand,
+    screen: &mut Screen,
+    manager: &Mutex<Manager>,
+    sender: &Mutex<Sender<Message>>,
+) -> io::Result<()> {
+    let mut manager = manager.lock().unwrap();
+    let result = match command {
+        DccCommand::List => {
+            let mut transfers = manager.transfers().peekable();
+            if transfers.peek().is_none() {
+                return screen.print("No DCC transfers");
+            }
+            for transfer in transfers {
+                let direction = match transfer.direction {
+                    dcc::Direction::Incoming => "from",
+                    dcc::Direction::Outgoing => "to",
+                };
+                screen.print(&format!(
+                    "{}: {} {} ({}, {})",
+                    transfer.id,
+                    transfer.describe(),
+                    direction,
+                    transfer.nick,
+                    transfer.status()
+                ))?;
+            }
+            return Ok(());
+        }
+        DccCommand::Accept(id) => manager.accept(id),
+        DccCommand::Reject(id) => manager.reject(id).map(|_| vec![]),
+        DccCommand::Chat { nick, passive } => {
+            screen.editor.set_buffer(&format!("={}", nick));
+            manager.offer_chat(&nick, passive)
+        }
+        DccCommand::Send {
+            nick,
+            path,
+            passive,
+        } => manager.offer_file(&nick, Path::new(&path), passive),
+        DccCommand::Close(nick) => {
+            manager.close_chat(&nick);
+            Ok(vec![])
         }
+    };
+    match result {
+        Ok(messages) => {
+            send(sender, messages);
+            Ok(())
+        }
+        Err(e) => screen.print(&e),
     }
+}
+
+/// Settings for a /connect URL.
+type Connect = Box<dyn Fn(&Url) -> Result<Settings, String> + Send>;
+
+/// What the input thread works with besides the screen and state.
+struct Inputs {
+    manager: Arc<Mutex<Manager>>,
+    sender: Arc<Mutex<Sender<Message>>>,
+    connect: Connect,
+    /// Where the reader thread goes next, after /connect.
+    next: Arc<Mutex<Option<Settings>>>,
+    /// The network we're on, as search results call it.
+    network: String,
+    /// The config file, where /notify keeps its changes.
+    config_path: Option<PathBuf>,
+    /// Whether to tell others when we're typing.
+    typing: bool,
+    store: Option<Store>,
+    /// The last /search results, for /jump.
+    hits: Vec<Hit>,
+}
+
+/// Keeps the notify list in the network's part of the config, so it's
+/// there next time.
+fn save_notify(inputs: &Inputs, notify: &NotifyList) -> io::Result<()> {
+    let path = inputs
+        .config_path
+        .as_ref()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "there's no config file"))?;
+    let nicks: Vec<&str> = notify.nicks().into_iter().map(|(nick, _)| nick).collect();
+    config::save_notify(path, &inputs.network, &nicks)
+}
+
+/// How many results /search shows, newest first.
+const SEARCH_RESULTS: usize = 20;
+/// How many messages /jump shows on either side of a result.
+const JUMP_CONTEXT: usize = 5;
+/// How many recent messages /reply lists to pick from.
+const REPLY_CHOICES: usize = 9;
+/// How many channels /list shows at a time.
+const LIST_PAGE: usize = 20;
+/// How many server notices are shown on switching to their buffer, at
+/// least.
+const SNOTICES_SHOWN: usize = 20;
+
+fn handle_input(
+    line: &str,
+    screen: &mut Screen,
+    state: &mut State,
+    inputs: &mut Inputs,
+) -> io::Result<()> {
+    let manager = &inputs.manager;
+    let sender = &inputs.sender;
+    let input = match Input::parse(line) {
+        Ok(input) => input,
+        Err(usage) => return screen.print(&usage),
+    };
 
+    match input {
+        Input::Text(text) if screen.editor.buffer().starts_with('=') => {
+            let nick = screen.editor.buffer()[1..].to_string();
+            match manager.lock().unwrap().send_chat(&nick, &text) {
+                Ok(_) => screen.print(&ui::format_own_message(&state.nick, &text))?,
+                Err(e) => screen.print(&format!("DCC chat with {}: {}", nick, e))?,
+            }
+        }
+        Input::Text(_) | Input::Me(_) if screen.editor.buffer() == snotices::BUFFER => {
+            screen.print("Server notices can't be answered, /wallops reaches other opers")?
+        }
+        // With echo-message our lines are shown once the server echoes them
+        Input::Text(text) => {
+            let buffer = screen.editor.buffer().to_string();
+            send(sender, client::priv_msg(state, &buffer, &text));
+            if !state.caps.is_enabled("echo-message") {
+                screen.print(&ui::format_own_message(&state.nick, &text))?;
+            }
+        }
+        Input::Me(text) => {
+            let buffer = screen.editor.buffer().to_string();
+            send(sender, client::action(state, &buffer, &text));
+            if !state.caps.is_enabled("echo-message") {
+                screen.print(&ui::format_own_action(&state.nick, &text))?;
+            }
+        }
+        Input::Ctcp(target, command, params) => {
+            let ctcp = Ctcp::new(&command, params.as_deref());
+            send(sender, vec![Message::ctcp_request(&target, &ctcp)]);
+            screen.print(&format!("-> {} CTCP {}", target, ctcp.command))?;
+        }
+        Input::Msg(target, text) => {
+            send(sender, client::priv_msg(state, &target, &text));
+            if !state.caps.is_enabled("echo-message") {
+                screen.print(&format!("-> {}: {}", target, text))?;
+            }
+        }
+        Input::Dcc(command) => handle_dcc(command, screen, manager, sender)?,
+        Input::Mode(..)
+        | Input::Ban(_)
+        | Input::Unban(_)
+        | Input::Quiet(_)
+        | Input::Op(_)
+        | Input::Voice(_) => handle_modes(input, screen, state, sender)?,
+        Input::Names(channel) => {
+            let channel = channel.unwrap_or_else(|| screen.editor.buffer().to_string());
+            match state.channels.contains_key(&channel) {
+                true => screen.print(&ui::format_names(&channel, state))?,
+                false => screen.print(&format!("Not in {}", channel))?,
+            }
+        }
+        Input::Notify(command) => {
+            let changed = command != NotifyCommand::List;
+            match command {
+                NotifyCommand::List => {}
+                NotifyCommand::Add(nick) if !state::is_nick(&nick) => {
+                    return screen.print(&format!("{:?} is not a nick", nick));
+                }
+                NotifyCommand::Add(nick) => send(sender, state.notify.add(&nick)),
+                NotifyCommand::Remove(nick) => send(sender, state.notify.remove(&nick)),
+            }
+            screen.print(&ui::format_notify_list(&state.notify))?;
+            if changed {
+                if let Err(e) = save_notify(inputs, &state.notify) {
+                    let text = format!("The notify list only lasts until you quit: {}", e);
+                    screen.print(&ui::format_warning(&text))?;
+                }
+            }
+            screen.set_status("online", ui::online_status(&state.notify.online()))?;
+        }
+        Input::Buffer(name) if name == snotices::BUFFER => show_snotices(screen, state)?,
+        Input::Buffer(name) | Input::Query(name) => screen.editor.set_buffer(&name),
+        Input::Snotices(filter) => {
+            if let Some(words) = filter {
+                state.snotices.set_filter(&words);
+                screen.print(&ui::format_snotices_filter(state.snotices.filter()))?;
+            }
+            show_snotices(screen, state)?;
+        }
+        Input::Oper(name, password) => {
+            let message = Message::new(Command::Oper, vec![&name, &password]);
+            send(sender, vec![message]);
+        }
+        Input::Kill(nick, reason) => {
+            let message = Message::new(Command::Kill, vec![&nick, &reason]);
+            send(sender, vec![message]);
+        }
+        // Servers only send wallops back to those with +w
+        Input::Wallops(text) => {
+            send(sender, vec![Message::new(Command::Wallops, vec![&text])]);
+            if !state.has_user_mode('w') {
+                screen.print(&format!("-> wallops: {}", text))?;
+            }
+        }
+        Input::UserMode(modes) => {
+            let params = [vec![state.nick.clone()], modes].concat();
+            let message = Message::new(Command::Mode, vec![]).with_params(params);
+            send(sender, vec![message]);
+        }
+        Input::Join(channel, key) => {
+            let messages = match key {
+                Some(key) => client::join_with_key(&channel, &key),
+                None => client::join(&channel),
+            };
+            send(sender, messages);
+            screen.editor.set_buffer(&client::channel_name(&channel));
+        }
+        Input::Part(channel, reason) => {
+            let channel = channel.unwrap_or_else(|| screen.editor.buffer().to_string());
+            send(sender, client::part(&channel, reason.as_deref()));
+        }
+        Input::Connect(url) => match (inputs.connect)(&url) {
+            // One server at a time, so this replaces the current connection
+            Ok(settings) => {
+                screen.editor.set_buffer(&settings.buffer());
+                inputs.network = settings.name.clone();
+                *inputs.next.lock().unwrap() = Some(settings);
+                send(sender, client::quit(Some("Changing servers")));
+            }
+            Err(e) => screen.print(&e)?,
+        },
+        Input::History(subcommand, count) => {
+            if !state.caps.is_enabled(history::CAP) {
+                return screen.print("The server doesn't keep chat history");
+            }
+            let buffer = screen.editor.buffer().to_string();
+            let subcommand = subcommand.unwrap_or_else(|| match state.history.oldest(&buffer) {
+                Some(oldest) => Subcommand::Before(oldest),
+                None => Subcommand::Latest(Reference::Any),
+            });
+            let limit = history::limit(state, count);
+            send(sender, vec![subcommand.message(&buffer, limit)]);
+        }
+        Input::Search(query) => {
+            let store = match &inputs.store {
+                Some(store) => store,
+                None => return screen.print("Search is turned off in the config"),
+            };
+            inputs.hits = match store.search(&query, SEARCH_RESULTS) {
+                Ok(hits) => hits,
+                Err(e) => return screen.print(&format!("Search failed: {}", e)),
+            };
+            if inputs.hits.is_empty() {
+                return screen.print("No messages found");
+            }
+            screen.print("Newest first, /jump <number> shows one in its buffer")?;
+            for (i, hit) in inputs.hits.iter().enumerate() {
+                screen.print(&ui::format_hit(hit, Some(i + 1), false))?;
+            }
+        }
+        Input::Jump(number) => {
+            let (store, hit) = match (&inputs.store, inputs.hits.get(number.wrapping_sub(1))) {
+                (Some(store), Some(hit)) => (store, hit),
+                _ => return screen.print(&format!("No search result {}", number)),
+            };
+            jump(screen, store, hit, &inputs.network)?;
+        }
+        Input::Reply(None) => {
+            let buffer = screen.editor.buffer().to_string();
+            let said: Vec<&Said> = state
+                .scrollback
+                .in_buffer(&buffer)
+                .take(REPLY_CHOICES)
+                .collect();
+            if said.is_empty() {
+                return screen.print(&format!("Nothing to reply to in {}", buffer));
+            }
+            screen.print("/reply <number> <text> replies to one of these")?;
+            for (i, said) in said.iter().enumerate().rev() {
+                screen.print(&ui::format_reply_choice(i + 1, said))?;
+            }
+        }
+        Input::Reply(Some((choice, text))) => {
+            let buffer = screen.editor.buffer().to_string();
+            let parent = match state.scrollback.pick(&buffer, &choice) {
+                Some(parent) => parent.clone(),
+                None => return screen.print(&format!("No message {} in {}", choice, buffer)),
+            };
+            send(sender, client::reply(state, &buffer, &text, &parent));
+            if !state.caps.is_enabled("echo-message") {
+                screen.print(&ui::format_quote(Some(&parent)))?;
+                screen.print(&ui::format_own_message(&state.nick, &text))?;
+            }
+        }
+        Input::Parent(number) => {
+            let buffer = screen.editor.buffer().to_string();
+            let replied = state
+                .scrollback
+                .in_buffer(&buffer)
+                .filter_map(|said| said.reply_to.clone())
+                .nth(number - 1);
+            let msgid = match replied {
+                Some(msgid) => msgid,
+                None => return screen.print(&format!("No reply {} in {}", number, buffer)),
+            };
+            if let Some(store) = &inputs.store {
+                match store.by_msgid(&inputs.network, &msgid) {
+                    Ok(Some(hit)) => return jump(screen, store, &hit, &inputs.network),
+                    Ok(None) => {}
+                    Err(e) => return screen.print(&format!("Search failed: {}", e)),
+                }
+            }
+            // Without the logs there's only what's still in scrollback
+            match state.scrollback.get(&msgid) {
+                Some(said) => screen.print(&format!(
+                    "-- {}: <{}> {}",
+                    said.buffer, said.nick, said.text
+                ))?,
+                None => screen.print("The message replied to is no longer around")?,
+            }
+        }
+        Input::List(ListCommand::Fetch(filters)) => {
+            let elist = state.isupport("ELIST").unwrap_or_default().to_string();
+            match state.list.request(filters, &elist) {
+                Ok(message) => send(sender, vec![message]),
+                Err(e) => screen.print(&e)?,
+            }
+        }
+        Input::List(ListCommand::Show {
+            by_name,
+            page,
+            text,
+        }) => show_list(screen, state, by_name, page, &text)?,
+        Input::List(ListCommand::Join(number)) => match state.list.pick(number) {
+            Some(entry) => {
+                send(sender, client::join(&entry.channel));
+                screen.editor.set_buffer(&entry.channel);
+            }
+            None => screen.print(&format!("No channel {} in the list", number))?,
+        },
+        Input::Whois(nick) => send(sender, vec![state.whois.whois(&nick)]),
+        Input::Whowas(nick) => send(sender, vec![state.whois.whowas(&nick)]),
+        Input::Who(mask) => {
+            let whox = state.isupport("WHOX").is_some();
+            send(sender, vec![state.whois.who(&mask, whox)]);
+        }
+        Input::Nick(nick) => send(sender, client::nick(&nick)),
+        Input::Quit(reason) => send(sender, client::quit(reason.as_deref())),
+    }
+    Ok(())
+}
+
+/// Shows a page of the channels from the last LIST, biggest first unless
+/// `by_name`, and only those with `text` in their name or topic.
+fn show_list(
+    screen: &mut Screen,
+    state: &mut State,
+    by_name: bool,
+    page: usize,
+    text: &str,
+) -> io::Result<()> {
+    let listing = state.list.is_listing();
+    if state.list.len() == 0 && !listing {
+        return screen.print("No channels listed, /list fetches them");
+    }
+    let (entries, total) = state.list.show(by_name, text, page, LIST_PAGE);
+    let pages = total.div_ceil(LIST_PAGE).max(1);
+    screen.print(&ui::format_list(&entries, total, (page, pages), listing))
+}
+
+/// Shows `hit` among the messages around it, switching to its buffer.
+fn jump(screen: &mut Screen, store: &Store, hit: &Hit, network: &str) -> io::Result<()> {
+    let context = match store.context(hit, JUMP_CONTEXT) {
+        Ok(context) => context,
+        Err(e) => return screen.print(&format!("Search failed: {}", e)),
+    };
+    // Buffers on other networks can't be written to from here
+    if hit.network == network {
+        screen.editor.set_buffer(&hit.buffer);
+    }
+    screen.print(&format!("-- {} on {}", hit.buffer, hit.network))?;
+    for line in &context {
+        screen.print(&ui::format_hit(line, None, line.id == hit.id))?;
+    }
     Ok(())
 }
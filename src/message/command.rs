@@ -4,22 +4,57 @@ use std::fmt::{self, Display};
 pub enum Command {
     Numeric(u32),
     Cap,
+    Pass,
+    WebIrc,
     Notice,
     Nick,
     User,
     Join,
+    Part,
+    Kick,
+    Quit,
     PrivMsg,
+    TagMsg,
     Ping,
     Pong,
+    Authenticate,
+    Batch,
+    ChatHistory,
+    Account,
+    Away,
+    ChgHost,
+    SetName,
+    Invite,
+    Monitor,
+    Watch,
+    Ison,
+    Who,
+    Whois,
+    Whowas,
+    List,
+    Mode,
+    Oper,
+    Kill,
+    Wallops,
     Error,
     RplWelcome,
     RplYourHost,
     RplCreated,
     RplMyInfo,
+    RplISupport,
+    RplNamReply,
+    RplEndOfNames,
+    ErrNicknameInUse,
+    RplSaslSuccess,
+    ErrSaslFail,
+    ErrSaslTooLong,
+    ErrSaslAborted,
+    ErrSaslAlready,
     MessageOfTheDay,
     RplMotd,
     RplMotdStart,
     RplEndOfMotd,
+    ErrNoMotd,
     Unknown(String),
 }
 
@@ -28,11 +63,37 @@ impl From<&str> for Command {
         match s {
             "NOTICE" => Command::Notice,
             "PRIVMSG" => Command::PrivMsg,
+            "TAGMSG" => Command::TagMsg,
             "NICK" => Command::Nick,
             "CAP" => Command::Cap,
+            "PASS" => Command::Pass,
+            "WEBIRC" => Command::WebIrc,
             "USER" => Command::User,
+            "JOIN" => Command::Join,
+            "PART" => Command::Part,
+            "KICK" => Command::Kick,
+            "QUIT" => Command::Quit,
             "PING" => Command::Ping,
-            "PRIVMSG" => Command::PrivMsg,
+            "PONG" => Command::Pong,
+            "BATCH" => Command::Batch,
+            "CHATHISTORY" => Command::ChatHistory,
+            "ACCOUNT" => Command::Account,
+            "AWAY" => Command::Away,
+            "CHGHOST" => Command::ChgHost,
+            "SETNAME" => Command::SetName,
+            "INVITE" => Command::Invite,
+            "MONITOR" => Command::Monitor,
+            "WATCH" => Command::Watch,
+            "ISON" => Command::Ison,
+            "WHO" => Command::Who,
+            "WHOIS" => Command::Whois,
+            "WHOWAS" => Command::Whowas,
+            "LIST" => Command::List,
+            "MODE" => Command::Mode,
+            "OPER" => Command::Oper,
+            "KILL" => Command::Kill,
+            "WALLOPS" => Command::Wallops,
+            "AUTHENTICATE" => Command::Authenticate,
             "ERROR" => Command::Error,
             "MOTD" => Command::MessageOfTheDay,
             "RPL_MOTD" => Command::RplMotd,
@@ -43,24 +104,50 @@ impl From<&str> for Command {
                 "002" => Command::RplYourHost,
                 "003" => Command::RplCreated,
                 "004" => Command::RplMyInfo,
+                "005" => Command::RplISupport,
+                "353" => Command::RplNamReply,
+                "366" => Command::RplEndOfNames,
+                "433" => Command::ErrNicknameInUse,
+                "903" => Command::RplSaslSuccess,
+                "904" => Command::ErrSaslFail,
+                "905" => Command::ErrSaslTooLong,
+                "906" => Command::ErrSaslAborted,
+                "907" => Command::ErrSaslAlready,
                 "372" => Command::RplMotd,
                 "375" => Command::RplMotdStart,
                 "376" => Command::RplEndOfMotd,
-                _ => {
-                    Command::Unknown(s.to_string())
-                }
+                "422" => Command::ErrNoMotd,
+                _ => Command::Unknown(s.to_string()),
             },
         }
     }
 }
 
+impl Command {
+    /// Error numerics, 400 to 599, and `FAIL` standard replies.
+    pub fn is_error(&self) -> bool {
+        let is_error_code = |n: u32| (400..600).contains(&n);
+        match self {
+            Command::ErrNicknameInUse
+            | Command::ErrNoMotd
+            | Command::ErrSaslFail
+            | Command::ErrSaslTooLong
+            | Command::ErrSaslAborted
+            | Command::ErrSaslAlready => true,
+            Command::Numeric(n) => is_error_code(*n),
+            Command::Unknown(s) => s == "FAIL" || s.parse().is_ok_and(is_error_code),
+            _ => false,
+        }
+    }
+}
+
 impl PartialEq<String> for Command {
     fn eq(&self, rhs: &String) -> bool {
         &self.to_string() == rhs
     }
 }
 
-impl PartialEq<&Command> for String { 
+impl PartialEq<&Command> for String {
     fn eq(&self, rhs: &&Command) -> bool {
         self == &rhs.to_string()
     }
@@ -74,24 +161,58 @@ impl Display for Command {
             }
             Command::Unknown(s) => s.clone(),
             Command::Cap => "CAP".to_string(),
+            Command::Pass => "PASS".to_string(),
+            Command::WebIrc => "WEBIRC".to_string(),
             Command::Notice => "NOTICE".to_string(),
             Command::Nick => "NICK".to_string(),
             Command::User => "USER".to_string(),
             Command::PrivMsg => "PRIVMSG".to_string(),
+            Command::TagMsg => "TAGMSG".to_string(),
             Command::Ping => "PING".to_string(),
             Command::Pong => "PONG".to_string(),
+            Command::Batch => "BATCH".to_string(),
+            Command::ChatHistory => "CHATHISTORY".to_string(),
+            Command::Account => "ACCOUNT".to_string(),
+            Command::Away => "AWAY".to_string(),
+            Command::ChgHost => "CHGHOST".to_string(),
+            Command::SetName => "SETNAME".to_string(),
+            Command::Invite => "INVITE".to_string(),
+            Command::Monitor => "MONITOR".to_string(),
+            Command::Watch => "WATCH".to_string(),
+            Command::Ison => "ISON".to_string(),
+            Command::Who => "WHO".to_string(),
+            Command::Whois => "WHOIS".to_string(),
+            Command::Whowas => "WHOWAS".to_string(),
+            Command::List => "LIST".to_string(),
+            Command::Mode => "MODE".to_string(),
+            Command::Oper => "OPER".to_string(),
+            Command::Kill => "KILL".to_string(),
+            Command::Wallops => "WALLOPS".to_string(),
+            Command::Authenticate => "AUTHENTICATE".to_string(),
             Command::Join => "JOIN".to_string(),
-            Command::RplWelcome => "RPL_WELCOME".to_string(),
+            Command::Part => "PART".to_string(),
+            Command::Kick => "KICK".to_string(),
+            Command::Quit => "QUIT".to_string(),
             Command::MessageOfTheDay => "MOTD".to_string(),
             Command::Error => "ERROR".to_string(),
             Command::RplMotd => "RPL_MOTD".to_string(),
             Command::RplMotdStart => "RPL_MOTDSTART".to_string(),
             Command::RplEndOfMotd => "RPL_ENDOFMOTD".to_string(),
+            Command::ErrNoMotd => "ERR_NOMOTD".to_string(),
             Command::RplWelcome => "RPL_WELCOME".to_string(),
             Command::RplYourHost => "RPL_YOURHOST".to_string(),
             Command::RplCreated => "RPL_CREATED".to_string(),
-            Command::RplMyInfo => "RPL_MYINFO".to_string()
+            Command::RplMyInfo => "RPL_MYINFO".to_string(),
+            Command::RplISupport => "RPL_ISUPPORT".to_string(),
+            Command::RplNamReply => "RPL_NAMREPLY".to_string(),
+            Command::RplEndOfNames => "RPL_ENDOFNAMES".to_string(),
+            Command::ErrNicknameInUse => "ERR_NICKNAMEINUSE".to_string(),
+            Command::RplSaslSuccess => "RPL_SASLSUCCESS".to_string(),
+            Command::ErrSaslFail => "ERR_SASLFAIL".to_string(),
+            Command::ErrSaslTooLong => "ERR_SASLTOOLONG".to_string(),
+            Command::ErrSaslAborted => "ERR_SASLABORTED".to_string(),
+            Command::ErrSaslAlready => "ERR_SASLALREADY".to_string(),
         };
         write!(f, "{}", command)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,110 @@
+//! Client-To-Client Protocol messages, carried inside PRIVMSG (requests) and
+//! NOTICE (replies).
+//!
+//! <https://modern.ircdocs.horse/ctcp.html>
+
+use std::fmt::{self, Display};
+
+use crate::message::parser;
+use crate::message::{Command, Message};
+
+const DELIMITER: char = '\u{1}';
+
+#[derive(Debug, PartialEq)]
+pub struct Ctcp {
+    pub command: String,
+    pub params: Option<String>,
+}
+
+impl Ctcp {
+    pub fn new(command: &str, params: Option<&str>) -> Self {
+        Self {
+            command: command.to_uppercase(),
+            params: params.map(|p| p.to_string()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let (_, (command, params)) = parser::ctcp(text).ok()?;
+        Some(Self::new(command, params))
+    }
+
+    pub fn is_action(&self) -> bool {
+        self.command == "ACTION"
+    }
+}
+
+impl Display for Ctcp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.params {
+            Some(params) => write!(f, "{}{} {}{}", DELIMITER, self.command, params, DELIMITER),
+            None => write!(f, "{}{}{}", DELIMITER, self.command, DELIMITER),
+        }
+    }
+}
+
+impl Message {
+    /// The CTCP carried by this message, if it's a PRIVMSG or NOTICE with one.
+    pub fn ctcp(&self) -> Option<Ctcp> {
+        match self.command() {
+            Command::PrivMsg | Command::Notice => Ctcp::parse(&self.get_param(1)?.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn ctcp_request(target: &str, ctcp: &Ctcp) -> Self {
+        Message::new(Command::PrivMsg, vec![target, &ctcp.to_string()])
+    }
+
+    pub fn ctcp_reply(target: &str, ctcp: &Ctcp) -> Self {
+        Message::new(Command::Notice, vec![target, &ctcp.to_string()])
+    }
+
+    pub fn action(target: &str, text: &str) -> Self {
+        Message::ctcp_request(target, &Ctcp::new("ACTION", Some(text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ctcp::parse("\u{1}ping 1234\u{1}"),
+            Some(Ctcp::new("PING", Some("1234")))
+        );
+        assert_eq!(Ctcp::parse("not a ctcp"), None);
+    }
+
+    #[test]
+    fn test_message_ctcp() {
+        let message = parse_line(":alice!a@host PRIVMSG #rust :\u{1}ACTION waves\u{1}");
+        let ctcp = message.ctcp().unwrap();
+        assert!(ctcp.is_action());
+        assert_eq!(ctcp.params, Some("waves".to_string()));
+    }
+
+    #[test]
+    fn test_encode() {
+        let message = Message::action("#rust", "waves");
+        assert_eq!(
+            message.as_bytes(),
+            b"PRIVMSG #rust :\x01ACTION waves\x01\r\n".to_vec()
+        );
+
+        let message = Message::ctcp_reply("alice", &Ctcp::new("VERSION", Some("yairc 0.1.0")));
+        assert_eq!(
+            message.as_bytes(),
+            b"NOTICE alice :\x01VERSION yairc 0.1.0\x01\r\n".to_vec()
+        );
+
+        let message = Message::ctcp_request("alice", &Ctcp::new("VERSION", None));
+        assert_eq!(
+            message.as_bytes(),
+            b"PRIVMSG alice \x01VERSION\x01\r\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Direct Client-to-Client negotiation, carried in `DCC` CTCP requests.
+//!
+//! <https://modern.ircdocs.horse/dcc.html>
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::message::ctcp::Ctcp;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dcc {
+    Chat {
+        address: IpAddr,
+        port: u16,
+        token: Option<String>,
+    },
+    Send {
+        filename: String,
+        address: IpAddr,
+        port: u16,
+        size: Option<u64>,
+        token: Option<String>,
+    },
+    Resume {
+        filename: String,
+        port: u16,
+        position: u64,
+        token: Option<String>,
+    },
+    Accept {
+        filename: String,
+        port: u16,
+        position: u64,
+        token: Option<String>,
+    },
+}
+
+impl Dcc {
+    pub fn parse(ctcp: &Ctcp) -> Option<Self> {
+        if ctcp.command != "DCC" {
+            return None;
+        }
+        let params = ctcp.params.as_deref()?;
+        let (kind, rest) = split_once(params)?;
+        let (name, rest) = filename(rest)?;
+        let mut args = rest.split_whitespace();
+
+        match kind.to_uppercase().as_str() {
+            "CHAT" => Some(Dcc::Chat {
+                address: address(args.next()?)?,
+                port: args.next()?.parse().ok()?,
+                token: args.next().map(|t| t.to_string()),
+            }),
+            "SEND" => Some(Dcc::Send {
+                filename: name,
+                address: address(args.next()?)?,
+                port: args.next()?.parse().ok()?,
+                // 0 is what's sent when the size isn't known
+                size: args.next().and_then(|s| s.parse().ok()).filter(|s| *s > 0),
+                token: args.next().map(|t| t.to_string()),
+            }),
+            "RESUME" => Some(Dcc::Resume {
+                filename: name,
+                port: args.next()?.parse().ok()?,
+                position: args.next()?.parse().ok()?,
+                token: args.next().map(|t| t.to_string()),
+            }),
+            "ACCEPT" => Some(Dcc::Accept {
+                filename: name,
+                port: args.next()?.parse().ok()?,
+                position: args.next()?.parse().ok()?,
+                token: args.next().map(|t| t.to_string()),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Passive (reverse) offers have no port, the receiver listens instead
+    /// and answers with its own address and the same token.
+    pub fn is_passive(&self) -> bool {
+        match self {
+            Dcc::Chat { port, token, .. } | Dcc::Send { port, token, .. } => {
+                *port == 0 && token.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            Dcc::Chat { token, .. }
+            | Dcc::Send { token, .. }
+            | Dcc::Resume { token, .. }
+            | Dcc::Accept { token, .. } => token.as_deref(),
+        }
+    }
+
+    pub fn to_ctcp(&self) -> Ctcp {
+        let mut params = match self {
+            Dcc::Chat { address, port, .. } => {
+                format!("CHAT chat {} {}", encode_address(address), port)
+            }
+            Dcc::Send {
+                filename,
+                address,
+                port,
+                size,
+                ..
+            } => format!(
+                "SEND {} {} {} {}",
+                encode_filename(filename),
+                encode_address(address),
+                port,
+                size.unwrap_or(0)
+            ),
+            Dcc::Resume {
+                filename,
+                port,
+                position,
+                ..
+            } => format!("RESUME {} {} {}", encode_filename(filename), port, position),
+            Dcc::Accept {
+                filename,
+                port,
+                position,
+                ..
+            } => format!("ACCEPT {} {} {}", encode_filename(filename), port, position),
+        };
+        if let Some(token) = self.token() {
+            params.push(' ');
+            params.push_str(token);
+        }
+        Ctcp::new("DCC", Some(&params))
+    }
+}
+
+fn split_once(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    match s.find(' ') {
+        Some(i) => Some((&s[..i], s[i + 1..].trim_start())),
+        None => None,
+    }
+}
+
+/// Filenames with spaces are sent in double quotes.
+fn filename(s: &str) -> Option<(String, &str)> {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        Some((quoted[..end].to_string(), &quoted[end + 1..]))
+    } else {
+        let (name, rest) = split_once(s)?;
+        Some((name.to_string(), rest))
+    }
+}
+
+fn encode_filename(filename: &str) -> String {
+    if filename.contains(' ') {
+        format!("\"{}\"", filename)
+    } else {
+        filename.to_string()
+    }
+}
+
+/// IPv4 addresses are sent as a single 32-bit integer, IPv6 ones as text.
+fn address(s: &str) -> Option<IpAddr> {
+    match s.parse::<u32>() {
+        Ok(n) => Some(IpAddr::V4(Ipv4Addr::from(n))),
+        Err(_) => s.parse().ok(),
+    }
+}
+
+fn encode_address(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => u32::from(*v4).to_string(),
+        IpAddr::V6(v6) => v6.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dcc(params: &str) -> Option<Dcc> {
+        Dcc::parse(&Ctcp::new("DCC", Some(params)))
+    }
+
+    #[test]
+    fn test_send() {
+        assert_eq!(
+            dcc("SEND notes.txt 2130706433 5000 1024"),
+            Some(Dcc::Send {
+                filename: "notes.txt".to_string(),
+                address: "127.0.0.1".parse().unwrap(),
+                port: 5000,
+                size: Some(1024),
+                token: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_quoted_filename() {
+        let send = dcc("SEND \"my notes.txt\" ::1 5000 1024").unwrap();
+        match &send {
+            Dcc::Send {
+                filename, address, ..
+            } => {
+                assert_eq!(filename, "my notes.txt");
+                assert_eq!(address, &"::1".parse::<IpAddr>().unwrap());
+            }
+            _ => panic!("Expected a SEND"),
+        }
+        assert_eq!(
+            send.to_ctcp().params.unwrap(),
+            "SEND \"my notes.txt\" ::1 5000 1024"
+        );
+    }
+
+    #[test]
+    fn test_passive() {
+        let send = dcc("SEND file.bin 16909060 0 99 17").unwrap();
+        assert!(send.is_passive());
+        assert_eq!(send.token(), Some("17"));
+        assert_eq!(
+            send.to_ctcp().params.unwrap(),
+            "SEND file.bin 16909060 0 99 17"
+        );
+    }
+
+    #[test]
+    fn test_unknown_size() {
+        let send = Dcc::Send {
+            filename: "file.bin".to_string(),
+            address: "1.2.3.4".parse().unwrap(),
+            port: 0,
+            size: None,
+            token: Some("17".to_string()),
+        };
+        let params = send.to_ctcp().params.unwrap();
+        assert_eq!(params, "SEND file.bin 16909060 0 0 17");
+        assert_eq!(dcc(&params), Some(send));
+    }
+
+    #[test]
+    fn test_chat() {
+        let chat = dcc("CHAT chat 2130706433 4000").unwrap();
+        assert!(!chat.is_passive());
+        assert_eq!(chat.to_ctcp().params.unwrap(), "CHAT chat 2130706433 4000");
+    }
+
+    #[test]
+    fn test_resume_and_accept() {
+        assert_eq!(
+            dcc("RESUME file.bin 5000 512"),
+            Some(Dcc::Resume {
+                filename: "file.bin".to_string(),
+                port: 5000,
+                position: 512,
+                token: None,
+            })
+        );
+        let accept = Dcc::Accept {
+            filename: "file.bin".to_string(),
+            port: 0,
+            position: 512,
+            token: Some("7".to_string()),
+        };
+        assert_eq!(accept.to_ctcp().params.unwrap(), "ACCEPT file.bin 0 512 7");
+    }
+
+    #[test]
+    fn test_not_dcc() {
+        assert_eq!(Dcc::parse(&Ctcp::new("VERSION", None)), None);
+        assert_eq!(dcc("SEND"), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! mIRC style formatting codes in message text.
+//!
+//! <https://modern.ircdocs.horse/formatting.html>
+
+use crate::message::parser::{color_code, control_character, formatted_text, hex_color_code};
+
+pub const BOLD: char = '\u{2}';
+pub const COLOR: char = '\u{3}';
+pub const HEX_COLOR: char = '\u{4}';
+pub const RESET: char = '\u{f}';
+pub const MONOSPACE: char = '\u{11}';
+pub const REVERSE: char = '\u{16}';
+pub const ITALIC: char = '\u{1d}';
+pub const STRIKETHROUGH: char = '\u{1e}';
+pub const UNDERLINE: char = '\u{1f}';
+
+/// Color 99 means "the default color" rather than an actual color.
+const DEFAULT_COLOR: u8 = 99;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// One of the 99 numbered mIRC colors.
+    Palette(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    pub reverse: bool,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+impl Style {
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// The control codes that turn plain text into this style.
+    pub fn codes(&self) -> String {
+        let mut codes = String::new();
+        let toggles = [
+            (self.bold, BOLD),
+            (self.italic, ITALIC),
+            (self.underline, UNDERLINE),
+            (self.strikethrough, STRIKETHROUGH),
+            (self.monospace, MONOSPACE),
+            (self.reverse, REVERSE),
+        ];
+        for (on, code) in toggles.iter() {
+            if *on {
+                codes.push(*code);
+            }
+        }
+        let hex = |c: Option<Color>| match c {
+            Some(Color::Rgb(r, g, b)) => format!("{:02X}{:02X}{:02X}", r, g, b),
+            _ => "000000".to_string(),
+        };
+        let number = |c: Option<Color>| match c {
+            Some(Color::Palette(n)) => n,
+            _ => DEFAULT_COLOR,
+        };
+        let is_rgb = |c: Option<Color>| matches!(c, Some(Color::Rgb(..)));
+        // A hex code always sets the foreground, and either code leaves the
+        // background alone without one, so a palette color mixed with an
+        // RGB one gets a code of its own
+        match (self.fg, self.bg) {
+            (None, None) => {}
+            (fg, bg) if is_rgb(bg) => {
+                codes.push_str(&format!("{}{},{}", HEX_COLOR, hex(fg), hex(bg)));
+                if !is_rgb(fg) {
+                    codes.push_str(&format!("{}{:02}", COLOR, number(fg)));
+                }
+            }
+            (fg, bg) if is_rgb(fg) => {
+                if bg.is_some() {
+                    codes.push_str(&format!("{}{:02},{:02}", COLOR, DEFAULT_COLOR, number(bg)));
+                }
+                codes.push_str(&format!("{}{}", HEX_COLOR, hex(fg)));
+            }
+            (fg, bg) => {
+                codes.push_str(&format!("{}{:02}", COLOR, number(fg)));
+                if bg.is_some() {
+                    codes.push_str(&format!(",{:02}", number(bg)));
+                }
+            }
+        }
+        codes
+    }
+}
+
+/// A run of text sharing the same style.
+#[derive(Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Splits formatted text into styled spans, dropping the control codes.
+pub fn parse(text: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    scan(text, |t, style| match spans.last_mut() {
+        Some(span) if span.style == style => span.text.push_str(t),
+        _ => spans.push(Span {
+            text: t.to_string(),
+            style,
+        }),
+    });
+    spans
+}
+
+/// The style in effect at the end of `text`, which is what text appended
+/// to it would be shown in.
+pub fn style_after(text: &str) -> Style {
+    scan(text, |_, _| {})
+}
+
+/// Walks `text`, calling `on_text` for each run of text with its style, and
+/// returns the style at the end.
+fn scan(text: &str, mut on_text: impl FnMut(&str, Style)) -> Style {
+    let mut style = Style::default();
+    let mut i = text;
+
+    while !i.is_empty() {
+        if let Ok((rest, t)) = formatted_text(i) {
+            on_text(t, style);
+            i = rest;
+            continue;
+        }
+
+        let (rest, code) = match control_character(i) {
+            Ok(result) => result,
+            Err(_) => break,
+        };
+        i = rest;
+        match code.chars().next() {
+            Some(BOLD) => style.bold = !style.bold,
+            Some(ITALIC) => style.italic = !style.italic,
+            Some(UNDERLINE) => style.underline = !style.underline,
+            Some(STRIKETHROUGH) => style.strikethrough = !style.strikethrough,
+            Some(MONOSPACE) => style.monospace = !style.monospace,
+            Some(REVERSE) => style.reverse = !style.reverse,
+            Some(RESET) => style = Style::default(),
+            Some(COLOR) => {
+                if let Ok((rest, (fg, bg))) = color_code(i) {
+                    i = rest;
+                    set_colors(&mut style, fg.map(palette), bg.map(palette));
+                }
+            }
+            Some(HEX_COLOR) => {
+                if let Ok((rest, (fg, bg))) = hex_color_code(i) {
+                    i = rest;
+                    set_colors(&mut style, fg.map(rgb), bg.map(rgb));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    style
+}
+
+/// Removes all formatting, leaving plain text suitable for logs.
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|span| span.text).collect()
+}
+
+/// A color code on its own resets both colors, otherwise only the colors
+/// given change.
+fn set_colors(style: &mut Style, fg: Option<Option<Color>>, bg: Option<Option<Color>>) {
+    match (fg, bg) {
+        (None, _) => {
+            style.fg = None;
+            style.bg = None;
+        }
+        (Some(fg), bg) => {
+            style.fg = fg;
+            if let Some(bg) = bg {
+                style.bg = bg;
+            }
+        }
+    }
+}
+
+fn palette(number: &str) -> Option<Color> {
+    match number.parse::<u8>() {
+        Ok(DEFAULT_COLOR) | Err(_) => None,
+        Ok(n) => Some(Color::Palette(n)),
+    }
+}
+
+fn rgb(hex: &str) -> Option<Color> {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(parse("hello"), vec![span("hello", Style::default())]);
+    }
+
+    #[test]
+    fn test_toggles() {
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let bold_italic = Style {
+            italic: true,
+            ..bold
+        };
+        assert_eq!(
+            parse("a\u{2}b\u{1d}c\u{2}\u{1d}d"),
+            vec![
+                span("a", Style::default()),
+                span("b", bold),
+                span("c", bold_italic),
+                span("d", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colors() {
+        let red_on_blue = Style {
+            fg: Some(Color::Palette(4)),
+            bg: Some(Color::Palette(2)),
+            ..Style::default()
+        };
+        let green_on_blue = Style {
+            fg: Some(Color::Palette(3)),
+            ..red_on_blue
+        };
+        assert_eq!(
+            parse("\u{3}4,2red\u{3}03green\u{3}plain"),
+            vec![
+                span("red", red_on_blue),
+                span("green", green_on_blue),
+                span("plain", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comma_without_color_is_text() {
+        assert_eq!(parse("\u{3},5"), vec![span(",5", Style::default())]);
+    }
+
+    #[test]
+    fn test_default_color() {
+        assert_eq!(parse("\u{3}99x"), vec![span("x", Style::default())]);
+    }
+
+    #[test]
+    fn test_hex_colors() {
+        let style = Style {
+            fg: Some(Color::Rgb(0xff, 0x88, 0x00)),
+            bg: Some(Color::Rgb(0, 0, 0)),
+            ..Style::default()
+        };
+        assert_eq!(
+            parse("\u{4}FF8800,000000orange"),
+            vec![span("orange", style)]
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        assert_eq!(
+            parse("\u{2}\u{1f}\u{3}4a\u{f}b"),
+            vec![
+                span(
+                    "a",
+                    Style {
+                        bold: true,
+                        underline: true,
+                        fg: Some(Color::Palette(4)),
+                        ..Style::default()
+                    }
+                ),
+                span("b", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(
+            strip("\u{2}bold\u{2} \u{3}12,01blue\u{3} \u{1e}gone\u{f}"),
+            "bold blue gone"
+        );
+    }
+
+    #[test]
+    fn test_style_after_and_codes() {
+        let style = style_after("\u{2}bold \u{3}4,12red\u{1d} italic");
+        assert_eq!(
+            style,
+            Style {
+                bold: true,
+                italic: true,
+                fg: Some(Color::Palette(4)),
+                bg: Some(Color::Palette(12)),
+                ..Style::default()
+            }
+        );
+        assert_eq!(style.codes(), "\u{2}\u{1d}\u{3}04,12");
+        assert_eq!(style_after(&format!("{}x", style.codes())), style);
+
+        let hex = style_after("\u{4}FF8800orange");
+        assert_eq!(hex.codes(), "\u{4}FF8800");
+        assert_eq!(style_after("\u{2}a\u{f}").codes(), "");
+
+        // RGB and palette colors mixed, or an RGB background on its own
+        let rgb = Some(Color::Rgb(0xff, 0x88, 0));
+        for (fg, bg) in [
+            (rgb, Some(Color::Palette(2))),
+            (Some(Color::Palette(4)), rgb),
+            (None, rgb),
+        ] {
+            let style = Style {
+                fg,
+                bg,
+                ..Style::default()
+            };
+            assert_eq!(style_after(&format!("{}x", style.codes())), style);
+        }
+    }
+}
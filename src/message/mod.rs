@@ -1,27 +1,44 @@
 use std::fmt;
 use std::fmt::Display;
 
-mod parser;
+use chrono::{DateTime, Utc};
+
 mod command;
-mod source;
+pub mod ctcp;
+pub mod dcc;
+pub mod format;
 mod param;
+mod parser;
+mod source;
+pub mod split;
 
-use crate::message::parser::message;
 pub use crate::message::command::Command;
 use crate::message::param::{Param, Params};
+use crate::message::parser::message;
 use crate::message::source::Source;
 
 #[derive(Debug, PartialEq)]
-struct Tag(String, String);
-type Tags = Vec<Tag>;
+pub struct Tag(String, String);
+pub type Tags = Vec<Tag>;
 
+impl Tag {
+    pub fn key(&self) -> &str {
+        &self.0
+    }
+
+    pub fn value(&self) -> &str {
+        &self.1
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Message {
     tags: Option<Tags>,
     source: Option<Source>,
     command: Command,
-    params: Option<Params>
+    params: Option<Params>,
+    /// When the message was sent, from its `time` tag, or when we got it.
+    time: Option<DateTime<Utc>>,
 }
 
 impl Message {
@@ -31,16 +48,12 @@ impl Message {
             source: None,
             command,
             params: Some(Params::from(params)),
+            time: None,
         }
     }
 
-    pub fn ping() -> Self {
-        Message {
-            tags: None,
-            source: None,
-            command: Command::Ping,
-            params: None
-        }
+    pub fn ping(token: &str) -> Self {
+        Message::new(Command::Ping, vec![token])
     }
 
     pub fn pong(server: Param) -> Self {
@@ -48,7 +61,8 @@ impl Message {
             tags: None,
             source: None,
             command: Command::Pong,
-            params: Some(Params::from([server]))
+            params: Some(Params::from([server])),
+            time: None,
         }
     }
 
@@ -57,14 +71,20 @@ impl Message {
             tags: None,
             source: None,
             command: Command::MessageOfTheDay,
-            params: None
+            params: None,
+            time: None,
         }
     }
 
-    pub fn priv_msg(nick: String, message: String) -> Self {
-        let source = Some(Source { nick, user: None, host: None });
-        let mut params = vec![message];
-        Message { tags: None, source , command: Command::PrivMsg, params: Some(params.into()) }
+    pub fn priv_msg(target: String, message: String) -> Self {
+        let params = vec![target, message];
+        Message {
+            tags: None,
+            source: None,
+            command: Command::PrivMsg,
+            params: Some(params.into()),
+            time: None,
+        }
     }
 
     /// Get a reference to the message's tags.
@@ -72,6 +92,14 @@ impl Message {
         self.tags.as_ref()
     }
 
+    /// The value of the tag `key`, empty if it has none.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags()?
+            .iter()
+            .find(|t| t.key() == key)
+            .map(|t| t.value())
+    }
+
     /// Get a reference to the message's source.
     pub fn source(&self) -> Option<&Source> {
         self.source.as_ref()
@@ -82,50 +110,128 @@ impl Message {
     }
 
     pub fn parse(raw: &str) -> Result<Self, Box<dyn std::error::Error + '_>> {
-        let (i, (tags, source, command, params)) = message(raw)?;
-        let tags = match tags {
-            Some(tags) => {
-                let tags = tags
-                    .iter()
-                    .map(|t| Tag(t.0.to_string(), t.1.to_string()))
-                    .collect();
-                Some(tags)
-            }
-            None => None,
-        };
-        let source = match source {
-            None => None,
-            Some(source) => Some(Source::from(source)),
-        };
+        let (_, (tags, source, command, params)) = message(raw)?;
+        let tags = tags.map(|tags| {
+            tags.iter()
+                .map(|t| Tag(t.0.to_string(), unescape_tag_value(t.1)))
+                .collect()
+        });
+        let source = source.map(Source::from);
         let command = Command::from(command);
         let params = params.map(|p| p.iter().map(|p| Param::from(*p)).collect());
-        Ok(Self {
+        let mut message = Self {
             tags,
             source,
             command,
             params,
-        })
+            time: None,
+        };
+        message.time = message.tag("time").and_then(parse_time);
+        Ok(message)
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        self.time
+    }
+
+    /// Notes when the message was received, unless the server said when it
+    /// was sent.
+    pub fn received_at(mut self, time: DateTime<Utc>) -> Self {
+        self.time.get_or_insert(time);
+        self
+    }
+
+    /// Adds a tag, one without a value when `value` is `None`.
+    pub fn with_tag(mut self, key: &str, value: Option<&str>) -> Self {
+        if key == "time" {
+            self.time = value.and_then(parse_time).or(self.time);
+        }
+        let tag = Tag(key.to_string(), value.unwrap_or_default().to_string());
+        self.tags.get_or_insert_with(Vec::new).push(tag);
+        self
+    }
+
+    pub fn with_params(mut self, params: Vec<String>) -> Self {
+        self.params = Some(params.into());
+        self
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        let s = if let Some(params) = &self.params {
-            format!("{} {}\r\n", self.command, params)
+        let tags = match &self.tags {
+            Some(tags) if !tags.is_empty() => {
+                let tags: Vec<String> = tags
+                    .iter()
+                    .map(|Tag(key, value)| match value.as_str() {
+                        "" => key.clone(),
+                        value => format!("{}={}", key, escape_tag_value(value)),
+                    })
+                    .collect();
+                format!("@{} ", tags.join(";"))
+            }
+            _ => String::new(),
+        };
+        let s = if let Some(params) = self.params.as_ref().filter(|p| !p.is_empty()) {
+            format!("{}{} {}\r\n", tags, self.command, params.to_wire())
         } else {
-            format!("{}\r\n", self.command)
+            format!("{}{}\r\n", tags, self.command)
         };
         s.into_bytes()
     }
 
+    pub fn params(&self) -> Vec<String> {
+        self.params.as_ref().map(|p| p.to_vec()).unwrap_or_default()
+    }
+
     pub fn get_param(&self, index: usize) -> Option<&Param> {
         match &self.params {
-            Some(p) => {
-                p.get(index)
-            }
-            _ => None
+            Some(p) => p.get(index),
+            _ => None,
         }
     }
 }
 
+fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        // A lone backslash at the end is dropped
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
 impl Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut s = String::new();
@@ -137,6 +243,12 @@ impl Display for Message {
     }
 }
 
+/// Parses a line as the server sends it, without the `\r\n`, for tests.
+#[cfg(test)]
+pub fn parse_line(raw: &str) -> Message {
+    Message::parse(&format!("{}\r\n", raw)).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +256,36 @@ mod tests {
     #[test]
     fn test_as_bytes() {
         let msg = Message::new(Command::Cap, vec!["LS", "302"]);
+        assert_eq!(msg.as_bytes(), b"CAP LS 302\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_as_bytes_with_tags() {
+        let msg = Message::priv_msg("#rust".to_string(), "hi".to_string())
+            .with_tag("batch", Some("a b;c"))
+            .with_tag("draft/multiline-concat", None);
+        assert_eq!(
+            msg.as_bytes(),
+            b"@batch=a\\sb\\:c;draft/multiline-concat PRIVMSG #rust hi\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_tag_escaping() {
+        let raw = "@+draft/react=a\\sb\\:c\\\\;+typing=active TAGMSG #rust\r\n";
+        let message = Message::parse(raw).unwrap();
+        assert_eq!(message.tag("+draft/react"), Some("a b;c\\"));
+        assert_eq!(message.tag("+typing"), Some("active"));
+        assert_eq!(String::from_utf8(message.as_bytes()).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_as_bytes_trailing_param() {
+        let msg = Message::priv_msg("#rust".to_string(), "hello there".to_string());
+        assert_eq!(msg.as_bytes(), b"PRIVMSG #rust :hello there\r\n".to_vec());
+
+        let msg = Message::priv_msg("#rust".to_string(), ":)".to_string());
+        assert_eq!(msg.as_bytes(), b"PRIVMSG #rust ::)\r\n".to_vec());
     }
 
     #[test]
@@ -155,9 +297,14 @@ mod tests {
                 Tag("id".to_string(), "123".to_string()),
                 Tag("type".to_string(), "something".to_string()),
             ]),
-            source: Some(Source::new_with_user_and_host("Guest1".to_string(), "textual".to_string(), "254D99FE.73C022D0.AC18634F.IP".to_string())), // source
+            source: Some(Source::new_with_user_and_host(
+                "Guest1".to_string(),
+                "textual".to_string(),
+                "254D99FE.73C022D0.AC18634F.IP".to_string(),
+            )), // source
             command: Command::PrivMsg,
             params: Some(Params::from(vec!["#test_123", "Hello"])), // paramerters
+            time: None,
         };
         assert_eq!(actual, expected);
     }
@@ -171,13 +318,28 @@ mod tests {
             source: Some(Source::new("irc.jonkgrimes.com".to_string())), // source
             command: Command::Notice,
             params: Some(Params::from(vec!["*", "*** Looking up your hostname..."])), // paramerters
+            time: None,
         };
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_time() {
+        let raw = "@time=2026-01-31T12:00:00.250Z :a!a@h PRIVMSG #rust :hi\r\n";
+        let message = Message::parse(raw).unwrap();
+        let time = message.time().unwrap();
+        assert_eq!(time.to_rfc3339(), "2026-01-31T12:00:00.250+00:00");
+        // The server's time wins over when we got it
+        assert_eq!(message.received_at(Utc::now()).time(), Some(time));
+
+        let message = Message::parse(":a!a@h PRIVMSG #rust :hi\r\n").unwrap();
+        assert_eq!(message.time(), None);
+        assert_eq!(message.received_at(time).time(), Some(time));
+    }
+
     use serde::{Deserialize, Serialize};
-    use std::{fs::File, io::Read};
     use std::collections::HashMap;
+    use std::{fs::File, io::Read};
 
     #[derive(Debug, Serialize, Deserialize)]
     struct SplitTests {
@@ -199,8 +361,8 @@ mod tests {
 
     #[test]
     fn parser_integration_tests() {
-        let mut yaml =
-            File::open("src/message/test_data/msg-split.yaml").expect("Unable to open msg-split.yaml");
+        let mut yaml = File::open("src/message/test_data/msg-split.yaml")
+            .expect("Unable to open msg-split.yaml");
         let mut buffer = Vec::new();
         yaml.read_to_end(&mut buffer)
             .expect("Unable to read from file");
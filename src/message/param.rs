@@ -28,7 +28,7 @@ impl From<String> for Param {
     }
 }
 
-impl PartialEq<String>  for Param {
+impl PartialEq<String> for Param {
     fn eq(&self, other: &String) -> bool {
         &self.0 == other
     }
@@ -39,7 +39,6 @@ pub struct Params {
     params: Vec<Param>,
 }
 
-
 impl Params {
     pub fn new() -> Self {
         Self { params: Vec::new() }
@@ -53,9 +52,25 @@ impl Params {
         self.params.get(index)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
     pub fn to_vec(&self) -> Vec<String> {
         self.params.iter().map(|p| p.0.clone()).collect()
     }
+
+    /// Formats the params the way they are sent to the server, marking the
+    /// last one as trailing when it is empty, has spaces or starts with `:`.
+    pub fn to_wire(&self) -> String {
+        let mut params: Vec<String> = self.params.iter().map(|p| p.0.clone()).collect();
+        if let Some(last) = params.last_mut() {
+            if last.is_empty() || last.contains(' ') || last.starts_with(':') {
+                last.insert(0, ':');
+            }
+        }
+        params.join(" ")
+    }
 }
 
 impl Display for Params {
@@ -63,7 +78,7 @@ impl Display for Params {
         let s: String = self
             .params
             .iter()
-            .map(|p| format!("{}", p.0))
+            .map(|p| p.0.clone())
             .collect::<Vec<String>>()
             .join(" ");
         write!(f, "{}", s)
@@ -88,9 +103,7 @@ impl From<Vec<String>> for Params {
 
 impl From<[Param; 1]> for Params {
     fn from(a: [Param; 1]) -> Self {
-        Self {
-            params: a.iter().map(|p| p.clone()).collect(),
-        }
+        Self { params: a.to_vec() }
     }
 }
 
@@ -104,4 +117,4 @@ impl FromIterator<Param> for Params {
 
         c
     }
-}
\ No newline at end of file
+}
@@ -2,20 +2,29 @@
 
 use std::str;
 
-use nom::bytes::complete::{escaped, tag, take, take_while, take_while_m_n};
-use nom::sequence::{preceded, delimited};
-use nom::character::complete::{alphanumeric0, crlf, multispace0, space0, char};
-use nom::combinator::{recognize, value};
-use nom::multi::{separated_list0};
 use nom::branch::alt;
-use nom::sequence::{separated_pair};
+use nom::bytes::complete::{tag, take_till, take_till1, take_while, take_while_m_n};
+use nom::character::complete::{char, crlf, multispace0, one_of, space0};
+use nom::combinator::recognize;
+use nom::multi::separated_list0;
+use nom::sequence::preceded;
+use nom::sequence::separated_pair;
 use nom::{
-    bytes::complete::take_until, character::is_alphanumeric, character::is_space, combinator::opt, multi::many_till,
-    sequence::terminated, IResult,
+    bytes::complete::take_until, character::is_alphanumeric, character::is_space, combinator::opt,
+    multi::many_till, sequence::terminated, IResult,
 };
 
 const LINE_ENDING: &str = "\r\n";
 
+type RawTags<'a> = Vec<(&'a str, &'a str)>;
+type RawSource<'a> = (&'a str, Option<&'a str>, Option<&'a str>);
+type RawMessage<'a> = (
+    Option<RawTags<'a>>,
+    Option<RawSource<'a>>,
+    &'a str,
+    Option<Vec<&'a str>>,
+);
+
 // Basic message structure
 // [@tags] [:source] <command> <parameters>
 
@@ -28,8 +37,11 @@ fn tag_separator(i: &str) -> IResult<&str, &str> {
     tag(";")(i)
 }
 
+// Client-only tags start with a +, and vendors are hostnames
 fn tag_key(i: &str) -> IResult<&str, &str> {
-    let f = |c: char| is_alphanumeric(c as u8) || c == '-' || c == '/' || c == ' ';
+    let f = |c: char| {
+        is_alphanumeric(c as u8) || c == '-' || c == '/' || c == ' ' || c == '+' || c == '.'
+    };
     take_while(f)(i)
 }
 
@@ -49,14 +61,14 @@ fn tag_pair(i: &str) -> IResult<&str, (&str, &str)> {
     }
 }
 
-fn tags(i: &str) -> IResult<&str, Option<Vec<(&str, &str)>>> {
+fn tags(i: &str) -> IResult<&str, Option<RawTags<'_>>> {
     let (i, o) = tag_start(i)?;
-    if o == None {
+    if o.is_none() {
         return Ok((i, None));
     }
 
     let (rest, tags) = terminated(separated_list0(tag_separator, tag_pair), tag(" "))(i)?;
-    if tags.len() == 0 {
+    if tags.is_empty() {
         Ok((rest, None))
     } else {
         Ok((rest, Some(tags)))
@@ -68,16 +80,16 @@ fn source_start(i: &str) -> IResult<&str, Option<&str>> {
     opt(tag(":"))(i)
 }
 
+fn nick(i: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| c == '!' || c == '@' || c == ' ')(i)
+}
+
 fn user(i: &str) -> IResult<&str, &str> {
-    let f = |c: char| is_alphanumeric(c as u8) || c == '-' || c == '.' || c == '_';
-    take_while(f)(i)
+    take_till1(|c: char| c == '@' || c == ' ')(i)
 }
 
 fn host(i: &str) -> IResult<&str, &str> {
-    let f = |c: char| is_alphanumeric(c as u8) || c == '-' || c == '.' || c == '_';
-
-    // let (i, host) = escaped(alphanumeric0, '\\', one_of(r#"u\{\}"#))(i)?;
-    take_while(f)(i)
+    take_till1(|c: char| c == ' ')(i)
 }
 
 fn user_and_host(i: &str) -> IResult<&str, (&str, &str)> {
@@ -85,13 +97,13 @@ fn user_and_host(i: &str) -> IResult<&str, (&str, &str)> {
 }
 
 fn nick_user_and_host(i: &str) -> IResult<&str, (&str, (&str, &str))> {
-    separated_pair(alphanumeric0, tag("!"), user_and_host)(i)
+    separated_pair(nick, tag("!"), user_and_host)(i)
 }
 
-fn source(i: &str) -> IResult<&str, Option<(&str, Option<&str>, Option<&str>)>> {
+fn source(i: &str) -> IResult<&str, Option<RawSource<'_>>> {
     // No source
     let (i, o) = source_start(i)?;
-    if o == None {
+    if o.is_none() {
         return Ok((i, None));
     }
 
@@ -102,15 +114,13 @@ fn source(i: &str) -> IResult<&str, Option<(&str, Option<&str>, Option<&str>)>>
             let (_, (nick, (user, host))) = nick_user_and_host(source)?;
             Ok((i, Some((nick, Some(user), Some(host)))))
         }
-        Err(_) => Ok((i, Some((source, None, None))))
+        Err(_) => Ok((i, Some((source, None, None)))),
     }
 }
 
 // Command parsers
 fn command(i: &str) -> IResult<&str, &str> {
-    let (i, command) = alt(
-        (take_until(" "), take_until("\r\n"))
-    )(i)?;
+    let (i, command) = alt((take_until(" "), take_until("\r\n")))(i)?;
     let (i, _) = alt((tag(" "), tag("\r\n")))(i)?;
     Ok((i, command))
 }
@@ -124,7 +134,7 @@ fn params(i: &str) -> IResult<&str, Vec<&str>> {
 
 fn param(i: &str) -> IResult<&str, &str> {
     let (i, tag) = opt(tag(":"))(i)?;
-    if let Some(_) = tag {
+    if tag.is_some() {
         trailing_param(i)
     } else {
         normal_param(i)
@@ -132,10 +142,7 @@ fn param(i: &str) -> IResult<&str, &str> {
 }
 
 fn normal_param(i: &str) -> IResult<&str, &str> {
-    let (i, param) = alt((
-      terminated(take_until(" "), space0),
-      trailing_param
-    ))(i)?;
+    let (i, param) = alt((terminated(take_until(" "), space0), trailing_param))(i)?;
     Ok((i, param))
 }
 
@@ -143,9 +150,7 @@ fn trailing_param(i: &str) -> IResult<&str, &str> {
     take_until(LINE_ENDING)(i)
 }
 
-pub fn message(
-    i: &str,
-) -> IResult<&str, (Option<Vec<(&str, &str)>>, Option<(&str, Option<&str>, Option<&str>)>, &str, Option<Vec<&str>>)> {
+pub fn message(i: &str) -> IResult<&str, RawMessage<'_>> {
     let (i, tags) = tags(i)?;
     let (i, source) = source(i)?;
     let (i, command) = command(i)?;
@@ -154,24 +159,63 @@ pub fn message(
     Ok((i, (tags, source, command, params)))
 }
 
-fn unicode_control_character(i: &str) -> IResult<&str, &str> {
-  // let parse_hex = take_while_m_n(0, 6, |c: char| c.is_ascii_hexdigit());
-  dbg!(i);
-  Ok(("", ""))
+// Formatting parsers
+// mIRC formatting codes can show up anywhere in the text of a message
+
+const CONTROL_CHARACTERS: &str = "\u{2}\u{3}\u{4}\u{f}\u{11}\u{16}\u{1d}\u{1e}\u{1f}";
+
+pub fn control_character(i: &str) -> IResult<&str, &str> {
+    recognize(one_of(CONTROL_CHARACTERS))(i)
+}
+
+fn color_number(i: &str) -> IResult<&str, &str> {
+    take_while_m_n(1, 2, |c: char| c.is_ascii_digit())(i)
+}
+
+fn hex_color(i: &str) -> IResult<&str, &str> {
+    take_while_m_n(6, 6, |c: char| c.is_ascii_hexdigit())(i)
 }
 
-fn control_charater(i: &str) -> IResult<&str, &str> {
-    dbg!(i);
-    preceded(
-        char('\\'),
-        // `delimited` is like `preceded`, but it parses both a prefix and a suffix.
-        // It returns the result of the middle parser. In this case, it parses
-        // {XXXX}, where XXXX is 1 to 6 hex numerals, and returns XXXX
-        alt((
-          unicode_control_character,
-          value("\u{3}", tag("u{3}"))
-        ))
-    )(i)
+/// The `<fg>[,<bg>]` that follows a `\u{3}`. A background is only read when
+/// there is a foreground, otherwise the comma is just text.
+pub fn color_code(i: &str) -> IResult<&str, (Option<&str>, Option<&str>)> {
+    let (i, fg) = opt(color_number)(i)?;
+    if fg.is_none() {
+        return Ok((i, (None, None)));
+    }
+    let (i, bg) = opt(preceded(char(','), color_number))(i)?;
+    Ok((i, (fg, bg)))
+}
+
+/// The `RRGGBB[,RRGGBB]` that follows a `\u{4}`.
+pub fn hex_color_code(i: &str) -> IResult<&str, (Option<&str>, Option<&str>)> {
+    let (i, fg) = opt(hex_color)(i)?;
+    if fg.is_none() {
+        return Ok((i, (None, None)));
+    }
+    let (i, bg) = opt(preceded(char(','), hex_color))(i)?;
+    Ok((i, (fg, bg)))
+}
+
+/// Text up to the next control character.
+pub fn formatted_text(i: &str) -> IResult<&str, &str> {
+    take_till1(|c: char| CONTROL_CHARACTERS.contains(c))(i)
+}
+
+// CTCP parsers
+// \u{1}COMMAND [params]\u{1}, the closing delimiter is optional
+
+const CTCP_DELIMITER: char = '\u{1}';
+
+pub fn ctcp(i: &str) -> IResult<&str, (&str, Option<&str>)> {
+    let (i, _) = char(CTCP_DELIMITER)(i)?;
+    let (i, command) = take_till1(|c: char| c == ' ' || c == CTCP_DELIMITER)(i)?;
+    let (i, params) = opt(preceded(
+        char(' '),
+        take_till(|c: char| c == CTCP_DELIMITER),
+    ))(i)?;
+    let (i, _) = opt(char(CTCP_DELIMITER))(i)?;
+    Ok((i, (command, params.filter(|p| !p.is_empty()))))
 }
 
 #[cfg(test)]
@@ -180,7 +224,7 @@ mod tests {
     #[test]
     fn test_escaped_control_charater() {
         let raw = "\u{3}";
-        let actual = control_charater(raw);
+        let actual = control_character(raw);
         let expected = ("", "\u{3}");
         assert_eq!(actual, Ok(expected))
     }
@@ -193,6 +237,11 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_client_tag_key() {
+        let (_, actual) = tag_key("+example.com/react=x").unwrap();
+        assert_eq!(actual, "+example.com/react");
+    }
 
     #[test]
     fn test_tag_key_error() {
@@ -223,7 +272,11 @@ mod tests {
     fn test_source_with_client() {
         let raw = ":Guest1!textual@254D99FE.73C022D0.AC18634F.IP ";
         let (_i, source) = source(raw).unwrap();
-        let expected = Some(("Guest1", Some("textual"), Some("254D99FE.73C022D0.AC18634F.IP")));
+        let expected = Some((
+            "Guest1",
+            Some("textual"),
+            Some("254D99FE.73C022D0.AC18634F.IP"),
+        ));
         let actual = source;
         assert_eq!(actual, expected);
     }
@@ -232,7 +285,7 @@ mod tests {
     fn test_host_with_control_characters() {
         let raw = ":Guest1!tex\x30tual@localhost ";
         let (_i, source) = source(raw).unwrap();
-        let expected = Some(("Guest1", Some("tex\x30tual"), Some("localhost ")));
+        let expected = Some(("Guest1", Some("tex\x30tual"), Some("localhost")));
         let actual = source;
         assert_eq!(actual, expected);
     }
@@ -281,7 +334,7 @@ mod tests {
     #[test]
     fn test_tags_with_true_terminator() {
         let raw = "@id=123;type= ";
-        let (_,  actual) = tags(raw).unwrap();
+        let (_, actual) = tags(raw).unwrap();
         let expected = vec![("id", "123"), ("type", "")];
         assert_eq!(actual.unwrap(), expected);
     }
@@ -365,7 +418,7 @@ mod tests {
             None,
             None,
             "CAP",
-            Some(vec!["REQ", "sasl message-tags foo"])
+            Some(vec!["REQ", "sasl message-tags foo"]),
         );
         assert_eq!(actual, expected);
     }
@@ -378,7 +431,7 @@ mod tests {
             None,
             Some(("dan", Some("d"), Some("localhost"))),
             "PRIVMSG",
-            Some(vec!["#chan", "Hey!"])
+            Some(vec!["#chan", "Hey!"]),
         );
         assert_eq!(actual, expected);
     }
@@ -391,8 +444,19 @@ mod tests {
             None,
             Some(("dan", Some("d"), Some("localhost"))),
             "PRIVMSG",
-            Some(vec!["#chan", "Hey!"])
+            Some(vec!["#chan", "Hey!"]),
         );
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_ctcp() {
+        assert_eq!(ctcp("\u{1}VERSION\u{1}"), Ok(("", ("VERSION", None))));
+        assert_eq!(
+            ctcp("\u{1}ACTION waves hello\u{1}"),
+            Ok(("", ("ACTION", Some("waves hello"))))
+        );
+        assert_eq!(ctcp("\u{1}PING 123"), Ok(("", ("PING", Some("123")))));
+        assert!(ctcp("VERSION").is_err());
+    }
 }
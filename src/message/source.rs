@@ -10,7 +10,7 @@ pub struct Source {
 impl Source {
     pub fn new(nick: String) -> Source {
         Source {
-            nick: nick,
+            nick,
             user: None,
             host: None,
         }
@@ -18,16 +18,20 @@ impl Source {
 
     pub fn new_with_user_and_host(nick: String, user: String, host: String) -> Source {
         Source {
-            nick: nick,
+            nick,
             user: Some(user),
-            host: Some(host)
+            host: Some(host),
         }
     }
 }
 
 impl From<String> for Source {
     fn from(s: String) -> Self {
-        Self { nick: s, user: None, host: None }
+        Self {
+            nick: s,
+            user: None,
+            host: None,
+        }
     }
 }
 
@@ -43,16 +47,15 @@ impl From<(&str, Option<&str>, Option<&str>)> for Source {
 
 impl Display for Source {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.user.is_some() && self.host.is_some() {
-            write!(f, "{}!{}@{}", self.nick, self.user.as_ref().unwrap(), self.host.as_ref().unwrap())
-        } else {
-            write!(f, "{}", self.nick)
+        match (&self.user, &self.host) {
+            (Some(user), Some(host)) => write!(f, "{}!{}@{}", self.nick, user, host),
+            _ => write!(f, "{}", self.nick),
         }
     }
 }
 
-impl PartialEq<Source> for String { 
+impl PartialEq<Source> for String {
     fn eq(&self, rhs: &Source) -> bool {
-        self == &rhs.nick
+        self == &rhs.to_string()
     }
 }
@@ -0,0 +1,145 @@
+//! Splitting text too long to fit in one message.
+
+use crate::message::format;
+use crate::message::parser::{color_code, control_character, hex_color_code};
+
+/// Splits `text` into pieces of at most `max` bytes to be sent as separate
+/// messages. Pieces end at a space where possible, which is dropped, and
+/// start with whatever formatting was in effect where the previous one
+/// ended.
+pub fn lines(text: &str, max: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut carry = String::new();
+    let mut rest = text;
+
+    while carry.len() + rest.len() > max {
+        // Not worth squeezing the text for
+        if carry.len() * 2 > max {
+            carry.clear();
+        }
+        let at = cut(rest, max - carry.len(), |b| rest[b..].starts_with(' '));
+        let piece = format!("{}{}", carry, &rest[..at]);
+        carry = format::style_after(&piece).codes();
+        pieces.push(piece);
+        rest = rest[at..].trim_start_matches(' ');
+    }
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(format!("{}{}", carry, rest));
+    }
+    pieces
+}
+
+/// Splits `text` into pieces of at most `max` bytes that join back into
+/// `text` exactly, for a `draft/multiline` batch. Pieces end after a space
+/// where possible.
+pub fn chunks(text: &str, max: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+
+    while rest.len() > max {
+        let at = cut(rest, max, |b| rest[..b].ends_with(' '));
+        pieces.push(&rest[..at]);
+        rest = &rest[at..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+/// Where to cut `text` to keep the first part within `budget` bytes: the
+/// last `preferred` boundary, or as much as fits. At least one character
+/// is always taken so splitting makes progress.
+fn cut(text: &str, budget: usize, preferred: impl Fn(usize) -> bool) -> usize {
+    let mut bounds = boundaries(text).peekable();
+    let first = bounds.peek().copied().unwrap_or(text.len());
+    let fitting: Vec<usize> = bounds.take_while(|b| *b <= budget).collect();
+    fitting
+        .iter()
+        .rev()
+        .find(|b| preferred(**b))
+        .or_else(|| fitting.last())
+        .copied()
+        .unwrap_or(first)
+}
+
+/// Byte offsets in `text` that don't fall inside a character or a
+/// formatting code, the end included.
+fn boundaries(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut i = text;
+    std::iter::from_fn(move || {
+        let rest = match control_character(i) {
+            Ok((rest, "\u{3}")) => color_code(rest).map_or(rest, |(r, _)| r),
+            Ok((rest, "\u{4}")) => hex_color_code(rest).map_or(rest, |(r, _)| r),
+            Ok((rest, _)) => rest,
+            Err(_) => &i[i.chars().next()?.len_utf8()..],
+        };
+        i = rest;
+        Some(text.len() - i.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_is_untouched() {
+        assert_eq!(lines("hello", 10), vec!["hello"]);
+        assert_eq!(lines("", 10), vec![""]);
+        assert_eq!(chunks("hello", 10), vec!["hello"]);
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        assert_eq!(
+            lines("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(
+            chunks("the quick brown fox jumps", 10),
+            vec!["the quick ", "brown fox ", "jumps"]
+        );
+    }
+
+    #[test]
+    fn test_long_words_and_utf8() {
+        assert_eq!(lines("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        // é is two bytes and must not be cut in half
+        assert_eq!(lines("ééé", 3), vec!["é", "é", "é"]);
+        assert_eq!(chunks("aéé", 4), vec!["aé", "é"]);
+    }
+
+    #[test]
+    fn test_carries_formatting() {
+        assert_eq!(
+            lines("\u{2}bold words \u{3}4red here", 12),
+            vec!["\u{2}bold words", "\u{2}\u{3}4red here"]
+        );
+        assert_eq!(
+            lines("\u{3}4red text here", 10),
+            vec!["\u{3}4red text", "\u{3}04here"]
+        );
+        assert_eq!(
+            lines("\u{3}4,2\u{4}FF8800orange text on a blue background", 30),
+            vec![
+                "\u{3}4,2\u{4}FF8800orange text on a",
+                "\u{3}99,02\u{4}FF8800blue background"
+            ]
+        );
+        assert_eq!(
+            lines(
+                "\u{4}000000,FF8800\u{3}04red text on an orange background",
+                40
+            ),
+            vec![
+                "\u{4}000000,FF8800\u{3}04red text on an orange",
+                "\u{4}000000,FF8800\u{3}04background"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_does_not_cut_color_codes() {
+        // Cutting after 6 bytes would split \u{3}12 from its digits
+        assert_eq!(lines("abcde\u{3}12x", 6), vec!["abcde", "\u{3}12x"]);
+    }
+}
@@ -0,0 +1,521 @@
+//! Everything we've seen, kept in SQLite with a full text index so it can
+//! be searched with `/search nick:alice "deploy" since:2026-01-01 in:#ops`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+
+use crate::client::state::State;
+use crate::logger;
+use crate::message::{format, Command, Message};
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    time INTEGER NOT NULL,
+    network TEXT NOT NULL,
+    buffer TEXT NOT NULL COLLATE NOCASE,
+    source TEXT,
+    nick TEXT COLLATE NOCASE,
+    command TEXT NOT NULL,
+    text TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    msgid TEXT
+);
+CREATE INDEX IF NOT EXISTS messages_buffer ON messages (network, buffer, id);
+CREATE INDEX IF NOT EXISTS messages_buffer_time ON messages (network, buffer, time, id);
+CREATE UNIQUE INDEX IF NOT EXISTS messages_msgid ON messages (network, buffer, msgid);
+CREATE INDEX IF NOT EXISTS messages_nick ON messages (nick, time);
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_text
+    USING fts5(text, content = 'messages', content_rowid = 'id');
+CREATE TRIGGER IF NOT EXISTS messages_indexed AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_text (rowid, text) VALUES (new.id, new.text);
+END;
+";
+
+/// The columns a `Hit` is read from.
+const HIT_COLUMNS: &str = "id, time, network, buffer, nick, command, text";
+
+pub struct Store {
+    connection: Connection,
+}
+
+/// A message found by a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub id: i64,
+    pub time: DateTime<Local>,
+    pub network: String,
+    pub buffer: String,
+    pub nick: String,
+    pub command: String,
+    pub text: String,
+}
+
+impl Hit {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let millis: i64 = row.get(1)?;
+        Ok(Self {
+            id: row.get(0)?,
+            time: Local
+                .timestamp_millis_opt(millis)
+                .single()
+                .unwrap_or_default(),
+            network: row.get(2)?,
+            buffer: row.get(3)?,
+            nick: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            command: row.get(5)?,
+            text: row.get(6)?,
+        })
+    }
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if needed. Each thread that
+    /// uses it opens its own.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    fn init(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Records a message from the server, before `state` has seen it.
+    pub fn received(
+        &self,
+        network: &str,
+        message: &Message,
+        state: &State,
+        time: DateTime<Local>,
+    ) -> rusqlite::Result<()> {
+        let source = message.source().map(|s| s.to_string());
+        self.record(network, message, source, false, state, time)
+    }
+
+    /// Records a message we've sent. Only chat, the server echoes anything
+    /// else we do, like joining.
+    pub fn sent(
+        &self,
+        network: &str,
+        message: &Message,
+        state: &State,
+        time: DateTime<Local>,
+    ) -> rusqlite::Result<()> {
+        if !matches!(message.command(), Command::PrivMsg | Command::Notice) {
+            return Ok(());
+        }
+        let source = match &state.userhost {
+            Some(userhost) => format!("{}!{}", state.nick, userhost),
+            None => state.nick.clone(),
+        };
+        self.record(network, message, Some(source), true, state, time)
+    }
+
+    fn record(
+        &self,
+        network: &str,
+        message: &Message,
+        source: Option<String>,
+        sent: bool,
+        state: &State,
+        time: DateTime<Local>,
+    ) -> rusqlite::Result<()> {
+        let param = |i| message.get_param(i).map(|p| p.to_string());
+        let text = match message.command() {
+            Command::PrivMsg => match message.ctcp() {
+                Some(ctcp) if ctcp.is_action() => ctcp.params,
+                Some(_) => return Ok(()),
+                None => param(1),
+            },
+            Command::Notice if message.ctcp().is_none() => param(1),
+            Command::Join => Some(String::new()),
+            Command::Part => Some(param(1).unwrap_or_default()),
+            _ => return Ok(()),
+        };
+        let text = format::strip(&text.unwrap_or_default());
+        let nick = source
+            .as_deref()
+            .and_then(|s| s.split('!').next())
+            .map(|n| n.to_string());
+        let tags: BTreeMap<&str, &str> = message
+            .tags()
+            .into_iter()
+            .flatten()
+            .map(|t| (t.key(), t.value()))
+            .collect();
+        let tags = serde_json::to_string(&tags).unwrap_or_default();
+        let command = match message.ctcp() {
+            Some(_) => "ACTION".to_string(),
+            None => message.command().to_string(),
+        };
+
+        let buffers = logger::buffers(message, nick.as_deref().unwrap_or_default(), sent, state);
+        for buffer in buffers {
+            // History from the server can repeat what we already have
+            self.connection.execute(
+                "INSERT OR IGNORE INTO messages
+                 (time, network, buffer, source, nick, command, text, tags, msgid)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    time.timestamp_millis(),
+                    network,
+                    buffer,
+                    source,
+                    nick,
+                    command,
+                    text,
+                    tags,
+                    message.tag("msgid")
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The msgid of the newest message in `buffer` that has one, to fetch
+    /// the server's history from.
+    pub fn newest_msgid(&self, network: &str, buffer: &str) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .query_row(
+                "SELECT msgid FROM messages
+                 WHERE network = ?1 AND buffer = ?2 AND msgid IS NOT NULL
+                 ORDER BY time DESC, id DESC LIMIT 1",
+                params![network, buffer],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// The message with `msgid` on `network`, like one that's replied to.
+    pub fn by_msgid(&self, network: &str, msgid: &str) -> rusqlite::Result<Option<Hit>> {
+        let sql = format!(
+            "SELECT {} FROM messages WHERE network = ?1 AND msgid = ?2 LIMIT 1",
+            HIT_COLUMNS
+        );
+        self.connection
+            .query_row(&sql, params![network, msgid], Hit::from_row)
+            .optional()
+    }
+
+    /// The newest messages matching `query`, newest first.
+    pub fn search(&self, query: &Query, limit: usize) -> rusqlite::Result<Vec<Hit>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if !query.terms.is_empty() {
+            conditions.push("id IN (SELECT rowid FROM messages_text WHERE messages_text MATCH ?)");
+            values.push(Value::Text(query.fts()));
+        }
+        if let Some(nick) = &query.nick {
+            conditions.push("nick = ?");
+            values.push(Value::Text(nick.clone()));
+        }
+        if let Some(buffer) = &query.buffer {
+            conditions.push("buffer = ?");
+            values.push(Value::Text(buffer.clone()));
+        }
+        if let Some(since) = query.since {
+            conditions.push("time >= ?");
+            values.push(Value::Integer(since.timestamp_millis()));
+        }
+        if let Some(until) = query.until {
+            conditions.push("time < ?");
+            values.push(Value::Integer(until.timestamp_millis()));
+        }
+        values.push(Value::Integer(limit as i64));
+
+        let sql = format!(
+            "SELECT {} FROM messages WHERE {} ORDER BY time DESC, id DESC LIMIT ?",
+            HIT_COLUMNS,
+            conditions.join(" AND ")
+        );
+        let mut statement = self.connection.prepare(&sql)?;
+        let hits = statement.query_map(params_from_iter(values), Hit::from_row)?;
+        hits.collect()
+    }
+
+    /// The messages around `hit` in its buffer, oldest first. History
+    /// played back later is stored after newer messages, so it goes by time.
+    pub fn context(&self, hit: &Hit, around: usize) -> rusqlite::Result<Vec<Hit>> {
+        let query = |comparison: &str, order: &str| {
+            format!(
+                "SELECT {} FROM messages WHERE network = ?1 AND buffer = ?2
+                 AND (time, id) {} (?3, ?4)
+                 ORDER BY time {}, id {} LIMIT ?5",
+                HIT_COLUMNS, comparison, order, order
+            )
+        };
+        let time = hit.time.timestamp_millis();
+        let args = params![hit.network, hit.buffer, time, hit.id, around as i64];
+
+        let mut before = self.connection.prepare(&query("<", "DESC"))?;
+        let mut hits = before
+            .query_map(args, Hit::from_row)?
+            .collect::<rusqlite::Result<Vec<Hit>>>()?;
+        hits.reverse();
+        hits.push(hit.clone());
+        let mut after = self.connection.prepare(&query(">", "ASC"))?;
+        for later in after.query_map(args, Hit::from_row)? {
+            hits.push(later?);
+        }
+        Ok(hits)
+    }
+}
+
+/// A parsed `/search`: words and "quoted phrases" to look for, and
+/// `nick:`, `in:`, `since:` and `until:` filters.
+#[derive(Debug, Default, PartialEq)]
+pub struct Query {
+    pub terms: Vec<String>,
+    pub nick: Option<String>,
+    pub buffer: Option<String>,
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut query = Query::default();
+        for word in words(text) {
+            let filter = word
+                .split_once(':')
+                .filter(|(name, value)| !name.contains('"') && !value.is_empty());
+            match filter {
+                Some(("nick", nick)) => query.nick = Some(nick.to_string()),
+                Some(("in", buffer)) => query.buffer = Some(buffer.to_string()),
+                Some(("since", date)) => query.since = Some(day(date)?),
+                Some(("until", date)) => query.until = Some(day(date)?),
+                _ => query.terms.push(word.trim_matches('"').to_string()),
+            }
+        }
+        query.terms.retain(|t| !t.is_empty());
+        if query == Query::default() {
+            return Err("Nothing to search for".to_string());
+        }
+        Ok(query)
+    }
+}
+
+impl Query {
+    /// The terms as an FTS5 query, each one a phrase that has to appear.
+    fn fts(&self) -> String {
+        self.terms
+            .iter()
+            .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Splits on spaces, keeping "quoted phrases" together.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            ' ' if !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// The start of `date`, a `YYYY-MM-DD` day in local time.
+fn day(date: &str) -> Result<DateTime<Local>, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|d| Local.from_local_datetime(&d).earliest())
+        .ok_or_else(|| format!("{} isn't a date like 2026-01-31", date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::parse_line;
+
+    fn store() -> Store {
+        Store::init(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn time(day: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, day, 12, 0, 0).unwrap()
+    }
+
+    fn fill(store: &Store) {
+        let mut state = State::new("me");
+        state.update(&parse_line(":me!me@example.com JOIN #ops"));
+        for (day, raw) in [
+            (
+                1,
+                "@msgid=m1 :alice!a@example.com PRIVMSG #ops :deploying the new build",
+            ),
+            (
+                2,
+                "@msgid=m2 :bob!b@example.com PRIVMSG #ops :did the deploy work?",
+            ),
+            (
+                3,
+                ":alice!a@example.com PRIVMSG #rust :deploy \u{2}all\u{2} the things",
+            ),
+            (
+                4,
+                ":alice!a@example.com PRIVMSG #ops :\u{1}ACTION rolls back the deploy\u{1}",
+            ),
+            (5, ":alice!a@example.com PRIVMSG #ops :\u{1}VERSION\u{1}"),
+        ] {
+            store
+                .received("libera", &parse_line(raw), &state, time(day))
+                .unwrap();
+        }
+        let reply = Message::priv_msg("#ops".into(), "deploy went fine".into());
+        store.sent("libera", &reply, &state, time(6)).unwrap();
+        let join = Message::new(Command::Join, vec!["#ops"]);
+        store.sent("libera", &join, &state, time(6)).unwrap();
+    }
+
+    #[test]
+    fn test_query() {
+        let query: Query = r#"nick:alice "deploy now" since:2026-01-01 in:#ops"#
+            .parse()
+            .unwrap();
+        assert_eq!(query.terms, vec!["deploy now"]);
+        assert_eq!(query.nick.as_deref(), Some("alice"));
+        assert_eq!(query.buffer.as_deref(), Some("#ops"));
+        assert_eq!(
+            query.since,
+            Some(Local.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(query.fts(), "\"deploy now\"");
+
+        assert!("since:yesterday".parse::<Query>().is_err());
+        assert!("  ".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let store = store();
+        fill(&store);
+        let search = |q: &str| -> Vec<String> {
+            store
+                .search(&q.parse().unwrap(), 10)
+                .unwrap()
+                .into_iter()
+                .map(|h| format!("{} {} {}", h.buffer, h.nick, h.text))
+                .collect()
+        };
+
+        assert_eq!(
+            search("nick:alice deploy in:#ops"),
+            vec!["#ops alice rolls back the deploy"]
+        );
+        assert_eq!(
+            search("deploy since:2026-01-03 until:2026-01-06"),
+            vec![
+                "#ops alice rolls back the deploy",
+                "#rust alice deploy all the things"
+            ]
+        );
+        assert_eq!(search("nick:me"), vec!["#ops me deploy went fine"]);
+        assert_eq!(search("VERSION").len(), 0);
+    }
+
+    #[test]
+    fn test_msgids() {
+        let store = store();
+        fill(&store);
+        let replayed =
+            parse_line("@msgid=m1 :alice!a@example.com PRIVMSG #ops :deploying the new build");
+        store
+            .received("libera", &replayed, &State::new("me"), time(7))
+            .unwrap();
+        assert_eq!(
+            store
+                .search(&"deploying".parse().unwrap(), 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            store.newest_msgid("libera", "#OPS").unwrap().as_deref(),
+            Some("m2")
+        );
+        assert_eq!(store.newest_msgid("oftc", "#ops").unwrap(), None);
+        let hit = store.by_msgid("libera", "m2").unwrap().unwrap();
+        assert_eq!(
+            (hit.nick.as_str(), hit.text.as_str()),
+            ("bob", "did the deploy work?")
+        );
+        assert_eq!(store.by_msgid("libera", "m9").unwrap(), None);
+    }
+
+    #[test]
+    fn test_context() {
+        let store = store();
+        fill(&store);
+        let hit = store.search(&"did".parse().unwrap(), 1).unwrap().remove(0);
+        let context: Vec<String> = store
+            .context(&hit, 1)
+            .unwrap()
+            .into_iter()
+            .map(|h| h.text)
+            .collect();
+        assert_eq!(
+            context,
+            vec![
+                "deploying the new build",
+                "did the deploy work?",
+                "rolls back the deploy"
+            ]
+        );
+
+        // Played back after newer messages came in
+        let state = State::new("me");
+        let earlier = parse_line(":carol!c@example.com PRIVMSG #ops :starting the build");
+        let earlier_time = time(1) + chrono::Duration::hours(1);
+        store
+            .received("libera", &earlier, &state, earlier_time)
+            .unwrap();
+        let hit = store
+            .search(&"starting".parse().unwrap(), 1)
+            .unwrap()
+            .remove(0);
+        let context: Vec<String> = store
+            .context(&hit, 1)
+            .unwrap()
+            .into_iter()
+            .map(|h| h.text)
+            .collect();
+        assert_eq!(
+            context,
+            vec![
+                "deploying the new build",
+                "starting the build",
+                "did the deploy work?"
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+/// Everything the editor can complete against for the active buffer.
+#[derive(Debug, Default)]
+pub struct Candidates {
+    pub nicks: Vec<String>,
+    pub channels: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+/// An in-progress tab completion. Pressing tab again cycles through
+/// `matches`, replacing whatever the previous press inserted.
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    pub start: usize,
+    pub inserted: usize,
+    matches: Vec<String>,
+    index: usize,
+}
+
+impl Completion {
+    pub fn current(&self) -> &str {
+        &self.matches[self.index]
+    }
+
+    pub fn advance(&mut self) {
+        self.index = (self.index + 1) % self.matches.len();
+    }
+}
+
+/// Starts a completion for the word that ends at `cursor`.
+///
+/// A `/word` at the start of the line completes commands, `#word` or `&word`
+/// completes channels and anything else completes nicks. A nick completed at
+/// the start of the line gets a `: ` suffix so it reads as an address.
+pub fn complete(line: &[char], cursor: usize, candidates: &Candidates) -> Option<Completion> {
+    let start = line[..cursor]
+        .iter()
+        .rposition(|c| c.is_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word: String = line[start..cursor].iter().collect();
+
+    let (pool, suffix) = if start == 0 && word.starts_with('/') {
+        (&candidates.commands, " ")
+    } else if word.starts_with('#') || word.starts_with('&') {
+        (&candidates.channels, " ")
+    } else if start == 0 {
+        (&candidates.nicks, ": ")
+    } else {
+        (&candidates.nicks, " ")
+    };
+
+    let word = word.to_lowercase();
+    let mut matches: Vec<String> = pool
+        .iter()
+        .filter(|candidate| candidate.to_lowercase().starts_with(&word))
+        .map(|candidate| format!("{}{}", candidate, suffix))
+        .collect();
+    if matches.is_empty() {
+        return None;
+    }
+    matches.sort_by_key(|m| m.to_lowercase());
+    matches.dedup();
+
+    Some(Completion {
+        start,
+        inserted: cursor - start,
+        matches,
+        index: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> Candidates {
+        Candidates {
+            nicks: vec!["alice".to_string(), "Albert".to_string(), "bob".to_string()],
+            channels: vec!["#ops".to_string(), "#rust".to_string()],
+            commands: vec!["/join".to_string(), "/part".to_string()],
+        }
+    }
+
+    fn run(line: &str) -> Option<Completion> {
+        let chars: Vec<char> = line.chars().collect();
+        complete(&chars, chars.len(), &candidates())
+    }
+
+    #[test]
+    fn test_nick_at_start_of_line() {
+        let mut completion = run("al").unwrap();
+        assert_eq!(completion.current(), "Albert: ");
+        completion.advance();
+        assert_eq!(completion.current(), "alice: ");
+        completion.advance();
+        assert_eq!(completion.current(), "Albert: ");
+    }
+
+    #[test]
+    fn test_nick_mid_line() {
+        let completion = run("thanks bo").unwrap();
+        assert_eq!(completion.start, 7);
+        assert_eq!(completion.current(), "bob ");
+    }
+
+    #[test]
+    fn test_channel() {
+        let completion = run("/join #ru").unwrap();
+        assert_eq!(completion.current(), "#rust ");
+    }
+
+    #[test]
+    fn test_command() {
+        let completion = run("/jo").unwrap();
+        assert_eq!(completion.current(), "/join ");
+        assert_eq!(run("hello /jo"), None);
+    }
+
+    #[test]
+    fn test_no_match() {
+        assert_eq!(run("zed"), None);
+    }
+}
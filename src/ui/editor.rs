@@ -0,0 +1,600 @@
+//! Line editor for the input line with emacs style keybindings.
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+
+use termion::event::{Event, Key};
+
+use crate::message::format;
+use crate::ui::completion::{complete, Candidates, Completion};
+use crate::ui::history::History;
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// What the caller should do after the editor handled an event.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// Only the input line changed, redraw it.
+    Redraw,
+    /// Send these lines, in order, to the active buffer.
+    Submit(Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Normal,
+    Search {
+        query: String,
+        found: Option<usize>,
+    },
+    ConfirmPaste(Vec<String>),
+    Ask {
+        question: String,
+        yes: String,
+        no: String,
+    },
+}
+
+pub struct Editor {
+    line: Vec<char>,
+    cursor: usize,
+    kill_ring: String,
+    mode: Mode,
+    /// Questions waiting for the input line to be free, so what's being
+    /// typed doesn't answer them.
+    questions: VecDeque<Mode>,
+    completion: Option<Completion>,
+    paste: Option<String>,
+    confirm_paste: bool,
+    format_prefix: bool,
+    buffer: String,
+    histories: HashMap<String, History>,
+}
+
+impl Editor {
+    pub fn new(buffer: &str) -> Self {
+        Self {
+            line: Vec::new(),
+            cursor: 0,
+            kill_ring: String::new(),
+            mode: Mode::Normal,
+            questions: VecDeque::new(),
+            completion: None,
+            paste: None,
+            confirm_paste: true,
+            format_prefix: false,
+            buffer: buffer.to_string(),
+            histories: HashMap::new(),
+        }
+    }
+
+    /// Whether pasting more than one line asks before sending them.
+    pub fn set_confirm_paste(&mut self, confirm: bool) {
+        self.confirm_paste = confirm;
+    }
+
+    /// Name of the buffer input is currently sent to.
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+
+    /// Switches the active buffer. Each buffer keeps its own history.
+    pub fn set_buffer(&mut self, buffer: &str) {
+        self.history().reset();
+        self.buffer = buffer.to_string();
+    }
+
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    /// Asks a yes or no question in place of the input line, once it's
+    /// empty and nothing else is being asked. The answer submits `yes` or
+    /// `no` as if it had been typed.
+    pub fn ask(&mut self, question: &str, yes: &str, no: &str) {
+        self.questions.push_back(Mode::Ask {
+            question: question.to_string(),
+            yes: yes.to_string(),
+            no: no.to_string(),
+        });
+        self.next_question();
+    }
+
+    fn next_question(&mut self) {
+        if self.mode == Mode::Normal && self.line.is_empty() && self.paste.is_none() {
+            if let Some(question) = self.questions.pop_front() {
+                self.mode = question;
+            }
+        }
+    }
+
+    /// Text to draw as the input line and the column the cursor sits at
+    /// within it.
+    pub fn render(&self) -> (String, usize) {
+        match &self.mode {
+            Mode::Normal => {
+                let prompt = format!("[{}] ", self.buffer);
+                let width = prompt.chars().count();
+                (format!("{}{}", prompt, self.line()), width + self.cursor)
+            }
+            Mode::Search { query, found } => {
+                let matched = found
+                    .and_then(|i| self.histories.get(&self.buffer)?.search(query, Some(i + 1)))
+                    .map(|(_, line)| line)
+                    .unwrap_or("");
+                let prompt = format!("(reverse-i-search)`{}': ", query);
+                let width = prompt.chars().count();
+                (format!("{}{}", prompt, matched), width)
+            }
+            Mode::ConfirmPaste(lines) => {
+                let prompt = format!("Paste {} lines to {}? (y/n) ", lines.len(), self.buffer);
+                let width = prompt.chars().count();
+                (prompt, width)
+            }
+            Mode::Ask { question, .. } => {
+                let prompt = format!("{} (y/n) ", question);
+                let width = prompt.chars().count();
+                (prompt, width)
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event, candidates: &Candidates) -> Action {
+        let action = self.handle_input(event, candidates);
+        self.next_question();
+        action
+    }
+
+    fn handle_input(&mut self, event: Event, candidates: &Candidates) -> Action {
+        match event {
+            Event::Unsupported(ref bytes) if bytes.as_slice() == PASTE_START => {
+                self.paste = Some(String::new());
+                Action::Redraw
+            }
+            Event::Unsupported(ref bytes) if bytes.as_slice() == PASTE_END => {
+                let text = self.paste.take().unwrap_or_default();
+                self.finish_paste(&text)
+            }
+            Event::Key(key) => {
+                if let Some(paste) = self.paste.as_mut() {
+                    match key {
+                        Key::Char(c) => paste.push(c),
+                        Key::Ctrl('j') | Key::Ctrl('m') => paste.push('\n'),
+                        _ => {}
+                    }
+                    return Action::Redraw;
+                }
+                self.handle_key(key, candidates)
+            }
+            _ => Action::Redraw,
+        }
+    }
+
+    fn finish_paste(&mut self, text: &str) -> Action {
+        let lines: Vec<String> = text
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect();
+        if lines.len() > 1 && self.confirm_paste {
+            self.mode = Mode::ConfirmPaste(lines);
+        } else if lines.len() > 1 {
+            for line in lines.iter() {
+                self.history().push(line);
+            }
+            return Action::Submit(lines);
+        } else if let Some(line) = lines.first() {
+            self.insert(line);
+        }
+        Action::Redraw
+    }
+
+    fn handle_key(&mut self, key: Key, candidates: &Candidates) -> Action {
+        match mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::ConfirmPaste(lines) => match key {
+                Key::Char('y') | Key::Char('Y') => {
+                    for line in lines.iter() {
+                        self.history().push(line);
+                    }
+                    Action::Submit(lines)
+                }
+                _ => Action::Redraw,
+            },
+            Mode::Ask { question, yes, no } => match key {
+                Key::Char('y') | Key::Char('Y') => Action::Submit(vec![yes]),
+                Key::Char('n') | Key::Char('N') | Key::Esc | Key::Ctrl('g') => {
+                    Action::Submit(vec![no])
+                }
+                _ => {
+                    self.mode = Mode::Ask { question, yes, no };
+                    Action::Redraw
+                }
+            },
+            Mode::Search { query, found } => self.handle_search_key(key, query, found, candidates),
+            Mode::Normal => self.handle_normal_key(key, candidates),
+        }
+    }
+
+    fn handle_search_key(
+        &mut self,
+        key: Key,
+        mut query: String,
+        found: Option<usize>,
+        candidates: &Candidates,
+    ) -> Action {
+        let history = self.histories.entry(self.buffer.clone()).or_default();
+        match key {
+            Key::Ctrl('r') => {
+                let found = history.search(&query, found).map(|(i, _)| i).or(found);
+                self.mode = Mode::Search { query, found };
+            }
+            Key::Char(c) if c != '\n' && c != '\t' => {
+                query.push(c);
+                let found = history.search(&query, None).map(|(i, _)| i);
+                self.mode = Mode::Search { query, found };
+            }
+            Key::Backspace => {
+                query.pop();
+                let found = history.search(&query, None).map(|(i, _)| i);
+                self.mode = Mode::Search { query, found };
+            }
+            Key::Ctrl('g') | Key::Esc => {}
+            key => {
+                if let Some((_, line)) = found.and_then(|i| history.search(&query, Some(i + 1))) {
+                    let line = line.to_string();
+                    self.set_line(&line);
+                }
+                return self.handle_normal_key(key, candidates);
+            }
+        }
+        Action::Redraw
+    }
+
+    fn handle_normal_key(&mut self, key: Key, candidates: &Candidates) -> Action {
+        if key != Key::Char('\t') {
+            self.completion = None;
+        }
+
+        if mem::take(&mut self.format_prefix) {
+            if let Key::Char(c) = key {
+                if let Some(code) = format_shortcut(c) {
+                    self.insert(&code.to_string());
+                }
+            }
+            return Action::Redraw;
+        }
+
+        match key {
+            Key::Char('\n') => return self.submit(),
+            Key::Char('\t') => self.complete(candidates),
+            Key::Char(c) => self.insert(&c.to_string()),
+
+            Key::Ctrl('a') | Key::Home => self.cursor = 0,
+            Key::Ctrl('e') | Key::End => self.cursor = self.line.len(),
+            Key::Ctrl('b') | Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Ctrl('f') | Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Alt('b') => self.cursor = self.word_start(),
+            Key::Alt('f') => self.cursor = self.word_end(),
+
+            Key::Ctrl('h') | Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            Key::Ctrl('d') | Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Ctrl('k') => self.kill(self.cursor, self.line.len()),
+            Key::Ctrl('u') => self.kill(0, self.cursor),
+            Key::Ctrl('w') | Key::Alt('\x7f') => self.kill(self.word_start(), self.cursor),
+            Key::Alt('d') => self.kill(self.cursor, self.word_end()),
+            Key::Ctrl('y') => {
+                let text = self.kill_ring.clone();
+                self.insert(&text);
+            }
+            Key::Ctrl('t') => self.transpose(),
+            Key::Ctrl('x') => self.format_prefix = true,
+
+            Key::Ctrl('p') | Key::Up => {
+                let current = self.line();
+                if let Some(line) = self.history().previous(&current).map(|l| l.to_string()) {
+                    self.set_line(&line);
+                }
+            }
+            Key::Ctrl('n') | Key::Down => {
+                if let Some(line) = self.history().next().map(|l| l.to_string()) {
+                    self.set_line(&line);
+                }
+            }
+            Key::Ctrl('r') => {
+                self.mode = Mode::Search {
+                    query: String::new(),
+                    found: None,
+                }
+            }
+            _ => {}
+        }
+        Action::Redraw
+    }
+
+    fn submit(&mut self) -> Action {
+        let line = self.line();
+        self.set_line("");
+        if line.is_empty() {
+            return Action::Redraw;
+        }
+        self.history().push(&line);
+        Action::Submit(vec![line])
+    }
+
+    fn complete(&mut self, candidates: &Candidates) {
+        if self.completion.is_none() {
+            self.completion = complete(&self.line, self.cursor, candidates);
+        } else if let Some(completion) = self.completion.as_mut() {
+            completion.advance();
+        }
+
+        if let Some(completion) = self.completion.as_mut() {
+            let end = completion.start + completion.inserted;
+            let text: Vec<char> = completion.current().chars().collect();
+            completion.inserted = text.len();
+            self.cursor = completion.start + text.len();
+            self.line.splice(completion.start..end, text);
+        }
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn set_line(&mut self, text: &str) {
+        self.line = text.chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    fn kill(&mut self, from: usize, to: usize) {
+        if from < to {
+            self.kill_ring = self.line.drain(from..to).collect();
+            self.cursor = from;
+        }
+    }
+
+    fn transpose(&mut self) {
+        if self.line.len() < 2 || self.cursor == 0 {
+            return;
+        }
+        let at = self.cursor.min(self.line.len() - 1);
+        self.line.swap(at - 1, at);
+        self.cursor = at + 1;
+    }
+
+    fn word_start(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !self.line[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        while i > 0 && self.line[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_end(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.line.len() && !self.line[i].is_alphanumeric() {
+            i += 1;
+        }
+        while i < self.line.len() && self.line[i].is_alphanumeric() {
+            i += 1;
+        }
+        i
+    }
+
+    fn history(&mut self) -> &mut History {
+        self.histories.entry(self.buffer.clone()).or_default()
+    }
+}
+
+/// The formatting code inserted by `C-x` followed by `c`.
+fn format_shortcut(c: char) -> Option<char> {
+    match c {
+        'b' => Some(format::BOLD),
+        'c' => Some(format::COLOR),
+        'h' => Some(format::HEX_COLOR),
+        'i' => Some(format::ITALIC),
+        'u' => Some(format::UNDERLINE),
+        's' => Some(format::STRIKETHROUGH),
+        'm' => Some(format::MONOSPACE),
+        'r' => Some(format::REVERSE),
+        'o' => Some(format::RESET),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(editor: &mut Editor, keys: Vec<Key>) -> Vec<Action> {
+        let candidates = Candidates {
+            nicks: vec!["alice".to_string(), "bob".to_string()],
+            channels: vec!["#rust".to_string()],
+            commands: vec!["/join".to_string()],
+        };
+        keys.into_iter()
+            .map(|k| editor.handle_event(Event::Key(k), &candidates))
+            .collect()
+    }
+
+    fn typed(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn test_submit() {
+        let mut editor = Editor::new("#rust");
+        let actions = keys(&mut editor, typed("hi\n"));
+        assert_eq!(
+            actions.last(),
+            Some(&Action::Submit(vec!["hi".to_string()]))
+        );
+        assert_eq!(editor.line(), "");
+    }
+
+    #[test]
+    fn test_cursor_movement_and_kill() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("hello world"));
+        keys(
+            &mut editor,
+            vec![Key::Ctrl('a'), Key::Alt('f'), Key::Ctrl('k')],
+        );
+        assert_eq!(editor.line(), "hello");
+        keys(&mut editor, vec![Key::Ctrl('a'), Key::Ctrl('y')]);
+        assert_eq!(editor.line(), " worldhello");
+        keys(&mut editor, vec![Key::Ctrl('w')]);
+        assert_eq!(editor.line(), " hello");
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("ab"));
+        keys(&mut editor, vec![Key::Ctrl('t')]);
+        assert_eq!(editor.line(), "ba");
+    }
+
+    #[test]
+    fn test_format_shortcuts() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, vec![Key::Ctrl('x'), Key::Char('b')]);
+        keys(&mut editor, typed("loud"));
+        keys(&mut editor, vec![Key::Ctrl('x'), Key::Char('c')]);
+        keys(&mut editor, typed("4red"));
+        keys(&mut editor, vec![Key::Ctrl('x'), Key::Char('z')]);
+        assert_eq!(editor.line(), "\u{2}loud\u{3}4red");
+    }
+
+    #[test]
+    fn test_history_is_per_buffer() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("in rust\n"));
+        editor.set_buffer("#ops");
+        keys(&mut editor, typed("in ops\n"));
+
+        keys(&mut editor, vec![Key::Up]);
+        assert_eq!(editor.line(), "in ops");
+        keys(&mut editor, vec![Key::Up]);
+        assert_eq!(editor.line(), "in ops");
+
+        editor.set_buffer("#rust");
+        keys(&mut editor, vec![Key::Ctrl('u'), Key::Up]);
+        assert_eq!(editor.line(), "in rust");
+    }
+
+    #[test]
+    fn test_reverse_search() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("/join #rust\nhello\n"));
+        keys(&mut editor, vec![Key::Ctrl('r')]);
+        keys(&mut editor, typed("jo"));
+        assert_eq!(editor.render().0, "(reverse-i-search)`jo': /join #rust");
+        keys(&mut editor, vec![Key::Ctrl('e')]);
+        assert_eq!(editor.line(), "/join #rust");
+    }
+
+    #[test]
+    fn test_tab_completion() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("al\t"));
+        assert_eq!(editor.line(), "alice: ");
+        keys(&mut editor, typed("see #r\t"));
+        assert_eq!(editor.line(), "alice: see #rust ");
+    }
+
+    #[test]
+    fn test_multi_line_paste_needs_confirmation() {
+        let mut editor = Editor::new("#rust");
+        let candidates = Candidates::default();
+        editor.handle_event(Event::Unsupported(PASTE_START.to_vec()), &candidates);
+        keys(&mut editor, typed("one\ntwo\n"));
+        editor.handle_event(Event::Unsupported(PASTE_END.to_vec()), &candidates);
+        assert_eq!(editor.render().0, "Paste 2 lines to #rust? (y/n) ");
+
+        let actions = keys(&mut editor, typed("y"));
+        assert_eq!(
+            actions,
+            vec![Action::Submit(vec!["one".to_string(), "two".to_string()])]
+        );
+    }
+
+    #[test]
+    fn test_ask() {
+        let mut editor = Editor::new("#rust");
+        editor.ask("Accept?", "/dcc accept 1", "/dcc reject 1");
+        assert_eq!(editor.render().0, "Accept? (y/n) ");
+
+        let actions = keys(&mut editor, typed("xn"));
+        assert_eq!(
+            actions,
+            vec![
+                Action::Redraw,
+                Action::Submit(vec!["/dcc reject 1".to_string()])
+            ]
+        );
+        assert_eq!(editor.render().0, "[#rust] ");
+    }
+
+    #[test]
+    fn test_ask_waits_for_empty_line() {
+        let mut editor = Editor::new("#rust");
+        keys(&mut editor, typed("hello"));
+        editor.ask("Accept?", "/dcc accept 1", "/dcc reject 1");
+        editor.ask("Accept?", "/dcc accept 2", "/dcc reject 2");
+        // Still typing, so the y and n are just text
+        keys(&mut editor, typed(" yn"));
+        assert_eq!(editor.render().0, "[#rust] hello yn");
+
+        let actions = keys(&mut editor, vec![Key::Char('\n')]);
+        assert_eq!(actions, vec![Action::Submit(vec!["hello yn".to_string()])]);
+        assert_eq!(editor.render().0, "Accept? (y/n) ");
+        let actions = keys(&mut editor, typed("yn"));
+        assert_eq!(
+            actions,
+            vec![
+                Action::Submit(vec!["/dcc accept 1".to_string()]),
+                Action::Submit(vec!["/dcc reject 2".to_string()])
+            ]
+        );
+        assert_eq!(editor.render().0, "[#rust] ");
+    }
+
+    #[test]
+    fn test_paste_without_confirmation() {
+        let mut editor = Editor::new("#rust");
+        editor.set_confirm_paste(false);
+        let candidates = Candidates::default();
+        editor.handle_event(Event::Unsupported(PASTE_START.to_vec()), &candidates);
+        keys(&mut editor, typed("one\ntwo\n"));
+        let action = editor.handle_event(Event::Unsupported(PASTE_END.to_vec()), &candidates);
+        assert_eq!(
+            action,
+            Action::Submit(vec!["one".to_string(), "two".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_rejected_paste_is_dropped() {
+        let mut editor = Editor::new("#rust");
+        let candidates = Candidates::default();
+        editor.handle_event(Event::Unsupported(PASTE_START.to_vec()), &candidates);
+        keys(&mut editor, typed("one\ntwo"));
+        editor.handle_event(Event::Unsupported(PASTE_END.to_vec()), &candidates);
+        keys(&mut editor, typed("n"));
+        assert_eq!(editor.line(), "");
+        assert_eq!(editor.render().0, "[#rust] ");
+    }
+}
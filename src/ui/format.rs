@@ -0,0 +1,132 @@
+//! Draws mIRC formatted text with terminal attributes.
+
+use std::env;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use termion::{color, style};
+
+use crate::message::format::{self, Color, Span, Style};
+
+/// The xterm 256 color equivalents of the 99 mIRC colors.
+const PALETTE: [u8; 99] = [
+    15, 0, 4, 2, 9, 1, 5, 3, 11, 10, 6, 14, 12, 13, 8, 7, // 0-15
+    52, 94, 100, 58, 22, 29, 23, 24, 17, 54, 53, 89, // 16-27
+    88, 130, 142, 64, 28, 35, 30, 25, 18, 91, 90, 125, // 28-39
+    124, 166, 184, 106, 34, 49, 37, 33, 19, 129, 127, 161, // 40-51
+    196, 208, 226, 154, 46, 86, 51, 75, 21, 171, 201, 198, // 52-63
+    203, 215, 227, 191, 83, 122, 87, 111, 63, 177, 207, 205, // 64-75
+    217, 223, 229, 193, 157, 158, 159, 153, 147, 183, 219, 212, // 76-87
+    16, 233, 235, 237, 239, 241, 244, 247, 250, 254, 231, // 88-98
+];
+
+static COLORS: AtomicBool = AtomicBool::new(true);
+
+pub fn set_colors(enabled: bool) {
+    COLORS.store(enabled, Ordering::Relaxed);
+}
+
+/// Renders formatted text for the terminal, stripping the formatting
+/// instead when `NO_COLOR` is set or colors are turned off in the config.
+pub fn render(text: &str) -> String {
+    let colors = COLORS.load(Ordering::Relaxed) && env::var_os("NO_COLOR").is_none();
+    render_with(text, colors)
+}
+
+/// Renders formatted text with terminal attributes when `colors` is true,
+/// or strips it. The output always ends with the terminal attributes reset
+/// so formatting can't leak into what follows.
+fn render_with(text: &str, colors: bool) -> String {
+    if !colors {
+        return format::strip(text);
+    }
+
+    let spans = format::parse(text);
+    if spans.iter().all(|span| span.style.is_plain()) {
+        return spans.into_iter().map(|span| span.text).collect();
+    }
+
+    let mut out = String::new();
+    for Span { text, style } in spans {
+        out.push_str(&attributes(&style));
+        out.push_str(&text);
+    }
+    write!(out, "{}", style::Reset).unwrap();
+    out
+}
+
+fn attributes(s: &Style) -> String {
+    let mut out = style::Reset.to_string();
+    if s.bold {
+        write!(out, "{}", style::Bold).unwrap();
+    }
+    if s.italic {
+        write!(out, "{}", style::Italic).unwrap();
+    }
+    if s.underline {
+        write!(out, "{}", style::Underline).unwrap();
+    }
+    if s.strikethrough {
+        write!(out, "{}", style::CrossedOut).unwrap();
+    }
+    if s.reverse {
+        write!(out, "{}", style::Invert).unwrap();
+    }
+    // Terminals are already monospace, there's nothing to do for it
+    match s.fg {
+        Some(Color::Palette(n)) => write!(out, "{}", color::Fg(ansi(n))).unwrap(),
+        Some(Color::Rgb(r, g, b)) => write!(out, "{}", color::Fg(color::Rgb(r, g, b))).unwrap(),
+        None => {}
+    }
+    match s.bg {
+        Some(Color::Palette(n)) => write!(out, "{}", color::Bg(ansi(n))).unwrap(),
+        Some(Color::Rgb(r, g, b)) => write!(out, "{}", color::Bg(color::Rgb(r, g, b))).unwrap(),
+        None => {}
+    }
+    out
+}
+
+fn ansi(n: u8) -> color::AnsiValue {
+    color::AnsiValue(PALETTE.get(n as usize).copied().unwrap_or(15))
+}
+
+/// How a formatting control character is shown while it's being typed.
+pub fn control_placeholder(c: char) -> Option<char> {
+    match c {
+        format::BOLD => Some('B'),
+        format::COLOR => Some('C'),
+        format::HEX_COLOR => Some('H'),
+        format::RESET => Some('O'),
+        format::MONOSPACE => Some('M'),
+        format::REVERSE => Some('R'),
+        format::ITALIC => Some('I'),
+        format::STRIKETHROUGH => Some('S'),
+        format::UNDERLINE => Some('U'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_is_untouched() {
+        assert_eq!(render_with("hello", true), "hello");
+    }
+
+    #[test]
+    fn test_render() {
+        let expected = format!(
+            "{}a{}{}b{}{}c{}",
+            style::Reset,
+            style::Reset,
+            style::Bold,
+            style::Reset,
+            color::Fg(color::AnsiValue(9)),
+            style::Reset
+        );
+        assert_eq!(render_with("a\u{2}b\u{2}\u{3}4c", true), expected);
+        assert_eq!(render_with("a\u{2}b\u{2}\u{3}4c", false), "abc");
+    }
+}
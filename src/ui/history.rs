@@ -0,0 +1,112 @@
+const MAX_ENTRIES: usize = 500;
+
+/// Lines previously submitted from a single buffer, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    position: Option<usize>,
+    draft: String,
+}
+
+impl History {
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+        if line.trim().is_empty() || self.entries.last().map(|l| l.as_str()) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Steps back one entry. `current` is what's in the editor right now so
+    /// it can be restored once the user walks forward past the newest entry.
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => 0,
+            Some(n) => n - 1,
+        };
+        self.position = Some(position);
+        self.entries.get(position).map(|l| l.as_str())
+    }
+
+    pub fn next(&mut self) -> Option<&str> {
+        match self.position {
+            None => None,
+            Some(n) if n + 1 >= self.entries.len() => {
+                self.position = None;
+                Some(self.draft.as_str())
+            }
+            Some(n) => {
+                self.position = Some(n + 1);
+                self.entries.get(n + 1).map(|l| l.as_str())
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.position = None;
+        self.draft.clear();
+    }
+
+    /// Finds the newest entry containing `query`, starting at (and including)
+    /// `before - 1`. Returns the index alongside the entry so the caller can
+    /// keep searching further back.
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<(usize, &str)> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, line)| line.contains(query))
+            .map(|(i, line)| (i, line.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_previous_and_next() {
+        let mut history = History::default();
+        history.push("first");
+        history.push("second");
+
+        assert_eq!(history.previous("draft"), Some("second"));
+        assert_eq!(history.previous("second"), Some("first"));
+        assert_eq!(history.previous("first"), Some("first"));
+        assert_eq!(history.next(), Some("second"));
+        assert_eq!(history.next(), Some("draft"));
+        assert_eq!(history.next(), None);
+    }
+
+    #[test]
+    fn test_skips_duplicates_and_blanks() {
+        let mut history = History::default();
+        history.push("hello");
+        history.push("hello");
+        history.push("   ");
+
+        assert_eq!(history.previous(""), Some("hello"));
+        assert_eq!(history.previous("hello"), Some("hello"));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut history = History::default();
+        history.push("/join #rust");
+        history.push("hello there");
+        history.push("/join #ops");
+
+        assert_eq!(history.search("join", None), Some((2, "/join #ops")));
+        assert_eq!(history.search("join", Some(2)), Some((0, "/join #rust")));
+        assert_eq!(history.search("nothing", None), None);
+    }
+}
@@ -0,0 +1,606 @@
+use crate::client::history::{Reference, Subcommand};
+use crate::client::list::Filter;
+use crate::client::state::CHANNEL_PREFIXES;
+use crate::client::url::Url;
+use crate::search::Query;
+
+/// Commands the user can type, used for tab completion.
+pub const COMMANDS: &[&str] = &[
+    "/ban",
+    "/buffer",
+    "/connect",
+    "/ctcp",
+    "/dcc",
+    "/history",
+    "/join",
+    "/jump",
+    "/kill",
+    "/list",
+    "/me",
+    "/mode",
+    "/msg",
+    "/names",
+    "/nick",
+    "/notify",
+    "/op",
+    "/oper",
+    "/parent",
+    "/part",
+    "/query",
+    "/quiet",
+    "/quit",
+    "/reply",
+    "/search",
+    "/snotices",
+    "/umode",
+    "/unban",
+    "/voice",
+    "/wallops",
+    "/who",
+    "/whois",
+    "/whowas",
+];
+
+/// A line submitted from the editor.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// Plain text for the active buffer.
+    Text(String),
+    /// Bans a nick or mask from the active channel, or lists the bans.
+    Ban(Option<String>),
+    Buffer(String),
+    Connect(Url),
+    Ctcp(String, String, Option<String>),
+    Dcc(DccCommand),
+    /// Fetches history for the active buffer, earlier than what we've seen
+    /// without a subcommand.
+    History(Option<Subcommand>, Option<usize>),
+    Join(String, Option<String>),
+    /// Shows a result of the last search in its buffer.
+    Jump(usize),
+    /// Disconnects someone from the network, with a reason.
+    Kill(String, String),
+    List(ListCommand),
+    Me(String),
+    /// Changes a channel's modes, the active one's unless the first word is
+    /// a channel, or shows them.
+    Mode(Option<String>, Option<String>, Vec<String>),
+    Msg(String, String),
+    /// Lists who's in a channel, the active buffer without one.
+    Names(Option<String>),
+    Nick(String),
+    Notify(NotifyCommand),
+    Op(Vec<String>),
+    /// Logs in as an oper with a name and password.
+    Oper(String, String),
+    /// Shows what the n-th latest reply in the active buffer replied to.
+    Parent(usize),
+    Part(Option<String>, Option<String>),
+    Query(String),
+    Quiet(String),
+    Quit(Option<String>),
+    /// Replies to a message picked by number, nick or msgid, or lists the
+    /// ones to pick from without.
+    Reply(Option<(String, String)>),
+    Search(Query),
+    /// Switches to the server notices, or sets the words they're filtered
+    /// by.
+    Snotices(Option<Vec<String>>),
+    /// Lifts a ban by mask, nick, or number in the last ban list.
+    Unban(String),
+    /// Changes our user modes, or shows them.
+    UserMode(Vec<String>),
+    Voice(Vec<String>),
+    Wallops(String),
+    Who(String),
+    Whois(String),
+    Whowas(String),
+}
+
+/// The `/dcc` subcommands.
+#[derive(Debug, PartialEq)]
+pub enum DccCommand {
+    List,
+    Accept(usize),
+    Reject(usize),
+    Chat {
+        nick: String,
+        passive: bool,
+    },
+    Send {
+        nick: String,
+        path: String,
+        passive: bool,
+    },
+    Close(String),
+}
+
+impl DccCommand {
+    fn parse(args: &str) -> Option<Self> {
+        let mut words = args.split_whitespace();
+        let subcommand = words.next().unwrap_or("list").to_lowercase();
+        let mut rest: Vec<&str> = words.collect();
+        let passive = rest.first() == Some(&"-passive");
+        if passive {
+            rest.remove(0);
+        }
+
+        match (subcommand.as_str(), rest.as_slice()) {
+            ("list", []) => Some(DccCommand::List),
+            ("accept", [id]) => id.parse().ok().map(DccCommand::Accept),
+            ("reject", [id]) => id.parse().ok().map(DccCommand::Reject),
+            ("chat", [nick]) => Some(DccCommand::Chat {
+                nick: nick.to_string(),
+                passive,
+            }),
+            ("send", [nick, path @ ..]) if !path.is_empty() => Some(DccCommand::Send {
+                nick: nick.to_string(),
+                path: path.join(" "),
+                passive,
+            }),
+            ("close", [nick]) => Some(DccCommand::Close(nick.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// The `/notify` subcommands, which change the list in the config too.
+#[derive(Debug, PartialEq)]
+pub enum NotifyCommand {
+    List,
+    Add(String),
+    Remove(String),
+}
+
+/// The `/list` subcommands. Without one it fetches the list.
+#[derive(Debug, PartialEq)]
+pub enum ListCommand {
+    Fetch(Vec<Filter>),
+    Show {
+        by_name: bool,
+        page: usize,
+        text: String,
+    },
+    Join(usize),
+}
+
+impl ListCommand {
+    fn parse(args: &str) -> Option<Self> {
+        let mut words = args.split_whitespace().peekable();
+        match words.peek().map(|w| w.to_lowercase()).as_deref() {
+            Some("show") => {
+                words.next();
+                let mut words: Vec<&str> = words.collect();
+                let by_name = words.first() == Some(&"-name");
+                if by_name {
+                    words.remove(0);
+                }
+                let page = words.first().and_then(|w| w.parse().ok());
+                if page.is_some() {
+                    words.remove(0);
+                }
+                Some(ListCommand::Show {
+                    by_name,
+                    page: page.filter(|p| *p > 0).unwrap_or(1),
+                    text: words.join(" "),
+                })
+            }
+            Some("join") => match (words.nth(1), words.next()) {
+                (Some(number), None) => number.parse().ok().map(ListCommand::Join),
+                _ => None,
+            },
+            _ => words
+                .map(Filter::parse)
+                .collect::<Option<_>>()
+                .map(ListCommand::Fetch),
+        }
+    }
+}
+
+/// Parses `/history [subcommand [references]] [count]`.
+fn parse_history(args: &str) -> Option<(Option<Subcommand>, Option<usize>)> {
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let count = words.last().and_then(|w| w.parse().ok()).filter(|n| *n > 0);
+    if count.is_some() {
+        words.pop();
+    }
+    let reference = |word: &str| word.parse::<Reference>().ok();
+    let subcommand = match words.split_first() {
+        None => None,
+        Some((name, rest)) => Some(match (name.to_lowercase().as_str(), rest) {
+            ("latest", []) => Subcommand::Latest(Reference::Any),
+            ("latest", [from]) => Subcommand::Latest(reference(from)?),
+            ("before", [from]) => Subcommand::Before(reference(from)?),
+            ("after", [from]) => Subcommand::After(reference(from)?),
+            ("around", [from]) => Subcommand::Around(reference(from)?),
+            ("between", [from, to]) => Subcommand::Between(reference(from)?, reference(to)?),
+            ("targets", [from, to]) => Subcommand::Targets(reference(from)?, reference(to)?),
+            _ => return None,
+        }),
+    };
+    Some((subcommand, count))
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|w| w.to_string()).collect()
+}
+
+impl Input {
+    /// Parses a submitted line. Errors carry a usage string to show the
+    /// user. A leading `//` sends the rest of the line as text.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        if line.starts_with("//") {
+            return Ok(Input::Text(line[1..].to_string()));
+        }
+        if !line.starts_with('/') {
+            return Ok(Input::Text(line.to_string()));
+        }
+
+        let (command, rest) = match line.find(' ') {
+            Some(i) => (&line[..i], line[i + 1..].trim()),
+            None => (line, ""),
+        };
+        let mut args = rest.splitn(2, ' ');
+        let first = args.next().filter(|a| !a.is_empty()).map(|a| a.to_string());
+        let remainder = args
+            .next()
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty());
+        let usage = |u: &str| Err(format!("Usage: {}", u));
+
+        match command.to_lowercase().as_str() {
+            "/ban" => Ok(Input::Ban(first)),
+            "/buffer" => match first {
+                Some(name) => Ok(Input::Buffer(name)),
+                None => usage("/buffer <name>"),
+            },
+            "/connect" => match first {
+                Some(url) => url
+                    .parse()
+                    .map(Input::Connect)
+                    .map_err(|e| format!("Can't connect to {}: {}", url, e)),
+                None => usage("/connect <irc:// or ircs:// URL>"),
+            },
+            "/ctcp" => {
+                let mut rest = remainder.as_deref().unwrap_or("").splitn(2, ' ');
+                match (first, rest.next().filter(|c| !c.is_empty())) {
+                    (Some(target), Some(command)) => Ok(Input::Ctcp(
+                        target,
+                        command.to_string(),
+                        rest.next().map(|p| p.to_string()),
+                    )),
+                    _ => usage("/ctcp <target> <command> [params]"),
+                }
+            }
+            "/dcc" => match DccCommand::parse(rest) {
+                Some(command) => Ok(Input::Dcc(command)),
+                None => usage(
+                    "/dcc [list] | accept <id> | reject <id> | chat [-passive] <nick> \
+                     | send [-passive] <nick> <file> | close <nick>",
+                ),
+            },
+            "/history" => match parse_history(rest) {
+                Some((subcommand, count)) => Ok(Input::History(subcommand, count)),
+                None => usage(
+                    "/history [latest | before <ref> | after <ref> | around <ref> \
+                     | between <ref> <ref> | targets <time> <time>] [count]",
+                ),
+            },
+            "/join" => match first {
+                Some(channel) => Ok(Input::Join(channel, remainder)),
+                None => usage("/join <channel> [key]"),
+            },
+            "/jump" => match first.and_then(|n| n.parse().ok()) {
+                Some(number) => Ok(Input::Jump(number)),
+                None => usage("/jump <result number>"),
+            },
+            "/kill" => match (first, remainder) {
+                (Some(nick), Some(reason)) => Ok(Input::Kill(nick, reason)),
+                _ => usage("/kill <nick> <reason>"),
+            },
+            "/list" => match ListCommand::parse(rest) {
+                Some(command) => Ok(Input::List(command)),
+                None => usage(
+                    "/list [>users] [<users] [C>minutes] [C<minutes] [T>minutes] \
+                     [T<minutes] [mask] [!mask] | show [-name] [page] [text] | join <number>",
+                ),
+            },
+            "/me" if !rest.is_empty() => Ok(Input::Me(rest.to_string())),
+            "/me" => usage("/me <action>"),
+            "/mode" => {
+                let mut words = rest.split_whitespace().map(|w| w.to_string());
+                let mut channel = None;
+                let mut modes = words.next();
+                if modes
+                    .as_deref()
+                    .is_some_and(|m| m.starts_with(CHANNEL_PREFIXES) && !m.starts_with(['+', '-']))
+                {
+                    channel = modes;
+                    modes = words.next();
+                }
+                Ok(Input::Mode(channel, modes, words.collect()))
+            }
+            "/msg" => match (first, remainder) {
+                (Some(target), Some(text)) => Ok(Input::Msg(target, text)),
+                _ => usage("/msg <target> <text>"),
+            },
+            "/names" => Ok(Input::Names(first)),
+            "/nick" => match first {
+                Some(nick) => Ok(Input::Nick(nick)),
+                None => usage("/nick <nick>"),
+            },
+            "/notify" => match (first.as_deref(), remainder) {
+                (None, None) => Ok(Input::Notify(NotifyCommand::List)),
+                (Some("add"), Some(nick)) => Ok(Input::Notify(NotifyCommand::Add(nick))),
+                (Some("del"), Some(nick)) => Ok(Input::Notify(NotifyCommand::Remove(nick))),
+                _ => usage("/notify [add <nick> | del <nick>]"),
+            },
+            "/parent" => match first.map(|n| n.parse()) {
+                None => Ok(Input::Parent(1)),
+                Some(Ok(number)) if number > 0 => Ok(Input::Parent(number)),
+                Some(_) => usage("/parent [number]"),
+            },
+            "/op" | "/voice" if rest.is_empty() => usage(&format!("{} <nick>...", command)),
+            "/op" => Ok(Input::Op(words(rest))),
+            "/voice" => Ok(Input::Voice(words(rest))),
+            "/oper" => match (first, remainder) {
+                (Some(name), Some(password)) => Ok(Input::Oper(name, password)),
+                _ => usage("/oper <name> <password>"),
+            },
+            "/part" => Ok(Input::Part(first, remainder)),
+            "/query" => match first {
+                Some(nick) => Ok(Input::Query(nick)),
+                None => usage("/query <nick>"),
+            },
+            "/quiet" => match first {
+                Some(target) => Ok(Input::Quiet(target)),
+                None => usage("/quiet <nick or mask>"),
+            },
+            "/quit" => Ok(Input::Quit(
+                Some(rest.to_string()).filter(|r| !r.is_empty()),
+            )),
+            "/reply" => match (first, remainder) {
+                (None, None) => Ok(Input::Reply(None)),
+                (Some(choice), Some(text)) => Ok(Input::Reply(Some((choice, text)))),
+                _ => usage("/reply [<number | nick | msgid> <text>]"),
+            },
+            "/search" => match rest.parse() {
+                Ok(query) => Ok(Input::Search(query)),
+                Err(_) if rest.is_empty() => usage(
+                    "/search [nick:<nick>] [in:<buffer>] [since:<date>] [until:<date>] [words]",
+                ),
+                Err(e) => Err(e),
+            },
+            "/snotices" => match (first.as_deref(), remainder) {
+                (None, _) => Ok(Input::Snotices(None)),
+                (Some("filter"), words) => Ok(Input::Snotices(Some(
+                    words.as_deref().map(self::words).unwrap_or_default(),
+                ))),
+                _ => usage("/snotices [filter [word | -word]...]"),
+            },
+            "/umode" => Ok(Input::UserMode(words(rest))),
+            "/unban" => match first {
+                Some(target) => Ok(Input::Unban(target)),
+                None => usage("/unban <mask, nick or number>"),
+            },
+            "/wallops" if !rest.is_empty() => Ok(Input::Wallops(rest.to_string())),
+            "/wallops" => usage("/wallops <text>"),
+            "/who" => match first {
+                Some(mask) => Ok(Input::Who(mask)),
+                None => usage("/who <mask or channel>"),
+            },
+            "/whois" => match first {
+                Some(nick) => Ok(Input::Whois(nick)),
+                None => usage("/whois <nick>"),
+            },
+            "/whowas" => match first {
+                Some(nick) => Ok(Input::Whowas(nick)),
+                None => usage("/whowas <nick>"),
+            },
+            _ => Err(format!("Unknown command {}", command)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text() {
+        assert_eq!(Input::parse("hello"), Ok(Input::Text("hello".to_string())));
+        assert_eq!(Input::parse("//me"), Ok(Input::Text("/me".to_string())));
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(
+            Input::parse("/join #ops secret"),
+            Ok(Input::Join("#ops".to_string(), Some("secret".to_string())))
+        );
+        assert_eq!(
+            Input::parse("/msg bob see you soon"),
+            Ok(Input::Msg("bob".to_string(), "see you soon".to_string()))
+        );
+        assert_eq!(Input::parse("/part"), Ok(Input::Part(None, None)));
+        assert_eq!(
+            Input::parse("/names #ops"),
+            Ok(Input::Names(Some("#ops".to_string())))
+        );
+        assert_eq!(
+            Input::parse("/me waves at everyone"),
+            Ok(Input::Me("waves at everyone".to_string()))
+        );
+        assert_eq!(
+            Input::parse("/ctcp bob ping 1234"),
+            Ok(Input::Ctcp(
+                "bob".to_string(),
+                "ping".to_string(),
+                Some("1234".to_string())
+            ))
+        );
+        assert!(matches!(
+            Input::parse("/connect ircs://irc.libera.chat/#rust"),
+            Ok(Input::Connect(url)) if url.host == "irc.libera.chat"
+        ));
+        assert!(matches!(
+            Input::parse(r#"/search nick:alice "deploy" in:#ops"#),
+            Ok(Input::Search(query)) if query.terms == ["deploy"]
+        ));
+        assert_eq!(Input::parse("/jump 2"), Ok(Input::Jump(2)));
+        assert_eq!(
+            Input::parse("/mode #ops +ov-b alice bob *!*@x"),
+            Ok(Input::Mode(
+                Some("#ops".to_string()),
+                Some("+ov-b".to_string()),
+                vec!["alice".to_string(), "bob".to_string(), "*!*@x".to_string()]
+            ))
+        );
+        assert_eq!(
+            Input::parse("/mode +e"),
+            Ok(Input::Mode(None, Some("+e".to_string()), vec![]))
+        );
+        assert_eq!(Input::parse("/ban"), Ok(Input::Ban(None)));
+        assert_eq!(
+            Input::parse("/op alice bob"),
+            Ok(Input::Op(vec!["alice".to_string(), "bob".to_string()]))
+        );
+        assert!(Input::parse("/voice").is_err());
+        assert_eq!(
+            Input::parse("/oper admin hunter2"),
+            Ok(Input::Oper("admin".to_string(), "hunter2".to_string()))
+        );
+        assert_eq!(
+            Input::parse("/kill spammer Spamming #rust"),
+            Ok(Input::Kill(
+                "spammer".to_string(),
+                "Spamming #rust".to_string()
+            ))
+        );
+        assert!(Input::parse("/kill spammer").is_err());
+        assert_eq!(Input::parse("/snotices"), Ok(Input::Snotices(None)));
+        assert_eq!(
+            Input::parse("/snotices filter connect -bot"),
+            Ok(Input::Snotices(Some(vec![
+                "connect".to_string(),
+                "-bot".to_string()
+            ])))
+        );
+        assert_eq!(
+            Input::parse("/snotices filter"),
+            Ok(Input::Snotices(Some(vec![])))
+        );
+        assert_eq!(
+            Input::parse("/umode +s +cC"),
+            Ok(Input::UserMode(vec!["+s".to_string(), "+cC".to_string()]))
+        );
+        assert_eq!(
+            Input::parse("/list >50 #rust*"),
+            Ok(Input::List(ListCommand::Fetch(vec![
+                Filter::MoreUsers(50),
+                Filter::Mask("#rust*".to_string())
+            ])))
+        );
+        assert_eq!(
+            Input::parse("/list show -name 3 async io"),
+            Ok(Input::List(ListCommand::Show {
+                by_name: true,
+                page: 3,
+                text: "async io".to_string()
+            }))
+        );
+        assert_eq!(
+            Input::parse("/list join 12"),
+            Ok(Input::List(ListCommand::Join(12)))
+        );
+        assert!(Input::parse("/list >many").is_err());
+        assert_eq!(
+            Input::parse("/whois alice"),
+            Ok(Input::Whois("alice".to_string()))
+        );
+        assert_eq!(
+            Input::parse("/who #ops"),
+            Ok(Input::Who("#ops".to_string()))
+        );
+        assert!(Input::parse("/whowas").is_err());
+        assert_eq!(
+            Input::parse("/notify add alice"),
+            Ok(Input::Notify(NotifyCommand::Add("alice".to_string())))
+        );
+        assert!(Input::parse("/notify alice").is_err());
+        assert_eq!(Input::parse("/reply"), Ok(Input::Reply(None)));
+        assert_eq!(
+            Input::parse("/reply 2 sounds good"),
+            Ok(Input::Reply(Some((
+                "2".to_string(),
+                "sounds good".to_string()
+            ))))
+        );
+        assert!(Input::parse("/reply alice").is_err());
+        assert_eq!(Input::parse("/parent"), Ok(Input::Parent(1)));
+        assert_eq!(Input::parse("/parent 3"), Ok(Input::Parent(3)));
+        assert!(Input::parse("/parent 0").is_err());
+        assert_eq!(Input::parse("/history"), Ok(Input::History(None, None)));
+        assert_eq!(
+            Input::parse("/history around msgid=abc 20"),
+            Ok(Input::History(
+                Some(Subcommand::Around(Reference::MsgId("abc".to_string()))),
+                Some(20)
+            ))
+        );
+        assert!(matches!(
+            Input::parse("/history between 2026-01-01 2026-01-02"),
+            Ok(Input::History(Some(Subcommand::Between(_, _)), None))
+        ));
+        assert_eq!(
+            Input::parse("/QUIT gone fishing"),
+            Ok(Input::Quit(Some("gone fishing".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_dcc() {
+        assert_eq!(Input::parse("/dcc"), Ok(Input::Dcc(DccCommand::List)));
+        assert_eq!(
+            Input::parse("/dcc accept 3"),
+            Ok(Input::Dcc(DccCommand::Accept(3)))
+        );
+        assert_eq!(
+            Input::parse("/dcc send -passive bob my notes.txt"),
+            Ok(Input::Dcc(DccCommand::Send {
+                nick: "bob".to_string(),
+                path: "my notes.txt".to_string(),
+                passive: true,
+            }))
+        );
+        assert_eq!(
+            Input::parse("/dcc chat bob"),
+            Ok(Input::Dcc(DccCommand::Chat {
+                nick: "bob".to_string(),
+                passive: false,
+            }))
+        );
+        assert!(Input::parse("/dcc accept one").is_err());
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert_eq!(
+            Input::parse("/msg bob"),
+            Err("Usage: /msg <target> <text>".to_string())
+        );
+        assert_eq!(
+            Input::parse("/connect http://example.org"),
+            Err("Can't connect to http://example.org: expected an irc:// or ircs:// URL, not http://".to_string())
+        );
+        assert_eq!(
+            Input::parse("/search since:soon"),
+            Err("soon isn't a date like 2026-01-31".to_string())
+        );
+        assert!(Input::parse("/history before").is_err());
+        assert_eq!(
+            Input::parse("/frobnicate"),
+            Err("Unknown command /frobnicate".to_string())
+        );
+    }
+}
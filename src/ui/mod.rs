@@ -0,0 +1,932 @@
+use std::collections::BTreeMap;
+use std::io::{self, stdout, Stdout, Write};
+
+use chrono::Utc;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::{clear, color, cursor, style};
+
+pub mod completion;
+pub mod editor;
+pub mod format;
+pub mod history;
+pub mod input;
+pub mod timestamp;
+
+use crate::client::batch::Netsplit;
+use crate::client::connection::Event as ConnectionEvent;
+use crate::client::dcc::{self, Event as DccEvent};
+use crate::client::list::Entry;
+use crate::client::modes::{self, ModeList};
+use crate::client::notify::{self, Change, NotifyList};
+use crate::client::pending::Sent;
+use crate::client::scrollback::{Said, REACT_TAG, REPLY_TAG};
+use crate::client::snotices::Notice;
+use crate::client::state::{State, RPL_UMODEIS};
+use crate::client::whois::{Lookup, WhoReply, WhoisInfo};
+use crate::message::{Command, Message};
+use crate::search::Hit;
+use crate::ui::editor::Editor;
+
+const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+
+/// The terminal: scrolling output with a status line and the input line
+/// pinned underneath it. Output and input happen on different threads so
+/// both go through here to keep the bottom two lines intact.
+pub struct Screen {
+    out: RawTerminal<Stdout>,
+    pub editor: Editor,
+    status: BTreeMap<String, String>,
+}
+
+impl Screen {
+    pub fn new(buffer: &str) -> io::Result<Self> {
+        let mut out = stdout().into_raw_mode()?;
+        write!(out, "{}", BRACKETED_PASTE_ON)?;
+        let mut screen = Self {
+            out,
+            editor: Editor::new(buffer),
+            status: BTreeMap::new(),
+        };
+        screen.write_status()?;
+        screen.draw_input()?;
+        Ok(screen)
+    }
+
+    /// Prints a line of output above the status line.
+    pub fn print(&mut self, line: &str) -> io::Result<()> {
+        write!(self.out, "{}\r{}", cursor::Up(1), clear::AfterCursor)?;
+        for l in line.lines() {
+            write!(self.out, "{}\r\n", l)?;
+        }
+        self.write_status()?;
+        self.draw_input()
+    }
+
+    /// Sets or clears (with `None`) one item shown in the status line.
+    /// Items are shown sorted by `name`.
+    pub fn set_status(&mut self, name: &str, text: Option<String>) -> io::Result<()> {
+        match text {
+            Some(text) => self.status.insert(name.to_string(), text),
+            None => self.status.remove(name),
+        };
+        write!(self.out, "{}\r", cursor::Up(1))?;
+        self.write_status()?;
+        self.draw_input()
+    }
+
+    /// Writes the status line at the cursor and moves down to the input line.
+    fn write_status(&mut self) -> io::Result<()> {
+        let items: Vec<&str> = self.status.values().map(|s| s.as_str()).collect();
+        let text = format!(" {}", items.join(" | "));
+        let width = terminal_width();
+        let text: String = text.chars().take(width).collect();
+        write!(
+            self.out,
+            "{}{}{:width$}{}\r\n",
+            clear::CurrentLine,
+            style::Invert,
+            text,
+            style::Reset,
+            width = width
+        )
+    }
+
+    pub fn draw_input(&mut self) -> io::Result<()> {
+        let (text, position) = self.editor.render();
+        let width = terminal_width();
+
+        // Scroll the line horizontally so the cursor always stays visible
+        let skip = (position + 1).saturating_sub(width);
+        let visible: String = text
+            .chars()
+            .skip(skip)
+            .take(width)
+            .map(|c| match format::control_placeholder(c) {
+                Some(p) => format!("{}{}{}", style::Invert, p, style::NoInvert),
+                None => c.to_string(),
+            })
+            .collect();
+        write!(self.out, "\r{}{}\r", clear::CurrentLine, visible)?;
+        if position > skip {
+            write!(self.out, "{}", cursor::Right((position - skip) as u16))?;
+        }
+        self.out.flush()
+    }
+
+    /// Puts the terminal back the way we found it.
+    pub fn close(&mut self) -> io::Result<()> {
+        write!(
+            self.out,
+            "{}\r{}{}",
+            cursor::Up(1),
+            clear::AfterCursor,
+            BRACKETED_PASTE_OFF
+        )?;
+        self.out.suspend_raw_mode()?;
+        self.out.flush()
+    }
+}
+
+fn terminal_width() -> usize {
+    match termion::terminal_size() {
+        Ok((w, _)) if w > 0 => w as usize,
+        _ => 80,
+    }
+}
+
+/// Buffer a message belongs in. Private messages to us go in a buffer named
+/// after the sender.
+pub fn buffer_for(message: &Message, nick: &str) -> Option<String> {
+    let target = message.get_param(0)?.to_string();
+    if target == nick {
+        message.source().map(|s| s.nick.clone())
+    } else {
+        Some(target)
+    }
+}
+
+/// Formats a message from the server for display, after the time it was
+/// sent. Messages for buffers other than the `active` one are prefixed with
+/// their buffer name.
+pub fn format_message(message: &Message, active: &str, state: &State) -> String {
+    let parent = message
+        .tag(REPLY_TAG)
+        .and_then(|msgid| state.scrollback.get(msgid));
+    let line = message_line(message, active, &state.nick, parent);
+    if line.is_empty() {
+        return line;
+    }
+    let time = message.time().unwrap_or_else(Utc::now);
+    let line = format!("{}{}", timestamp::format(time), line);
+    match message.tag(REPLY_TAG) {
+        Some(_) if message.command() == &Command::PrivMsg => {
+            format!("{}\n{}", format_quote(parent), line)
+        }
+        _ => line,
+    }
+}
+
+/// The line above a reply quoting what it replies to, if we saw it.
+pub fn format_quote(parent: Option<&Said>) -> String {
+    let quoted = match parent {
+        Some(said) => format!("<{}> {}", said.nick, said.excerpt(QUOTE_LEN)),
+        None => "in reply to an earlier message".to_string(),
+    };
+    format!(
+        "{}  ┌ {}{}",
+        color::Fg(color::LightBlack),
+        quoted,
+        color::Fg(color::Reset)
+    )
+}
+
+/// Formats a message `/reply` can pick by `number`.
+pub fn format_reply_choice(number: usize, said: &Said) -> String {
+    let reply = match said.reply_to {
+        Some(_) => " (a reply)",
+        None => "",
+    };
+    format!(
+        "{:>3}. <{}> {}{}",
+        number,
+        said.nick,
+        said.excerpt(QUOTE_LEN),
+        reply
+    )
+}
+
+fn message_line(message: &Message, active: &str, nick: &str, parent: Option<&Said>) -> String {
+    let param = |i| {
+        message
+            .get_param(i)
+            .map(|p| p.to_string())
+            .unwrap_or_default()
+    };
+    match message.command() {
+        // Replies we sent ourselves, echoed back
+        Command::Notice if message.ctcp().is_some() && sender(message) == nick => String::new(),
+        Command::Notice if message.ctcp().is_some() => {
+            let ctcp = message.ctcp().unwrap();
+            format!(
+                "{}CTCP {} reply from {}: {}{}",
+                color::Fg(color::Yellow),
+                ctcp.command,
+                sender(message),
+                ctcp.params.unwrap_or_default(),
+                color::Fg(color::Reset)
+            )
+        }
+        Command::Notice => format!(
+            "{}{}{}",
+            color::Fg(color::Yellow),
+            format::render(&message.to_string()),
+            color::Fg(color::Reset)
+        ),
+        Command::RplWelcome | Command::RplMyInfo | Command::RplYourHost | Command::RplCreated => {
+            format!(
+                "{}{}{}{}{}",
+                style::Bold,
+                color::Fg(color::LightBlue),
+                message,
+                color::Fg(color::Reset),
+                style::Reset
+            )
+        }
+        // Kept up to date in the nick list rather than shown
+        Command::Account | Command::Away | Command::SetName => String::new(),
+        // Shown as who came online or went offline
+        _ if notify::is_reply(message) => String::new(),
+        // Shown once the whole list is in
+        _ if modes::is_list_reply(message) => String::new(),
+        Command::Mode => format!(
+            "{}-- {} sets {} on {}{}",
+            color::Fg(color::LightBlack),
+            sender(message),
+            message.params().get(1..).unwrap_or_default().join(" "),
+            param(0),
+            color::Fg(color::Reset)
+        ),
+        Command::Unknown(code) if code == RPL_UMODEIS => format!(
+            "{}-- Your user modes are {}{}",
+            color::Fg(color::LightBlack),
+            message.params().get(1..).unwrap_or_default().join(" "),
+            color::Fg(color::Reset)
+        ),
+        Command::ChgHost => format!(
+            "{}-- {} changed host to {}@{}{}",
+            color::Fg(color::LightBlack),
+            sender(message),
+            param(0),
+            param(1),
+            color::Fg(color::Reset)
+        ),
+        Command::Invite => {
+            let invited = match param(0) {
+                invited if invited == nick => "you".to_string(),
+                invited => invited,
+            };
+            format!(
+                "{}-- {} invited {} to {}{}",
+                color::Fg(color::Yellow),
+                sender(message),
+                invited,
+                param(1),
+                color::Fg(color::Reset)
+            )
+        }
+        Command::MessageOfTheDay
+        | Command::RplMotd
+        | Command::RplMotdStart
+        | Command::RplEndOfMotd
+        | Command::ErrNoMotd => format!("{}{}{}", style::Italic, message, style::Reset),
+        Command::TagMsg => {
+            let reaction = match message.tag(REACT_TAG) {
+                Some(reaction) => reaction,
+                None => return String::new(),
+            };
+            let about = match parent {
+                Some(said) => format!(" to <{}> {}", said.nick, said.excerpt(QUOTE_LEN)),
+                None => String::new(),
+            };
+            format!(
+                "{}{}-- {} reacted {}{}{}",
+                buffer_prefix(message, active, nick),
+                color::Fg(color::LightBlack),
+                sender(message),
+                reaction,
+                about,
+                color::Fg(color::Reset)
+            )
+        }
+        Command::PrivMsg => {
+            let name = sender(message);
+            let text = message
+                .get_param(1)
+                .map(|p| p.to_string())
+                .unwrap_or_default();
+            let buffer = buffer_prefix(message, active, nick);
+
+            // Our own, echoed back by the server
+            let own = name == nick;
+            match message.ctcp() {
+                Some(ctcp) if ctcp.is_action() => {
+                    let text = ctcp.params.unwrap_or_default();
+                    let action = match own {
+                        true => format_action(&name, &text, color::Cyan),
+                        false => format_action(&name, &text, color::Green),
+                    };
+                    return format!("{}{}", buffer, action);
+                }
+                // Offers are shown by the DCC manager once they've been checked,
+                // and requests we sent when we sent them
+                Some(ctcp) if ctcp.command == "DCC" || own => return String::new(),
+                Some(ctcp) => {
+                    return format!(
+                        "{}{}CTCP {} from {}{}",
+                        buffer,
+                        color::Fg(color::Yellow),
+                        ctcp.command,
+                        name,
+                        color::Fg(color::Reset)
+                    );
+                }
+                None => {}
+            }
+
+            let nick_color = match own {
+                true => color::Fg(color::Cyan).to_string(),
+                false => color::Fg(color::Green).to_string(),
+            };
+            format!(
+                "{}{}{}<{}>{}:{} {}",
+                buffer,
+                style::Bold,
+                nick_color,
+                name,
+                color::Fg(color::Reset),
+                style::Reset,
+                format::render(&text)
+            )
+        }
+        _ => format!("{}", message),
+    }
+}
+
+/// How many characters of a message are quoted when showing what's
+/// reacted or replied to.
+const QUOTE_LEN: usize = 40;
+
+/// The buffer a message is for, to show in front of it when it isn't the
+/// `active` one.
+fn buffer_prefix(message: &Message, active: &str, nick: &str) -> String {
+    match buffer_for(message, nick) {
+        Some(buffer) if buffer != active => format!("[{}] ", buffer),
+        _ => String::new(),
+    }
+}
+
+/// The status line item for who's typing in the active buffer.
+pub fn typing_status(nicks: &[String]) -> Option<String> {
+    match nicks {
+        [] => None,
+        [nick] => Some(format!("{} is typing", nick)),
+        [first, second] => Some(format!("{} and {} are typing", first, second)),
+        nicks => Some(format!("{} people are typing", nicks.len())),
+    }
+}
+
+/// Formats a change to the connection for display.
+pub fn format_connection_event(event: &ConnectionEvent) -> String {
+    let text = match event {
+        ConnectionEvent::Connecting { server, attempt } if *attempt > 1 => {
+            format!("Connecting to {} (attempt {})", server, attempt)
+        }
+        ConnectionEvent::Connecting { server, .. } => format!("Connecting to {}", server),
+        ConnectionEvent::Connected => "Connected".to_string(),
+        ConnectionEvent::Disconnected { reason } => format!("Disconnected: {}", reason),
+        ConnectionEvent::Retrying { delay } => {
+            format!("Reconnecting in {}s", delay.as_secs_f32().round())
+        }
+        ConnectionEvent::Restored { channels, queries } => {
+            let list = |names: &[String]| match names.len() {
+                0 => "none".to_string(),
+                _ => names.join(", "),
+            };
+            format!(
+                "Rejoining channels: {}; queries: {}",
+                list(channels),
+                list(queries)
+            )
+        }
+    };
+    format!(
+        "{}-- {}{}",
+        color::Fg(color::Magenta),
+        text,
+        color::Fg(color::Reset)
+    )
+}
+
+/// Formats something going wrong outside the conversation, like a log
+/// that can't be written.
+pub fn format_warning(text: &str) -> String {
+    format!(
+        "{}-- {}{}",
+        color::Fg(color::Red),
+        text,
+        color::Fg(color::Reset)
+    )
+}
+
+/// What the status line shows about the connection, nothing while it's up.
+pub fn connection_status(event: &ConnectionEvent) -> Option<String> {
+    match event {
+        ConnectionEvent::Connecting { .. } => Some("connecting".to_string()),
+        ConnectionEvent::Disconnected { .. } => Some("disconnected".to_string()),
+        ConnectionEvent::Retrying { delay } => Some(format!(
+            "disconnected, retrying in {}s",
+            delay.as_secs_f32().round()
+        )),
+        ConnectionEvent::Connected | ConnectionEvent::Restored { .. } => None,
+    }
+}
+
+/// Formats a DCC event for display. Chat lines go in a `=nick` buffer.
+pub fn format_dcc_event(event: &DccEvent, active: &str) -> String {
+    let notice = |text: String| {
+        format!(
+            "{}{}{}",
+            color::Fg(color::Yellow),
+            text,
+            color::Fg(color::Reset)
+        )
+    };
+    match event {
+        DccEvent::Offer {
+            id, description, ..
+        } => notice(format!("DCC offer {}: {}", id, description)),
+        DccEvent::Progress {
+            filename,
+            transferred,
+            size,
+            ..
+        } => match size {
+            Some(size) if *size > 0 => format!(
+                "{} {}% of {}",
+                filename,
+                transferred * 100 / size,
+                dcc::human_size(*size)
+            ),
+            _ => format!("{} {}", filename, dcc::human_size(*transferred)),
+        },
+        DccEvent::Finished { filename, .. } => notice(format!("DCC {} finished", filename)),
+        DccEvent::Failed { id, reason } => notice(format!("DCC {} failed: {}", id, reason)),
+        DccEvent::ChatOpened { nick } => notice(format!("DCC chat with {} opened", nick)),
+        DccEvent::ChatClosed { nick } => notice(format!("DCC chat with {} closed", nick)),
+        DccEvent::ChatLine { nick, text } => {
+            let buffer = format!("={}", nick);
+            let prefix = if buffer != active {
+                format!("[{}] ", buffer)
+            } else {
+                String::new()
+            };
+            format!(
+                "{}{}{}<{}>{}:{} {}",
+                prefix,
+                style::Bold,
+                color::Fg(color::Green),
+                nick,
+                color::Fg(color::Reset),
+                style::Reset,
+                format::render(text)
+            )
+        }
+    }
+}
+
+fn sender(message: &Message) -> String {
+    match message.source() {
+        Some(source) => source.nick.clone(),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Formats a `/me` action as `* nick does something`.
+fn format_action<C: color::Color>(nick: &str, text: &str, nick_color: C) -> String {
+    format!(
+        "{}* {}{}{} {}",
+        style::Bold,
+        color::Fg(nick_color),
+        nick,
+        style::Reset,
+        format::render(text)
+    )
+}
+
+/// Formats an action we sent so it reads like the ones we receive.
+pub fn format_own_action(nick: &str, text: &str) -> String {
+    let action = format_action(nick, text, color::Cyan);
+    format!("{}{}", timestamp::format(Utc::now()), action)
+}
+
+/// Formats a line we sent so it reads like the ones we receive.
+pub fn format_own_message(nick: &str, text: &str) -> String {
+    format!(
+        "{}{}{}<{}>{}:{} {}",
+        timestamp::format(Utc::now()),
+        style::Bold,
+        color::Fg(color::Cyan),
+        nick,
+        color::Fg(color::Reset),
+        style::Reset,
+        format::render(text)
+    )
+}
+
+/// Formats the line shown before messages from the server's history.
+pub fn format_history(target: &str, count: usize) -> String {
+    let text = match count {
+        0 => format!("History of {}: nothing new", target),
+        1 => format!("History of {}: 1 message", target),
+        n => format!("History of {}: {} messages", target, n),
+    };
+    format!("{}-- {}{}", style::Italic, text, style::Reset)
+}
+
+/// Formats who's in `channel`, with the account each is logged in to and
+/// whether they're away.
+pub fn format_names(channel: &str, state: &State) -> String {
+    let members = state.members(channel);
+    let names: Vec<String> = members
+        .iter()
+        .map(|nick| {
+            let user = state.user(nick);
+            let mut name = nick.clone();
+            if let Some(account) = user.and_then(|u| u.account.as_ref()) {
+                name.push_str(&format!(" [{}]", account));
+            }
+            if user.is_some_and(|u| u.away.is_some()) {
+                name.push_str(&format!(
+                    " {}(away){}",
+                    color::Fg(color::LightBlack),
+                    color::Fg(color::Reset)
+                ));
+            }
+            name
+        })
+        .collect();
+    format!("-- {} in {}: {}", names.len(), channel, names.join(", "))
+}
+
+/// Formats a message the server wouldn't send.
+pub fn format_not_sent(sent: &Sent, reason: &str) -> String {
+    format!(
+        "{}-- Not sent to {} ({}): {}{}",
+        color::Fg(color::Red),
+        sent.target,
+        reason,
+        sent.text,
+        color::Fg(color::Reset)
+    )
+}
+
+/// Formats someone on the notify list coming online or going offline.
+pub fn format_presence(change: &Change) -> String {
+    let color = match change.online {
+        true => color::Fg(color::Green).to_string(),
+        false => color::Fg(color::LightBlack).to_string(),
+    };
+    format!(
+        "{}{}-- {}{}",
+        timestamp::format(Utc::now()),
+        color,
+        change,
+        color::Fg(color::Reset)
+    )
+}
+
+/// The status line item for who on the notify list is online.
+pub fn online_status(nicks: &[String]) -> Option<String> {
+    Some(format!("online: {}", nicks.join(", "))).filter(|_| !nicks.is_empty())
+}
+
+/// Formats the notify list, with who's online and who isn't.
+pub fn format_notify_list(list: &NotifyList) -> String {
+    let nicks: Vec<String> = list
+        .nicks()
+        .iter()
+        .map(|(nick, online)| match online {
+            Some(true) => format!("{} (online)", nick),
+            Some(false) => format!("{} (offline)", nick),
+            None => nick.to_string(),
+        })
+        .collect();
+    match nicks.as_slice() {
+        [] => "-- The notify list is empty".to_string(),
+        nicks => format!("-- Notify list: {}", nicks.join(", ")),
+    }
+}
+
+/// The status line item for our user modes.
+pub fn user_modes_status(modes: &str) -> Option<String> {
+    Some(format!("modes {}", modes)).filter(|_| !modes.is_empty())
+}
+
+/// The status line item for server notices that came in while their
+/// buffer wasn't shown.
+pub fn snotices_status(unseen: usize) -> Option<String> {
+    Some(format!("{} server notices", unseen)).filter(|_| unseen > 0)
+}
+
+/// Formats a server notice, or wallops in the usual `!nick!` way.
+pub fn format_snotice(notice: &Notice) -> String {
+    let from = match notice.kind.as_deref() {
+        Some("WALLOPS") => format!(
+            "{}!{}!{}",
+            color::Fg(color::Magenta),
+            notice.from,
+            color::Fg(color::Reset)
+        ),
+        Some(kind) => format!("{}{}{}", style::Bold, kind, style::Reset),
+        None => format!(
+            "{}{}{}",
+            color::Fg(color::LightBlack),
+            notice.from,
+            color::Fg(color::Reset)
+        ),
+    };
+    format!(
+        "{}{} {}",
+        timestamp::format(notice.time),
+        from,
+        format::render(&notice.text)
+    )
+}
+
+/// Says which server notices are being shown.
+pub fn format_snotices_filter(filter: &[String]) -> String {
+    let (without, with): (Vec<&String>, Vec<&String>) =
+        filter.iter().partition(|w| w.starts_with('-'));
+    if filter.is_empty() {
+        return "-- Showing all server notices".to_string();
+    }
+    let mut text = "-- Showing server notices".to_string();
+    if !with.is_empty() {
+        let words: Vec<&str> = with.iter().map(|w| w.as_str()).collect();
+        text.push_str(&format!(" with {}", words.join(" or ")));
+    }
+    if !without.is_empty() {
+        let words: Vec<&str> = without.iter().map(|w| &w[1..]).collect();
+        text.push_str(&format!(" without {}", words.join(" or ")));
+    }
+    text
+}
+
+/// How many nicks a netsplit line names before just counting the rest.
+const NETSPLIT_NICKS: usize = 10;
+
+/// Formats a netsplit or netjoin as one line.
+pub fn format_netsplit(netsplit: &Netsplit) -> String {
+    let mut nicks = netsplit.nicks[..netsplit.nicks.len().min(NETSPLIT_NICKS)].join(", ");
+    if netsplit.nicks.len() > NETSPLIT_NICKS {
+        nicks.push_str(&format!(
+            " and {} more",
+            netsplit.nicks.len() - NETSPLIT_NICKS
+        ));
+    }
+    let (name, what) = match netsplit.joined {
+        false => ("Netsplit", "quit"),
+        true => ("Netjoin", "came back"),
+    };
+    let channels = match netsplit.channels.as_slice() {
+        [] => String::new(),
+        channels => format!(" in {}", channels.join(", ")),
+    };
+    format!(
+        "{}-- {} {}: {} {}{}{}",
+        color::Fg(color::LightBlack),
+        name,
+        netsplit.servers.join(" <-> "),
+        nicks,
+        what,
+        channels,
+        color::Fg(color::Reset)
+    )
+}
+
+/// Formats a page of the channel list, numbered to join with
+/// `/list join`.
+pub fn format_list(
+    entries: &[(usize, &Entry)],
+    total: usize,
+    (page, pages): (usize, usize),
+    listing: bool,
+) -> String {
+    let more = match listing {
+        true => ", more coming",
+        false => "",
+    };
+    let mut lines = vec![format!(
+        "-- {} channels{}, page {} of {}. /list show [-name] [page] [text] \
+         browses them, /list join <number> joins one",
+        total, more, page, pages
+    )];
+    for (number, entry) in entries {
+        lines.push(format!(
+            "{:>5}. {}{}{} ({}) {}",
+            number,
+            style::Bold,
+            entry.channel,
+            style::Reset,
+            entry.users,
+            format::render(&entry.topic)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Formats a channel's ban, exception, invite or quiet list, numbered to
+/// remove with `/unban`.
+pub fn format_mode_list(list: &ModeList) -> String {
+    let name = match list.mode {
+        'b' => "bans",
+        'e' => "ban exceptions",
+        'I' => "invite exceptions",
+        'q' => "quiets",
+        _ => "masks",
+    };
+    let mut lines = vec![format!(
+        "-- {} {}: {}",
+        list.channel,
+        name,
+        list.entries.len()
+    )];
+    for (i, entry) in list.entries.iter().enumerate() {
+        let set_by = match &entry.set_by {
+            Some(set_by) => format!(" by {}", set_by),
+            None => String::new(),
+        };
+        let set_at = match entry.set_at {
+            Some(set_at) => format!(
+                " on {}",
+                timestamp::convert(set_at).format("%Y-%m-%d %H:%M")
+            ),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "{:>3}. {}{}{}{}{}",
+            i + 1,
+            entry.mask,
+            color::Fg(color::LightBlack),
+            set_by,
+            set_at,
+            color::Fg(color::Reset)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Formats the answer to a WHOIS, WHOWAS or WHO as a card, one line per
+/// thing we learned.
+pub fn format_lookup(lookup: &Lookup) -> String {
+    let lines = match lookup {
+        Lookup::Whois(info) => whois_card(info, "is"),
+        Lookup::Whowas(entries) => entries.iter().flat_map(|i| whois_card(i, "was")).collect(),
+        Lookup::Who(mask, replies) => {
+            let mut lines = vec![format!("-- {} matching {}", replies.len(), mask)];
+            lines.extend(replies.iter().map(who_line));
+            lines
+        }
+        Lookup::NotFound(nick, reason) => vec![format!("-- {}: {}", nick, reason)],
+    };
+    lines.join("\n")
+}
+
+fn whois_card(info: &WhoisInfo, verb: &str) -> Vec<String> {
+    let userhost = match (&info.user, &info.host) {
+        (Some(user), Some(host)) => format!(" {} {}@{}", verb, user, host),
+        _ => String::new(),
+    };
+    let realname = match &info.realname {
+        Some(realname) => format!(" ({})", format::render(realname)),
+        None => String::new(),
+    };
+    let mut lines = vec![format!(
+        "-- {}{}{}{}{}",
+        style::Bold,
+        info.nick,
+        style::Reset,
+        userhost,
+        realname
+    )];
+    let mut field = |name: &str, value: String| {
+        lines.push(format!(
+            "   {}{:<9}{} {}",
+            color::Fg(color::LightBlack),
+            name,
+            color::Fg(color::Reset),
+            value
+        ))
+    };
+    if let Some(account) = &info.account {
+        field("account", account.clone());
+    }
+    if !info.channels.is_empty() {
+        field("channels", info.channels.join(" "));
+    }
+    if let Some((server, about)) = &info.server {
+        field("server", format!("{} ({})", server, about));
+    }
+    if let Some(idle) = info.idle {
+        let signon = match info.signon {
+            Some(signon) => format!(
+                ", on since {}",
+                timestamp::convert(signon).format("%Y-%m-%d %H:%M")
+            ),
+            None => String::new(),
+        };
+        field("idle", format!("{}{}", format_idle(idle), signon));
+    }
+    if let Some(away) = &info.away {
+        field("away", format::render(away));
+    }
+    if let Some(operator) = &info.operator {
+        field("operator", operator.clone());
+    }
+    if info.secure {
+        field("secure", "connected with TLS".to_string());
+    }
+    if let Some(actually) = &info.actually {
+        field("actually", actually.clone());
+    }
+    for other in &info.other {
+        field("", other.clone());
+    }
+    lines
+}
+
+/// `seconds` as the two largest units, like `2h 5m`.
+fn format_idle(seconds: u64) -> String {
+    let (days, hours) = (seconds / 86400, seconds / 3600 % 24);
+    let (minutes, seconds) = (seconds / 60 % 60, seconds % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+fn who_line(reply: &WhoReply) -> String {
+    let channel = match &reply.channel {
+        Some(channel) => format!(" in {}", channel),
+        None => String::new(),
+    };
+    let account = match &reply.account {
+        Some(account) => format!(" [{}]", account),
+        None => String::new(),
+    };
+    let away = match reply.is_away() {
+        true => format!(
+            " {}(away){}",
+            color::Fg(color::LightBlack),
+            color::Fg(color::Reset)
+        ),
+        false => String::new(),
+    };
+    format!(
+        "   {} {}@{}{}{}{} ({})",
+        reply.nick,
+        reply.user,
+        reply.host,
+        channel,
+        account,
+        away,
+        format::render(&reply.realname)
+    )
+}
+
+/// Formats a message from the search store, numbered if it's a result and
+/// highlighted if it's the one jumped to.
+pub fn format_hit(hit: &Hit, number: Option<usize>, highlight: bool) -> String {
+    let text = match hit.command.as_str() {
+        "ACTION" => format!("* {} {}", hit.nick, hit.text),
+        "NOTICE" => format!("-{}- {}", hit.nick, hit.text),
+        "JOIN" => format!("--> {} joined {}", hit.nick, hit.buffer),
+        "PART" if hit.text.is_empty() => format!("<-- {} left {}", hit.nick, hit.buffer),
+        "PART" => format!("<-- {} left {} ({})", hit.nick, hit.buffer, hit.text),
+        _ => format!("<{}> {}", hit.nick, hit.text),
+    };
+    // Results can come from anywhere, context is all from one buffer
+    let (number, place) = match number {
+        Some(n) => (
+            format!("{:>3}. ", n),
+            format!(" [{}/{}]", hit.network, hit.buffer),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let time = timestamp::convert(hit.time.with_timezone(&Utc));
+    let time = time.format("%Y-%m-%d %H:%M");
+    if highlight {
+        format!(
+            "{}{}{}{} {}{}",
+            number,
+            style::Invert,
+            time,
+            place,
+            text,
+            style::Reset
+        )
+    } else {
+        format!(
+            "{}{}{}{}{} {}",
+            number,
+            color::Fg(color::LightBlack),
+            time,
+            place,
+            color::Fg(color::Reset),
+            text
+        )
+    }
+}
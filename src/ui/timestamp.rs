@@ -0,0 +1,35 @@
+//! The time shown in front of messages.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use termion::color;
+
+use crate::config::Zone;
+
+static TIMESTAMP: OnceLock<(String, Zone)> = OnceLock::new();
+
+/// Shows times with the strftime `format`, in `zone`. No time is shown
+/// until this is called, or when `format` is empty.
+pub fn set(format: &str, zone: Zone) {
+    let _ = TIMESTAMP.set((format.to_string(), zone));
+}
+
+/// `time` as it's shown in front of a message, with a space after it.
+pub fn format(time: DateTime<Utc>) -> String {
+    match TIMESTAMP.get() {
+        Some((format, zone)) if !format.is_empty() => format!(
+            "{}{}{} ",
+            color::Fg(color::LightBlack),
+            zone.convert(time).format(format),
+            color::Fg(color::Reset)
+        ),
+        _ => String::new(),
+    }
+}
+
+/// `time` in the configured time zone.
+pub fn convert(time: DateTime<Utc>) -> DateTime<FixedOffset> {
+    let zone = TIMESTAMP.get().map(|(_, zone)| *zone).unwrap_or_default();
+    zone.convert(time)
+}